@@ -1,6 +1,5 @@
-use abdb::{string_column::StringColumnReader, write_batch, LineItem, TrackedWriter};
+use abdb::{column_file::ColumnFileWriter, kernels::Kernel, string_column::StringColumnReader, LineItem, TrackedWriter};
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use std::arch::aarch64::*;
 
 // Basic sum implementation
 fn sum_basic(arr: &[i32]) -> i32 {
@@ -26,59 +25,9 @@ fn sum_vectorized(arr: &[i32]) -> i32 {
 }
 
 
-#[target_feature(enable = "neon")]
-pub unsafe fn sum_intrinsics_optimized(arr: &[i32]) -> i32 {
-    let len = arr.len();
-    let mut sum = 0;
-
-    // Process 16 integers at a time using 4 accumulators
-    let chunk_size = 16;
-    let chunks = len / chunk_size;
-    
-    if chunks > 0 {
-        // Initialize 4 accumulator vectors
-        let mut acc1 = vdupq_n_s32(0);
-        let mut acc2 = vdupq_n_s32(0);
-        let mut acc3 = vdupq_n_s32(0);
-        let mut acc4 = vdupq_n_s32(0);
-        
-        // Process chunks of 16 integers
-        for i in 0..chunks {
-            let base_ptr = arr.as_ptr().add(i * chunk_size);
-            
-            // Load 4 sets of 4 integers
-            let chunk1 = vld1q_s32(base_ptr);
-            let chunk2 = vld1q_s32(base_ptr.add(4));
-            let chunk3 = vld1q_s32(base_ptr.add(8));
-            let chunk4 = vld1q_s32(base_ptr.add(12));
-            
-            // Add to respective accumulators
-            acc1 = vaddq_s32(acc1, chunk1);
-            acc2 = vaddq_s32(acc2, chunk2);
-            acc3 = vaddq_s32(acc3, chunk3);
-            acc4 = vaddq_s32(acc4, chunk4);
-        }
-        
-        // Combine accumulators
-        let mut temp = [0i32; 16];
-        vst1q_s32(temp.as_mut_ptr(), acc1);
-        vst1q_s32(temp.as_mut_ptr().add(4), acc2);
-        vst1q_s32(temp.as_mut_ptr().add(8), acc3);
-        vst1q_s32(temp.as_mut_ptr().add(12), acc4);
-        sum += temp.iter().sum::<i32>();
-    }
-    
-    // Handle remaining elements
-    for i in (chunks * chunk_size)..len {
-        sum += arr[i];
-    }
-    
-    sum
-}
-
 fn write_column_data() {
     let file = std::fs::File::create("lineitems_column_criterion.bin").expect("Failed to create file");
-    let mut writer = TrackedWriter::new(std::io::BufWriter::new(file));
+    let mut writer = ColumnFileWriter::new(file);
     for _ in 0..10 {
         let mut batch = vec![
             LineItem {
@@ -88,6 +37,7 @@ fn write_column_data() {
                 l_extendedprice: 2.0,
                 l_discount: 3.0,
                 l_tax: 4.0,
+                l_shipdate: 10000,
             },
             LineItem {
                 l_returnflag: "C".to_string(),
@@ -96,15 +46,18 @@ fn write_column_data() {
                 l_extendedprice: 6.0,
                 l_discount: 7.0,
                 l_tax: 8.0,
+                l_shipdate: 10000,
             },
         ];
-        batch.extend(std::iter::repeat(batch.clone()).take(3999).flatten());
-        write_batch(&mut writer, &mut batch);
+        batch.extend(std::iter::repeat_n(batch.clone(), 3999).flatten());
+        writer.write_batch(&mut batch).expect("Failed to write batch");
     }
+    writer.finish().expect("Failed to finish file");
 }
 
-fn query_1_column() -> Vec<Option<abdb::QueryOneStateColumn>> {
-    abdb::query_1_column("lineitems_column_criterion.bin")
+fn query_1_column() -> Vec<abdb::QueryOneRow> {
+    let shipdate_cutoff = abdb::date_column::parse_date(abdb::QUERY_1_SHIPDATE_CUTOFF).unwrap();
+    abdb::query_1_column("lineitems_column_criterion.bin", shipdate_cutoff).unwrap()
 }
 
 fn sum_benchmark(c: &mut Criterion) {
@@ -112,8 +65,8 @@ fn sum_benchmark(c: &mut Criterion) {
     // Create test data
     //let data: Vec<i32> = (0..10_000).collect();
     let mut data: [i32; 10_000] = [0; 10_000];
-    for i in 0..10_000 {
-        data[i] = i as i32;
+    for (i, value) in data.iter_mut().enumerate() {
+        *value = i as i32;
     }
 
     // Benchmark non vectorized sum
@@ -129,17 +82,33 @@ fn sum_benchmark(c: &mut Criterion) {
         b.iter(|| black_box(sum_vectorized(black_box(&data))))
     });
 
-    // Benchmark vectorized sum intrinsics
-    group.bench_function("sum vectorized intrinsics", |b| {
-        unsafe {
-            b.iter(|| black_box(sum_intrinsics_optimized(black_box(&data))));
-        }
-    });
-
     group.finish();
 
 }
 
+fn kernel_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Kernels");
+    let values: Vec<u16> = (0..10_000).map(|i| (i * 7) as u16).collect();
+    let selected: Vec<bool> = (0..10_000).map(|i| i % 3 != 0).collect();
+    let price: Vec<i64> = (0..10_000).map(|i| 100_000 + i).collect();
+    let discount: Vec<i64> = (0..10_000).map(|i| i % 11).collect();
+
+    // The library uses the last, fastest kernel of each list
+    for kernel in Kernel::available() {
+        group.bench_function(format!("sum u16 {:?}", kernel), |b| {
+            b.iter(|| black_box(kernel.sum_u16(black_box(&values))))
+        });
+        group.bench_function(format!("masked sum u16 {:?}", kernel), |b| {
+            b.iter(|| black_box(kernel.masked_sum_u16(black_box(&values), black_box(&selected))))
+        });
+        group.bench_function(format!("sum disc price {:?}", kernel), |b| {
+            b.iter(|| black_box(kernel.sum_disc_price(black_box(&price), black_box(&discount), 100)))
+        });
+    }
+
+    group.finish();
+}
+
 fn group_by_sum_benchmark(c: &mut Criterion) {
     write_column_data();
     c.bench_function("query_1_column", |b| b.iter(|| black_box(query_1_column())));
@@ -148,11 +117,11 @@ fn group_by_sum_benchmark(c: &mut Criterion) {
 fn write_string_column() {
     let file = std::fs::File::create("string_column_criterion.bin").expect("Failed to create file");
     let mut writer = TrackedWriter::new(std::io::BufWriter::new(file));
-    let data = vec!["a", "a", "b", "b", "b", "c"].repeat(1000);
+    let data = ["a", "a", "b", "b", "b", "c"].repeat(1000);
 
     for _ in 0..99 {  // 1 time already called above, so 99 more
-        let col = StringColumnReader::new_from_strings(data.clone());
-        col.write(&mut writer);
+        let col = StringColumnReader::new_from_strings(data.clone()).unwrap();
+        col.write(&mut writer).expect("Failed to write column");
     }
 }
 
@@ -162,7 +131,7 @@ fn read_all_strings() -> u64 {
     let mut col = StringColumnReader::empty();
     let mut count = 0;
     for _ in 0..99 {
-        col.read(&mut reader);
+        col.read(&mut reader).unwrap();
         count += col.count_strings(); // Using count() to get the number of strings
     }
     count
@@ -173,5 +142,5 @@ fn read_and_write_strings_benchmark(c: &mut Criterion) {
     c.bench_function("read_string_column", |b| b.iter(|| black_box(read_all_strings())));
 }
 
-criterion_group!(benches, sum_benchmark, group_by_sum_benchmark, read_and_write_strings_benchmark);
+criterion_group!(benches, sum_benchmark, kernel_benchmark, group_by_sum_benchmark, read_and_write_strings_benchmark);
 criterion_main!(benches);
\ No newline at end of file
@@ -0,0 +1,804 @@
+use std::any::Any;
+use std::fmt;
+use std::io::{Read, Seek};
+use std::ops::Range;
+
+use ahash::AHashSet;
+
+use crate::column_file::{required_column_index, ColumnFileReader};
+use crate::error::{Error, Result};
+use crate::f64_column::{scale_factor, Decimal, DecimalEncoding};
+use crate::group_by::{group_by, GroupValue, NO_GROUP};
+use crate::predicate::Predicate;
+use crate::scan::ColumnData;
+use crate::schema::LogicalType;
+
+/// A finalized aggregate. Sums, minimums and maximums keep the scale of
+/// their input column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateValue {
+    Decimal(Decimal),
+    Count(u64),
+    Float(f64),
+    /// AVG, MIN and MAX of no rows
+    Null,
+}
+
+impl fmt::Display for AggregateValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AggregateValue::Decimal(value) => write!(f, "{}", value),
+            AggregateValue::Count(value) => write!(f, "{}", value),
+            AggregateValue::Float(value) => write!(f, "{}", value),
+            AggregateValue::Null => write!(f, "NULL"),
+        }
+    }
+}
+
+/// An aggregate over the unscaled values of one column: char columns give
+/// their byte, decimal and date columns their unscaled value.
+pub trait AggregateFunction: Send + Sync {
+    type State: Send + 'static;
+
+    fn init(&self) -> Self::State;
+
+    /// Adds `run_length` rows that all hold `value`
+    fn update_run(&self, state: &mut Self::State, value: i64, run_length: u64);
+
+    /// Adds `values`, or only those flagged in `selected` when it is given
+    fn update_slice(&self, state: &mut Self::State, values: &[i64], selected: Option<&[bool]>);
+
+    fn merge(&self, state: &mut Self::State, other: &Self::State);
+
+    /// The result for input values with `scale` digits after the point
+    fn finalize(&self, state: &Self::State, scale: u8) -> AggregateValue;
+}
+
+/// `AggregateFunction` with a boxed state, so aggregates with different
+/// states can be listed together. See `AggregateExpr::new`.
+pub trait DynAggregateFunction: Send + Sync {
+    fn init(&self) -> Box<dyn Any + Send>;
+    fn update_run(&self, state: &mut dyn Any, value: i64, run_length: u64);
+    fn update_slice(&self, state: &mut dyn Any, values: &[i64], selected: Option<&[bool]>);
+    fn merge(&self, state: &mut dyn Any, other: &dyn Any);
+    fn finalize(&self, state: &dyn Any, scale: u8) -> AggregateValue;
+}
+
+struct Boxed<F>(F);
+
+impl<F: AggregateFunction> DynAggregateFunction for Boxed<F> {
+    fn init(&self) -> Box<dyn Any + Send> {
+        Box::new(self.0.init())
+    }
+
+    fn update_run(&self, state: &mut dyn Any, value: i64, run_length: u64) {
+        self.0
+            .update_run(downcast_mut::<F>(state), value, run_length)
+    }
+
+    fn update_slice(&self, state: &mut dyn Any, values: &[i64], selected: Option<&[bool]>) {
+        self.0
+            .update_slice(downcast_mut::<F>(state), values, selected)
+    }
+
+    fn merge(&self, state: &mut dyn Any, other: &dyn Any) {
+        self.0.merge(downcast_mut::<F>(state), downcast::<F>(other))
+    }
+
+    fn finalize(&self, state: &dyn Any, scale: u8) -> AggregateValue {
+        self.0.finalize(downcast::<F>(state), scale)
+    }
+}
+
+pub fn boxed(function: impl AggregateFunction + 'static) -> Box<dyn DynAggregateFunction> {
+    Box::new(Boxed(function))
+}
+
+fn downcast<F: AggregateFunction>(state: &dyn Any) -> &F::State {
+    state
+        .downcast_ref()
+        .expect("State was created by another aggregate function")
+}
+
+fn downcast_mut<F: AggregateFunction>(state: &mut dyn Any) -> &mut F::State {
+    state
+        .downcast_mut()
+        .expect("State was created by another aggregate function")
+}
+
+// The values of `values` flagged in `selected`, or all of them
+fn selected_values<'a>(
+    values: &'a [i64],
+    selected: Option<&'a [bool]>,
+) -> impl Iterator<Item = i64> + 'a {
+    values
+        .iter()
+        .enumerate()
+        .filter(move |(row, _)| selected.is_none_or(|x| x[*row]))
+        .map(|(_, value)| *value)
+}
+
+pub struct Sum;
+
+impl AggregateFunction for Sum {
+    type State = i128;
+
+    fn init(&self) -> i128 {
+        0
+    }
+
+    fn update_run(&self, state: &mut i128, value: i64, run_length: u64) {
+        *state += value as i128 * run_length as i128;
+    }
+
+    fn update_slice(&self, state: &mut i128, values: &[i64], selected: Option<&[bool]>) {
+        *state += selected_values(values, selected)
+            .map(|x| x as i128)
+            .sum::<i128>();
+    }
+
+    fn merge(&self, state: &mut i128, other: &i128) {
+        *state += other;
+    }
+
+    fn finalize(&self, state: &i128, scale: u8) -> AggregateValue {
+        AggregateValue::Decimal(Decimal::new(*state, scale))
+    }
+}
+
+/// Counts rows; columns have no nulls, so any column counts every row.
+pub struct Count;
+
+impl AggregateFunction for Count {
+    type State = u64;
+
+    fn init(&self) -> u64 {
+        0
+    }
+
+    fn update_run(&self, state: &mut u64, _value: i64, run_length: u64) {
+        *state += run_length;
+    }
+
+    fn update_slice(&self, state: &mut u64, values: &[i64], selected: Option<&[bool]>) {
+        *state += match selected {
+            Some(selected) => crate::kernels::count_selected(selected),
+            None => values.len() as u64,
+        };
+    }
+
+    fn merge(&self, state: &mut u64, other: &u64) {
+        *state += other;
+    }
+
+    fn finalize(&self, state: &u64, _scale: u8) -> AggregateValue {
+        AggregateValue::Count(*state)
+    }
+}
+
+/// The mean, computed from the exact sum and count.
+pub struct Avg;
+
+impl AggregateFunction for Avg {
+    type State = (i128, u64);
+
+    fn init(&self) -> (i128, u64) {
+        (0, 0)
+    }
+
+    fn update_run(&self, state: &mut (i128, u64), value: i64, run_length: u64) {
+        Sum.update_run(&mut state.0, value, run_length);
+        Count.update_run(&mut state.1, value, run_length);
+    }
+
+    fn update_slice(&self, state: &mut (i128, u64), values: &[i64], selected: Option<&[bool]>) {
+        Sum.update_slice(&mut state.0, values, selected);
+        Count.update_slice(&mut state.1, values, selected);
+    }
+
+    fn merge(&self, state: &mut (i128, u64), other: &(i128, u64)) {
+        state.0 += other.0;
+        state.1 += other.1;
+    }
+
+    fn finalize(&self, state: &(i128, u64), scale: u8) -> AggregateValue {
+        match state.1 {
+            0 => AggregateValue::Null,
+            count => {
+                AggregateValue::Float(state.0 as f64 / (count as f64 * scale_factor(scale) as f64))
+            }
+        }
+    }
+}
+
+pub struct Min;
+
+impl AggregateFunction for Min {
+    type State = Option<i64>;
+
+    fn init(&self) -> Option<i64> {
+        None
+    }
+
+    fn update_run(&self, state: &mut Option<i64>, value: i64, run_length: u64) {
+        if run_length > 0 {
+            *state = Some(state.map_or(value, |x| x.min(value)));
+        }
+    }
+
+    fn update_slice(&self, state: &mut Option<i64>, values: &[i64], selected: Option<&[bool]>) {
+        if let Some(min) = selected_values(values, selected).min() {
+            self.update_run(state, min, 1);
+        }
+    }
+
+    fn merge(&self, state: &mut Option<i64>, other: &Option<i64>) {
+        if let Some(other) = other {
+            self.update_run(state, *other, 1);
+        }
+    }
+
+    fn finalize(&self, state: &Option<i64>, scale: u8) -> AggregateValue {
+        state.map_or(AggregateValue::Null, |x| {
+            AggregateValue::Decimal(Decimal::new(x as i128, scale))
+        })
+    }
+}
+
+pub struct Max;
+
+impl AggregateFunction for Max {
+    type State = Option<i64>;
+
+    fn init(&self) -> Option<i64> {
+        None
+    }
+
+    fn update_run(&self, state: &mut Option<i64>, value: i64, run_length: u64) {
+        if run_length > 0 {
+            *state = Some(state.map_or(value, |x| x.max(value)));
+        }
+    }
+
+    fn update_slice(&self, state: &mut Option<i64>, values: &[i64], selected: Option<&[bool]>) {
+        if let Some(max) = selected_values(values, selected).max() {
+            self.update_run(state, max, 1);
+        }
+    }
+
+    fn merge(&self, state: &mut Option<i64>, other: &Option<i64>) {
+        if let Some(other) = other {
+            self.update_run(state, *other, 1);
+        }
+    }
+
+    fn finalize(&self, state: &Option<i64>, scale: u8) -> AggregateValue {
+        state.map_or(AggregateValue::Null, |x| {
+            AggregateValue::Decimal(Decimal::new(x as i128, scale))
+        })
+    }
+}
+
+/// Exact COUNT(DISTINCT), keeping every distinct value of the group.
+pub struct CountDistinct;
+
+impl AggregateFunction for CountDistinct {
+    type State = AHashSet<i64>;
+
+    fn init(&self) -> AHashSet<i64> {
+        AHashSet::new()
+    }
+
+    fn update_run(&self, state: &mut AHashSet<i64>, value: i64, run_length: u64) {
+        if run_length > 0 {
+            state.insert(value);
+        }
+    }
+
+    fn update_slice(&self, state: &mut AHashSet<i64>, values: &[i64], selected: Option<&[bool]>) {
+        state.extend(selected_values(values, selected));
+    }
+
+    fn merge(&self, state: &mut AHashSet<i64>, other: &AHashSet<i64>) {
+        state.extend(other);
+    }
+
+    fn finalize(&self, state: &AHashSet<i64>, _scale: u8) -> AggregateValue {
+        AggregateValue::Count(state.len() as u64)
+    }
+}
+
+// 2^12 HyperLogLog registers, for a standard error of about 1.6%
+const DISTINCT_PRECISION: u32 = 12;
+const DISTINCT_REGISTERS: usize = 1 << DISTINCT_PRECISION;
+
+/// Approximate COUNT(DISTINCT), estimated with a HyperLogLog sketch.
+/// Sketches built by different threads can be merged because the values are
+/// hashed with fixed seeds.
+pub struct ApproxCountDistinct;
+
+impl ApproxCountDistinct {
+    fn add(registers: &mut [u8], value: i64) {
+        let hash = ahash::RandomState::with_seeds(1, 2, 3, 4).hash_one(value);
+        let register = (hash >> (64 - DISTINCT_PRECISION)) as usize;
+        // Position of the first set bit in the remaining bits
+        let rank =
+            ((hash << DISTINCT_PRECISION) | (1 << (DISTINCT_PRECISION - 1))).leading_zeros() + 1;
+        registers[register] = registers[register].max(rank as u8);
+    }
+}
+
+impl AggregateFunction for ApproxCountDistinct {
+    type State = Vec<u8>;
+
+    fn init(&self) -> Vec<u8> {
+        vec![0; DISTINCT_REGISTERS]
+    }
+
+    fn update_run(&self, state: &mut Vec<u8>, value: i64, run_length: u64) {
+        if run_length > 0 {
+            ApproxCountDistinct::add(state, value);
+        }
+    }
+
+    fn update_slice(&self, state: &mut Vec<u8>, values: &[i64], selected: Option<&[bool]>) {
+        for value in selected_values(values, selected) {
+            ApproxCountDistinct::add(state, value);
+        }
+    }
+
+    fn merge(&self, state: &mut Vec<u8>, other: &Vec<u8>) {
+        for (register, other) in state.iter_mut().zip(other) {
+            *register = (*register).max(*other);
+        }
+    }
+
+    fn finalize(&self, state: &Vec<u8>, _scale: u8) -> AggregateValue {
+        let registers = DISTINCT_REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / registers);
+        let harmonic_sum: f64 = state.iter().map(|x| 2f64.powi(-(*x as i32))).sum();
+        let estimate = alpha * registers * registers / harmonic_sum;
+        let empty = state.iter().filter(|x| **x == 0).count();
+        // Small cardinalities are estimated better by linear counting
+        let estimate = if estimate <= 2.5 * registers && empty > 0 {
+            registers * (registers / empty as f64).ln()
+        } else {
+            estimate
+        };
+        AggregateValue::Count(estimate.round() as u64)
+    }
+}
+
+/// An aggregate function applied to a column
+pub struct AggregateExpr {
+    pub column: String,
+    pub function: Box<dyn DynAggregateFunction>,
+}
+
+impl AggregateExpr {
+    pub fn new(function: impl AggregateFunction + 'static, column: &str) -> Self {
+        AggregateExpr {
+            column: column.to_string(),
+            function: boxed(function),
+        }
+    }
+
+    pub fn sum(column: &str) -> Self {
+        AggregateExpr::new(Sum, column)
+    }
+
+    pub fn count(column: &str) -> Self {
+        AggregateExpr::new(Count, column)
+    }
+
+    pub fn avg(column: &str) -> Self {
+        AggregateExpr::new(Avg, column)
+    }
+
+    pub fn min(column: &str) -> Self {
+        AggregateExpr::new(Min, column)
+    }
+
+    pub fn max(column: &str) -> Self {
+        AggregateExpr::new(Max, column)
+    }
+
+    pub fn count_distinct(column: &str) -> Self {
+        AggregateExpr::new(CountDistinct, column)
+    }
+
+    pub fn approx_count_distinct(column: &str) -> Self {
+        AggregateExpr::new(ApproxCountDistinct, column)
+    }
+}
+
+/// Groups the rows satisfying `predicates` by the `keys` columns and
+/// computes `aggregates` for every group, ordered by key. Varchar columns
+/// can be keys but not aggregate inputs.
+pub fn aggregate<R: Read + Seek>(
+    reader: &mut ColumnFileReader<R>,
+    keys: &[&str],
+    aggregates: &[AggregateExpr],
+    predicates: &[Predicate],
+) -> Result<Vec<(Vec<GroupValue>, Vec<AggregateValue>)>> {
+    let schema = reader.schema().clone();
+    let mut inputs = Vec::with_capacity(aggregates.len());
+    for aggregate in aggregates {
+        let index = required_column_index(&schema, &aggregate.column)?;
+        let scale = match schema.fields[index].logical_type {
+            LogicalType::Decimal { scale } => scale,
+            LogicalType::Char | LogicalType::Date => 0,
+            LogicalType::Varchar => {
+                return Err(Error::InvalidQuery(format!(
+                    "cannot aggregate varchar column {}",
+                    aggregate.column
+                )))
+            }
+        };
+        inputs.push((index, scale));
+    }
+    let columns: Vec<&str> = aggregates.iter().map(|x| x.column.as_str()).collect();
+
+    let mut decoded = Vec::new();
+    let mut result = Ok(());
+    let groups = group_by(
+        reader,
+        keys,
+        &columns,
+        predicates,
+        |row_group, group_ids, states: &mut [Vec<Box<dyn Any + Send>>]| {
+            init_states(states, aggregates.iter().map(|x| &*x.function));
+            for (aggregate_index, (aggregate, (column, _))) in
+                aggregates.iter().zip(&inputs).enumerate()
+            {
+                let column = row_group.columns[*column]
+                    .as_ref()
+                    .expect("Aggregate inputs are projected");
+                let update = update_from_column(
+                    &*aggregate.function,
+                    aggregate_index,
+                    column,
+                    group_ids,
+                    states,
+                    &mut decoded,
+                );
+                if result.is_ok() {
+                    result = update;
+                }
+            }
+        },
+    )?;
+    result?;
+
+    Ok(groups
+        .into_iter()
+        .map(|(key, states)| {
+            let values = aggregates
+                .iter()
+                .zip(&inputs)
+                .zip(&states)
+                .map(|((aggregate, (_, scale)), state)| {
+                    aggregate.function.finalize(&**state, *scale)
+                })
+                .collect();
+            (key, values)
+        })
+        .collect())
+}
+
+// Gives the groups created since the last row group a state per aggregate
+pub(crate) fn init_states<'a>(
+    states: &mut [Vec<Box<dyn Any + Send>>],
+    functions: impl Iterator<Item = &'a dyn DynAggregateFunction> + Clone,
+) {
+    for state in states.iter_mut().filter(|x| x.is_empty()) {
+        *state = functions.clone().map(|x| x.init()).collect();
+    }
+}
+
+/// Adds the rows of a char, decimal or date `column` to state
+/// `aggregate_index` of their groups. Char and run-length encoded columns
+/// are aggregated a run at a time; `decoded` is scratch space for the rest.
+pub(crate) fn update_from_column(
+    function: &dyn DynAggregateFunction,
+    aggregate_index: usize,
+    column: &ColumnData,
+    group_ids: &[u32],
+    states: &mut [Vec<Box<dyn Any + Send>>],
+    decoded: &mut Vec<i64>,
+) -> Result<()> {
+    match column {
+        ColumnData::String(column) => {
+            let mut start = 0;
+            for (value, run_length) in column.compressed_iterator() {
+                let end = start + run_length as usize;
+                if end > group_ids.len() {
+                    break;
+                }
+                for (id, run) in group_runs(group_ids, start..end) {
+                    let state = &mut *states[id as usize][aggregate_index];
+                    function.update_run(state, value as i64, run.len() as u64);
+                }
+                start = end;
+            }
+            if start != group_ids.len() {
+                return Err(Error::corrupt(
+                    "string column runs do not cover the row group",
+                ));
+            }
+        }
+        ColumnData::Decimal(column) | ColumnData::Date(column)
+            if column.encoding() != DecimalEncoding::FrameOfReference =>
+        {
+            for (value, rows) in column.view().runs() {
+                for (id, run) in group_runs(group_ids, rows) {
+                    let state = &mut *states[id as usize][aggregate_index];
+                    function.update_run(state, value, run.len() as u64);
+                }
+            }
+        }
+        ColumnData::Decimal(column) | ColumnData::Date(column) => {
+            column.view().decode_into(decoded);
+            update_from_values(function, aggregate_index, decoded, group_ids, states);
+        }
+        ColumnData::Varchar(_) => unreachable!("Varchar columns are not aggregate inputs"),
+    }
+    Ok(())
+}
+
+/// Adds one unscaled value per row to state `aggregate_index` of the row's
+/// group.
+pub(crate) fn update_from_values(
+    function: &dyn DynAggregateFunction,
+    aggregate_index: usize,
+    values: &[i64],
+    group_ids: &[u32],
+    states: &mut [Vec<Box<dyn Any + Send>>],
+) {
+    for (id, run) in group_runs(group_ids, 0..group_ids.len()) {
+        let state = &mut *states[id as usize][aggregate_index];
+        function.update_slice(state, &values[run], None);
+    }
+}
+
+// The runs of rows within `rows` that belong to the same group, skipping
+// rows without one
+pub(crate) fn group_runs(
+    group_ids: &[u32],
+    rows: Range<usize>,
+) -> impl Iterator<Item = (u32, Range<usize>)> + '_ {
+    let mut start = rows.start;
+    std::iter::from_fn(move || {
+        while start < rows.end {
+            let id = group_ids[start];
+            let run_start = start;
+            while start < rows.end && group_ids[start] == id {
+                start += 1;
+            }
+            if id != NO_GROUP {
+                return Some((id, run_start..start));
+            }
+        }
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::column_file::ColumnFileWriter;
+    use crate::test_data::TempColumnFile;
+    use crate::{query_1_column, LineItem};
+
+    fn functions() -> [AggregateExpr; 6] {
+        ["sum", "count", "avg", "min", "max", "distinct"].map(|name| match name {
+            "sum" => AggregateExpr::sum("x"),
+            "count" => AggregateExpr::count("x"),
+            "avg" => AggregateExpr::avg("x"),
+            "min" => AggregateExpr::min("x"),
+            "max" => AggregateExpr::max("x"),
+            _ => AggregateExpr::count_distinct("x"),
+        })
+    }
+
+    fn finalize_all(values: &[i64], selected: Option<&[bool]>) -> Vec<AggregateValue> {
+        functions()
+            .iter()
+            .map(|AggregateExpr { function, .. }| {
+                let mut state = function.init();
+                function.update_slice(&mut *state, values, selected);
+                function.finalize(&*state, 2)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_aggregate_functions() {
+        let decimal = |x| AggregateValue::Decimal(Decimal::new(x, 2));
+
+        assert_eq!(
+            finalize_all(&[250, -100, 400, 250], None),
+            vec![
+                decimal(800),
+                AggregateValue::Count(4),
+                AggregateValue::Float(800.0 / 400.0),
+                decimal(-100),
+                decimal(400),
+                AggregateValue::Count(3),
+            ]
+        );
+        assert_eq!(
+            finalize_all(&[250, -100, 400, 250], Some(&[true, false, false, true])),
+            vec![
+                decimal(500),
+                AggregateValue::Count(2),
+                AggregateValue::Float(2.5),
+                decimal(250),
+                decimal(250),
+                AggregateValue::Count(1),
+            ]
+        );
+        assert_eq!(
+            finalize_all(&[], None),
+            vec![
+                decimal(0),
+                AggregateValue::Count(0),
+                AggregateValue::Null,
+                AggregateValue::Null,
+                AggregateValue::Null,
+                AggregateValue::Count(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_approx_count_distinct() {
+        for distinct in [0, 1, 100, 50_000] {
+            let mut state = ApproxCountDistinct.init();
+            let mut other = ApproxCountDistinct.init();
+            for value in 0..distinct {
+                // Every value twice, split over two sketches
+                ApproxCountDistinct.update_run(&mut state, value * 7, 2);
+                ApproxCountDistinct.update_slice(&mut other, &[value * 7], None);
+            }
+            ApproxCountDistinct.merge(&mut state, &other);
+
+            let AggregateValue::Count(estimate) = ApproxCountDistinct.finalize(&state, 0) else {
+                panic!("Expected a count");
+            };
+            let error = (estimate as f64 - distinct as f64).abs();
+            assert!(
+                error <= distinct as f64 * 0.05,
+                "{} for {}",
+                estimate,
+                distinct
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_runs_and_merges_match_slices(
+            runs in prop::collection::vec((-1000i64..1000, 0u64..5), 0..50),
+            split in 0usize..50,
+        ) {
+            let values: Vec<i64> = runs
+                .iter()
+                .flat_map(|(value, length)| std::iter::repeat_n(*value, *length as usize))
+                .collect();
+            let split = split.min(runs.len());
+            for (AggregateExpr { function, .. }, expected) in
+                functions().iter().zip(finalize_all(&values, None))
+            {
+                let mut state = function.init();
+                let mut other = function.init();
+                for (value, length) in &runs[..split] {
+                    function.update_run(&mut *state, *value, *length);
+                }
+                for (value, length) in &runs[split..] {
+                    function.update_run(&mut *other, *value, *length);
+                }
+                function.merge(&mut *state, &*other);
+
+                prop_assert_eq!(function.finalize(&*state, 2), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_aggregate_matches_query_1() {
+        let lineitems: Vec<LineItem> = (0..1000)
+            .map(|i| LineItem {
+                l_returnflag: ["A", "N", "R"][i % 3].to_string(),
+                l_linestatus: ["F", "O"][i / 500].to_string(),
+                l_quantity: (i % 50) as f64,
+                l_extendedprice: 1000.0 + i as f64,
+                l_discount: (i % 10) as f64 / 100.0,
+                l_tax: 0.0,
+                l_shipdate: 10000 + (i % 700) as i32,
+            })
+            .collect();
+        let file = TempColumnFile::with_row_group_size("aggregate", &lineitems, 128);
+        let path = file.path();
+        let cutoff = 10500;
+
+        let rows = query_1_column(path, cutoff).unwrap();
+        let mut reader = ColumnFileReader::open(path).unwrap();
+        let groups = aggregate(
+            &mut reader,
+            &["l_returnflag", "l_linestatus"],
+            &[
+                AggregateExpr::sum("l_quantity"),
+                AggregateExpr::sum("l_extendedprice"),
+                AggregateExpr::avg("l_quantity"),
+                AggregateExpr::count("l_linestatus"),
+                AggregateExpr::min("l_discount"),
+                AggregateExpr::max("l_shipdate"),
+                AggregateExpr::approx_count_distinct("l_discount"),
+                AggregateExpr::max("l_tax"),
+            ],
+            &[Predicate::lt_eq("l_shipdate", cutoff as f64)],
+        )
+        .unwrap();
+
+        assert_eq!(groups.len(), rows.len());
+        for ((key, values), row) in groups.iter().zip(&rows) {
+            let matching: Vec<_> = lineitems
+                .iter()
+                .filter(|x| x.l_shipdate <= cutoff)
+                .filter(|x| {
+                    x.l_returnflag == row.l_returnflag && x.l_linestatus == row.l_linestatus
+                })
+                .collect();
+            assert_eq!(
+                key,
+                &vec![
+                    GroupValue::Byte(row.l_returnflag.as_bytes()[0]),
+                    GroupValue::Byte(row.l_linestatus.as_bytes()[0]),
+                ]
+            );
+            assert_eq!(
+                values,
+                &vec![
+                    AggregateValue::Decimal(row.sum_qty),
+                    AggregateValue::Decimal(row.sum_base_price),
+                    AggregateValue::Float(row.avg_qty),
+                    AggregateValue::Count(row.count_order),
+                    AggregateValue::Decimal(Decimal::new(0, 2)),
+                    AggregateValue::Decimal(Decimal::new(
+                        matching.iter().map(|x| x.l_shipdate).max().unwrap() as i128,
+                        0
+                    )),
+                    AggregateValue::Count(10),
+                    AggregateValue::Decimal(Decimal::new(0, 2)),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_aggregate_rejects_varchar_inputs() {
+        let mut writer = ColumnFileWriter::with_schema(
+            Vec::new(),
+            crate::schema::Schema::new(vec![crate::schema::Field::new(
+                "o_comment",
+                LogicalType::Varchar,
+            )]),
+        );
+        writer
+            .write_row_group(&[crate::row_group::ColumnValues::Strings(vec![
+                "pending".to_string()
+            ])])
+            .unwrap();
+        let bytes = writer.finish().unwrap().into_inner().into_inner().unwrap();
+        let mut reader = ColumnFileReader::new(Cursor::new(bytes)).unwrap();
+
+        let result = aggregate(&mut reader, &[], &[AggregateExpr::count("o_comment")], &[]);
+
+        assert!(matches!(result, Err(Error::InvalidQuery(_))));
+    }
+}
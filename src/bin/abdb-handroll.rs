@@ -1,5 +1,11 @@
-use abdb::{print_state_column, query_1_column};
+use abdb::{date_column::parse_date, print_query_1, query_1_column, QUERY_1_SHIPDATE_CUTOFF};
 fn main() {
-    let result = query_1_column("lineitems_column.bin");
-    print_state_column(result);
+    let shipdate_cutoff = parse_date(QUERY_1_SHIPDATE_CUTOFF).unwrap();
+    match query_1_column("lineitems_column.bin", shipdate_cutoff) {
+        Ok(result) => print_query_1(&result),
+        Err(e) => {
+            eprintln!("lineitems_column.bin: {}", e);
+            std::process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,683 @@
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+
+use datafusion::arrow::record_batch::RecordBatch;
+
+use crate::error::{Error, Result};
+use crate::header::{FileHeader, MAGIC};
+use crate::io;
+use crate::predicate::Predicate;
+use crate::record_batch::{column_values, record_batch};
+use crate::row_group::{read_row_group_columns, write_row_group_columns, ColumnValues};
+use crate::scan::{read_column, ProjectedRowGroup};
+use crate::schema::{lineitem_schema, Schema};
+use crate::{write_batch, LineItem, TrackedWriter, DEFAULT_ROW_GROUP_SIZE, MAX_ROW_GROUP_SIZE};
+
+// Footer trailer: u64 footer length followed by the magic bytes
+const TRAILER_SIZE: u64 = 8 + MAGIC.len() as u64;
+
+/// Zone map for one column chunk. `min` and `max` are in the column's
+/// stored representation (unscaled decimals, string bytes).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ColumnStats {
+    pub min: i64,
+    pub max: i64,
+    pub count: u32,
+}
+
+impl ColumnStats {
+    pub fn from_values(values: impl Iterator<Item = i64>) -> Self {
+        let mut stats = ColumnStats {
+            min: i64::MAX,
+            max: i64::MIN,
+            count: 0,
+        };
+        for value in values {
+            stats.min = stats.min.min(value);
+            stats.max = stats.max.max(value);
+            stats.count += 1;
+        }
+        if stats.count == 0 {
+            stats.min = 0;
+            stats.max = 0;
+        }
+        stats
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnChunkMeta {
+    pub offset: u64,
+    pub length: u64,
+    pub stats: ColumnStats,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowGroupMeta {
+    pub offset: u64,
+    pub row_count: u32,
+    pub columns: Vec<ColumnChunkMeta>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Footer {
+    pub row_groups: Vec<RowGroupMeta>,
+}
+
+impl Footer {
+    pub fn write<W: Write>(&self, writer: &mut TrackedWriter<W>) -> Result<()> {
+        let start = writer.bytes_written();
+        io::write_u32(writer, self.row_groups.len() as u32)?;
+        for row_group in &self.row_groups {
+            io::write_u64(writer, row_group.offset)?;
+            io::write_u32(writer, row_group.row_count)?;
+            let column_count =
+                u8::try_from(row_group.columns.len()).expect("Row group has too many columns");
+            io::write_u8(writer, column_count)?;
+            for column in &row_group.columns {
+                io::write_u64(writer, column.offset)?;
+                io::write_u64(writer, column.length)?;
+                io::write_u64(writer, column.stats.min as u64)?;
+                io::write_u64(writer, column.stats.max as u64)?;
+                io::write_u32(writer, column.stats.count)?;
+            }
+        }
+        let footer_length = (writer.bytes_written() - start) as u64;
+        io::write_u64(writer, footer_length)?;
+        writer.write_all(&MAGIC)?;
+        Ok(())
+    }
+
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let file_length = reader.seek(SeekFrom::End(0))?;
+        if file_length < TRAILER_SIZE {
+            return Err(Error::MissingFooter);
+        }
+        reader.seek(SeekFrom::End(-(TRAILER_SIZE as i64)))?;
+        let mut trailer = [0u8; TRAILER_SIZE as usize];
+        reader.read_exact(&mut trailer)?;
+        if trailer[8..] != MAGIC {
+            return Err(Error::MissingFooter);
+        }
+        let footer_length = u64::from_le_bytes(trailer[0..8].try_into().unwrap());
+        if footer_length > file_length - TRAILER_SIZE {
+            return Err(Error::MissingFooter);
+        }
+
+        reader.seek(SeekFrom::End(-((TRAILER_SIZE + footer_length) as i64)))?;
+        let mut body = vec![0u8; footer_length as usize];
+        reader.read_exact(&mut body)?;
+
+        let mut body = body.as_slice();
+        let row_group_count = io::read_u32(&mut body)?;
+        let mut row_groups = Vec::new();
+        for _ in 0..row_group_count {
+            let offset = io::read_u64(&mut body)?;
+            let row_count = io::read_u32(&mut body)?;
+            let column_count = io::read_u8(&mut body)?;
+            let columns = (0..column_count)
+                .map(|_| {
+                    Ok(ColumnChunkMeta {
+                        offset: io::read_u64(&mut body)?,
+                        length: io::read_u64(&mut body)?,
+                        stats: ColumnStats {
+                            min: io::read_u64(&mut body)? as i64,
+                            max: io::read_u64(&mut body)? as i64,
+                            count: io::read_u32(&mut body)?,
+                        },
+                    })
+                })
+                .collect::<Result<_>>()?;
+            row_groups.push(RowGroupMeta {
+                offset,
+                row_count,
+                columns,
+            });
+        }
+        Ok(Footer { row_groups })
+    }
+
+    /// Checks the row groups against `schema`. Later reads index column
+    /// chunks by schema position.
+    pub fn validate(&self, schema: &Schema) -> Result<()> {
+        for (index, row_group) in self.row_groups.iter().enumerate() {
+            if row_group.row_count as usize > MAX_ROW_GROUP_SIZE {
+                return Err(Error::corrupt(format!(
+                    "row group {} has {} rows, at most {} are supported",
+                    index, row_group.row_count, MAX_ROW_GROUP_SIZE
+                )));
+            }
+            if row_group.columns.len() != schema.len() {
+                return Err(Error::corrupt(format!(
+                    "row group {} has {} columns but the header lists {}",
+                    index,
+                    row_group.columns.len(),
+                    schema.len()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Indices of the row groups whose zone maps do not rule out all of
+    /// `predicates` (which are ANDed together).
+    pub fn matching_row_groups(
+        &self,
+        schema: &Schema,
+        predicates: &[Predicate],
+    ) -> Result<Vec<usize>> {
+        let columns: Vec<usize> = predicates
+            .iter()
+            .map(|predicate| required_column_index(schema, &predicate.column))
+            .collect::<Result<_>>()?;
+        Ok(self
+            .row_groups
+            .iter()
+            .enumerate()
+            .filter(|(_, row_group)| {
+                predicates.iter().zip(&columns).all(|(predicate, &column)| {
+                    predicate.may_match(
+                        &row_group.columns[column].stats,
+                        schema.fields[column].logical_type,
+                    )
+                })
+            })
+            .map(|(index, _)| index)
+            .collect())
+    }
+}
+
+pub(crate) fn required_column_index(schema: &Schema, name: &str) -> Result<usize> {
+    schema
+        .index_of(name)
+        .ok_or_else(|| Error::UnknownColumn(name.to_string()))
+}
+
+/// Writes row groups of a fixed schema and, on `finish`, the footer indexing
+/// them.
+pub struct ColumnFileWriter<W: Write> {
+    writer: TrackedWriter<W>,
+    schema: Schema,
+    footer: Footer,
+    row_group_size: usize,
+}
+
+impl<W: Write> ColumnFileWriter<W> {
+    pub fn new(writer: W) -> Self {
+        ColumnFileWriter::with_schema(writer, lineitem_schema())
+    }
+
+    pub fn with_schema(writer: W, schema: Schema) -> Self {
+        ColumnFileWriter {
+            writer: TrackedWriter::new(writer),
+            schema,
+            footer: Footer::default(),
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+        }
+    }
+
+    /// Sets the most rows a row group may hold. `write_batch` splits larger
+    /// batches; `write_row_group` rejects larger row groups.
+    pub fn with_row_group_size(mut self, row_group_size: usize) -> Self {
+        assert!(
+            (1..=MAX_ROW_GROUP_SIZE).contains(&row_group_size),
+            "Row group size must be between 1 and {}, got {}",
+            MAX_ROW_GROUP_SIZE,
+            row_group_size
+        );
+        self.row_group_size = row_group_size;
+        self
+    }
+
+    pub fn row_group_size(&self) -> usize {
+        self.row_group_size
+    }
+
+    pub fn write_row_group(&mut self, columns: &[ColumnValues]) -> Result<()> {
+        let row_count = columns.first().map_or(0, |x| x.len());
+        assert!(
+            row_count <= self.row_group_size,
+            "Row group has {} rows, the writer allows at most {}",
+            row_count,
+            self.row_group_size
+        );
+        let row_group = write_row_group_columns(&self.schema, columns, &mut self.writer)?;
+        self.footer.row_groups.push(row_group);
+        Ok(())
+    }
+
+    pub fn write_batch(&mut self, batch: &mut [LineItem]) -> Result<()> {
+        assert_eq!(
+            self.schema,
+            lineitem_schema(),
+            "write_batch requires the lineitem schema"
+        );
+        for chunk in batch.chunks_mut(self.row_group_size) {
+            let row_group = write_batch(&mut self.writer, chunk)?;
+            self.footer.row_groups.push(row_group);
+        }
+        Ok(())
+    }
+
+    /// Writes `batch` in row groups of at most `row_group_size` rows. Its
+    /// columns must match the writer's schema, see `column_values`.
+    pub fn write_record_batch(&mut self, batch: &RecordBatch) -> Result<()> {
+        for offset in (0..batch.num_rows()).step_by(self.row_group_size) {
+            let length = self.row_group_size.min(batch.num_rows() - offset);
+            let columns = column_values(&batch.slice(offset, length), &self.schema)?;
+            self.write_row_group(&columns)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<TrackedWriter<W>> {
+        // An empty file still needs a header so it can be opened
+        if self.writer.bytes_written() == 0 {
+            FileHeader::new(self.schema.clone()).write(&mut self.writer)?;
+        }
+        self.footer.write(&mut self.writer)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Random access to the row groups and column chunks of a column file via
+/// its footer.
+pub struct ColumnFileReader<R: Read + Seek> {
+    reader: BufReader<R>,
+    header: FileHeader,
+    footer: Footer,
+}
+
+impl ColumnFileReader<std::fs::File> {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        ColumnFileReader::new(file)
+    }
+}
+
+impl<R: Read + Seek> ColumnFileReader<R> {
+    pub fn new(inner: R) -> Result<Self> {
+        let mut reader = BufReader::new(inner);
+        reader.seek(SeekFrom::Start(0))?;
+        let header = FileHeader::read(&mut reader)?;
+        let footer = Footer::read(&mut reader)?;
+        footer.validate(&header.schema)?;
+        Ok(ColumnFileReader {
+            reader,
+            header,
+            footer,
+        })
+    }
+
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    pub fn row_groups(&self) -> &[RowGroupMeta] {
+        &self.footer.row_groups
+    }
+
+    pub fn row_group_count(&self) -> usize {
+        self.footer.row_groups.len()
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.header.schema
+    }
+
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.header.schema.index_of(name)
+    }
+
+    /// See `Footer::matching_row_groups`.
+    pub fn matching_row_groups(&self, predicates: &[Predicate]) -> Result<Vec<usize>> {
+        self.footer
+            .matching_row_groups(&self.header.schema, predicates)
+    }
+
+    /// Calls `f` with the reader positioned at each row group that may
+    /// satisfy `predicates`, skipping the rest without reading them. Row
+    /// groups that are passed on can still contain non-matching rows.
+    pub fn scan(
+        &mut self,
+        predicates: &[Predicate],
+        mut f: impl FnMut(&mut BufReader<R>, &RowGroupMeta) -> Result<()>,
+    ) -> Result<()> {
+        for index in self.matching_row_groups(predicates)? {
+            let row_group = self.footer.row_groups[index].clone();
+            f(self.seek_to_row_group(index)?, &row_group)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_row_group(&mut self, index: usize) -> Result<Vec<ColumnValues>> {
+        let schema = self.header.schema.clone();
+        read_row_group_columns(self.seek_to_row_group(index)?, &schema)
+    }
+
+    /// Decodes only the `projection` columns of `row_group`, seeking directly
+    /// to each column chunk.
+    pub fn read_columns(
+        &mut self,
+        row_group: usize,
+        projection: &[usize],
+    ) -> Result<ProjectedRowGroup> {
+        let row_count = self.footer.row_groups[row_group].row_count;
+        let mut columns: Vec<_> = (0..self.header.schema.len()).map(|_| None).collect();
+        for &column in projection {
+            let logical_type = self.header.schema.fields[column].logical_type;
+            let reader = self.seek_to_column_chunk(row_group, column)?;
+            columns[column] = Some(read_column(reader, logical_type, row_count)?);
+        }
+        Ok(ProjectedRowGroup { row_count, columns })
+    }
+
+    /// The `projection` columns of `row_group` as Arrow arrays, in
+    /// projection order.
+    pub fn read_record_batch(
+        &mut self,
+        row_group: usize,
+        projection: &[usize],
+    ) -> Result<RecordBatch> {
+        let columns = self.read_columns(row_group, projection)?;
+        record_batch(&columns, &self.header.schema, projection, None)
+    }
+
+    /// Like `scan`, but only the named columns are read from each row group.
+    pub fn scan_projected(
+        &mut self,
+        columns: &[&str],
+        predicates: &[Predicate],
+        mut f: impl FnMut(&ProjectedRowGroup),
+    ) -> Result<()> {
+        let projection: Vec<usize> = columns
+            .iter()
+            .map(|name| required_column_index(&self.header.schema, name))
+            .collect::<Result<_>>()?;
+        for index in self.matching_row_groups(predicates)? {
+            f(&self.read_columns(index, &projection)?);
+        }
+        Ok(())
+    }
+
+    /// Positions the reader at the start of row group `index`, ready for
+    /// `update_state_from_row_group`.
+    pub fn seek_to_row_group(&mut self, index: usize) -> Result<&mut BufReader<R>> {
+        let offset = self.footer.row_groups[index].offset;
+        self.reader.seek(SeekFrom::Start(offset))?;
+        Ok(&mut self.reader)
+    }
+
+    /// Positions the reader at the start of column `column` within row
+    /// group `row_group`.
+    pub fn seek_to_column_chunk(
+        &mut self,
+        row_group: usize,
+        column: usize,
+    ) -> Result<&mut BufReader<R>> {
+        let offset = self.footer.row_groups[row_group].columns[column].offset;
+        self.reader.seek(SeekFrom::Start(offset))?;
+        Ok(&mut self.reader)
+    }
+
+    pub fn read_column_chunk(&mut self, row_group: usize, column: usize) -> Result<Vec<u8>> {
+        let length = self.footer.row_groups[row_group].columns[column].length;
+        let mut buffer = vec![0u8; length as usize];
+        self.seek_to_column_chunk(row_group, column)?
+            .read_exact(&mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::f64_column::{DecimalColumn, DecimalEncoding};
+    use crate::header::lineitem_header;
+    use crate::{get_state_index, update_state_from_row_group};
+
+    fn lineitem(returnflag: &str, quantity: f64) -> LineItem {
+        LineItem {
+            l_returnflag: returnflag.to_string(),
+            l_linestatus: "F".to_string(),
+            l_quantity: quantity,
+            l_extendedprice: 2.0,
+            l_discount: 0.05,
+            l_tax: 0.01,
+            l_shipdate: 10000,
+        }
+    }
+
+    fn write_file(batches: Vec<Vec<LineItem>>) -> Vec<u8> {
+        let mut writer = ColumnFileWriter::new(Vec::new());
+        for mut batch in batches {
+            writer.write_batch(&mut batch).unwrap();
+        }
+        writer.finish().unwrap().into_inner().into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_footer_indexes_row_groups() {
+        let bytes = write_file(vec![
+            vec![lineitem("A", 1.0); 10],
+            vec![lineitem("N", 2.0); 20],
+            vec![lineitem("R", 3.0); 30],
+        ]);
+
+        let reader = ColumnFileReader::new(Cursor::new(bytes)).unwrap();
+
+        assert_eq!(reader.header(), &lineitem_header());
+        assert_eq!(
+            reader
+                .row_groups()
+                .iter()
+                .map(|x| x.row_count)
+                .collect::<Vec<_>>(),
+            vec![10, 20, 30]
+        );
+        assert!(reader.row_groups().iter().all(|x| x.columns.len() == 7));
+    }
+
+    #[test]
+    fn test_row_groups_beyond_u16_rows() {
+        let mut batch: Vec<LineItem> = (0..100_000)
+            .map(|i| lineitem(if i % 2 == 0 { "A" } else { "N" }, 1.0))
+            .collect();
+        let mut writer = ColumnFileWriter::new(Vec::new()).with_row_group_size(70_000);
+        writer.write_batch(&mut batch).unwrap();
+        let bytes = writer.finish().unwrap().into_inner().into_inner().unwrap();
+
+        let mut reader = ColumnFileReader::new(Cursor::new(bytes)).unwrap();
+        let mut state = vec![None; 256 * 256];
+        reader
+            .scan(&[], |reader, _| {
+                update_state_from_row_group(reader, i32::MAX, &mut state)
+            })
+            .unwrap();
+
+        assert_eq!(
+            reader
+                .row_groups()
+                .iter()
+                .map(|x| x.row_count)
+                .collect::<Vec<_>>(),
+            vec![70_000, 30_000]
+        );
+        assert_eq!(reader.read_columns(0, &[2]).unwrap().row_count, 70_000);
+        let a_state = state[get_state_index(&b'A', &b'F')].as_ref().unwrap();
+        assert_eq!(a_state.count, 50_000);
+        assert_eq!(a_state.sum_qty, 50_000 * 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "the writer allows at most 2")]
+    fn test_write_row_group_respects_row_group_size() {
+        let mut writer = ColumnFileWriter::new(Vec::new()).with_row_group_size(2);
+
+        let _ = writer.write_row_group(&crate::lineitem_columns(&vec![lineitem("A", 1.0); 3]));
+    }
+
+    #[test]
+    fn test_writer_returns_io_errors() {
+        struct FullDisk;
+        impl Write for FullDisk {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::StorageFull.into())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        // The tracked writer buffers, so errors surface once the buffer fills
+        let mut writer = ColumnFileWriter::new(FullDisk);
+
+        let mut lineitems: Vec<LineItem> = (0..20_000)
+            .map(|i| lineitem("A", (i % 1000) as f64))
+            .collect();
+        let result = writer.write_batch(&mut lineitems);
+
+        assert!(matches!(
+            result,
+            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::StorageFull
+        ));
+        assert!(matches!(
+            ColumnFileWriter::new(FullDisk).finish(),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_seek_to_row_group() {
+        let bytes = write_file(vec![
+            vec![lineitem("A", 1.0); 10],
+            vec![lineitem("N", 2.0); 20],
+        ]);
+        let mut reader = ColumnFileReader::new(Cursor::new(bytes)).unwrap();
+        let mut state = vec![None; 256 * 256];
+
+        update_state_from_row_group(reader.seek_to_row_group(1).unwrap(), i32::MAX, &mut state)
+            .unwrap();
+
+        assert_eq!(state[get_state_index(&b'A', &b'F')], None);
+        let n_state = state[get_state_index(&b'N', &b'F')].as_ref().unwrap();
+        assert_eq!(n_state.count, 20);
+        assert_eq!(n_state.sum_qty, 20 * 200);
+    }
+
+    #[test]
+    fn test_seek_to_column_chunk() {
+        let bytes = write_file(vec![
+            vec![lineitem("A", 1.0); 10],
+            vec![lineitem("N", 2.5); 20],
+        ]);
+        let mut reader = ColumnFileReader::new(Cursor::new(bytes)).unwrap();
+
+        let quantity =
+            DecimalColumn::read(reader.seek_to_column_chunk(1, 2).unwrap(), 2, 20).unwrap();
+
+        assert!(quantity.to_i64s().iter().all(|x| *x == 250));
+        assert_eq!(quantity.encoding(), DecimalEncoding::Constant);
+        // Encoding, value, up to 3 bytes of padding after its length byte
+        // and the row count
+        let length = reader.read_column_chunk(1, 2).unwrap().len();
+        assert!((1 + 8 + 1 + 4..=1 + 8 + 1 + 3 + 4).contains(&length));
+    }
+
+    #[test]
+    fn test_footer_stores_column_stats() {
+        let mut batch = vec![lineitem("A", 1.0); 5];
+        batch.push(lineitem("R", 7.5));
+        let bytes = write_file(vec![batch]);
+
+        let reader = ColumnFileReader::new(Cursor::new(bytes)).unwrap();
+        let columns = &reader.row_groups()[0].columns;
+
+        assert_eq!(
+            columns[1].stats,
+            ColumnStats {
+                min: b'A' as i64,
+                max: b'R' as i64,
+                count: 6
+            }
+        );
+        assert_eq!(
+            columns[2].stats,
+            ColumnStats {
+                min: 100,
+                max: 750,
+                count: 6
+            }
+        );
+    }
+
+    #[test]
+    fn test_scan_skips_row_groups() {
+        let bytes = write_file(vec![
+            vec![lineitem("A", 10.0); 10],
+            vec![lineitem("N", 30.0); 20],
+            vec![lineitem("R", 20.0); 30],
+        ]);
+        let mut reader = ColumnFileReader::new(Cursor::new(bytes)).unwrap();
+
+        assert_eq!(
+            reader
+                .matching_row_groups(&[Predicate::lt("l_quantity", 25.0)])
+                .unwrap(),
+            vec![0, 2]
+        );
+        assert_eq!(
+            reader
+                .matching_row_groups(&[
+                    Predicate::lt("l_quantity", 25.0),
+                    Predicate::eq("l_returnflag", b'R' as f64)
+                ])
+                .unwrap(),
+            vec![2]
+        );
+
+        let mut state = vec![None; 256 * 256];
+        reader
+            .scan(&[Predicate::gt("l_quantity", 25.0)], |reader, _| {
+                update_state_from_row_group(reader, i32::MAX, &mut state)
+            })
+            .unwrap();
+        assert_eq!(state.iter().flatten().count(), 1);
+        assert_eq!(
+            state[get_state_index(&b'N', &b'F')].as_ref().unwrap().count,
+            20
+        );
+    }
+
+    #[test]
+    fn test_missing_footer() {
+        let mut bytes = Vec::new();
+        lineitem_header().write(&mut bytes).unwrap();
+
+        let result = ColumnFileReader::new(Cursor::new(bytes));
+
+        assert!(matches!(result, Err(Error::MissingFooter)));
+    }
+
+    #[test]
+    fn test_unknown_predicate_column() {
+        let bytes = write_file(vec![vec![lineitem("A", 1.0); 10]]);
+        let reader = ColumnFileReader::new(Cursor::new(bytes)).unwrap();
+
+        let result = reader.matching_row_groups(&[Predicate::lt("l_orderkey", 5.0)]);
+
+        assert!(matches!(result, Err(Error::UnknownColumn(name)) if name == "l_orderkey"));
+    }
+
+    #[test]
+    fn test_corrupt_column_chunk_is_an_error() {
+        let mut bytes = write_file(vec![vec![lineitem("A", 1.0); 10]]);
+        let reader = ColumnFileReader::new(Cursor::new(bytes.clone())).unwrap();
+        // Overwrite the encoding tag of the quantity chunk
+        bytes[reader.row_groups()[0].columns[2].offset as usize] = 3;
+        let mut reader = ColumnFileReader::new(Cursor::new(bytes)).unwrap();
+
+        assert!(matches!(reader.read_row_group(0), Err(Error::Corrupt(_))));
+    }
+}
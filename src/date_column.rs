@@ -0,0 +1,115 @@
+//! Dates are stored as days since 1970-01-01. Date columns use the same
+//! frame-of-reference encoding as decimal columns with a scale of 0, so a
+//! column spanning a few years packs into two bytes per row.
+
+/// Days since 1970-01-01 for a proleptic Gregorian calendar date, or `None`
+/// if the date is too far away for an `i32`.
+pub fn days_from_civil(year: i32, month: u32, day: u32) -> Option<i32> {
+    let year = year as i64 - if month <= 2 { 1 } else { 0 };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = ((153 * ((month + 9) % 12) + 2) / 5 + day - 1) as i64;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    i32::try_from(era * 146097 + day_of_era - 719468).ok()
+}
+
+/// The `(year, month, day)` of a day count since 1970-01-01.
+pub fn civil_from_days(days: i32) -> (i32, u32, u32) {
+    // In i64 so the offset cannot overflow; every i32 day count has a year
+    // that fits in an i32
+    let days = days as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year as i32, month, day)
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parses a `YYYY-MM-DD` date into days since 1970-01-01.
+pub fn parse_date(value: &str) -> Option<i32> {
+    let mut parts = value.splitn(3, '-');
+    let year: i32 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    days_from_civil(year, month, day)
+}
+
+/// Adds calendar months to a date, clamping the day to the end of a shorter
+/// month as `1998-01-31 + 1 month = 1998-02-28`. `None` if the result is out
+/// of range.
+pub fn add_months(days: i32, months: i32) -> Option<i32> {
+    let (year, month, day) = civil_from_days(days);
+    let month_index = year as i64 * 12 + month as i64 - 1 + months as i64;
+    let year = i32::try_from(month_index.div_euclid(12)).ok()?;
+    let month = month_index.rem_euclid(12) as u32 + 1;
+    days_from_civil(year, month, day.min(days_in_month(year, month)))
+}
+
+pub fn format_date(days: i32) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("1969-12-31"), Some(-1));
+        assert_eq!(parse_date("1998-09-02"), Some(10471));
+        assert_eq!(parse_date("2000-02-29"), Some(11016));
+        assert_eq!(parse_date("1999-02-29"), None);
+        assert_eq!(parse_date("1998-13-01"), None);
+        assert_eq!(parse_date("1998-09"), None);
+        assert_eq!(parse_date("99999999-01-01"), None);
+    }
+
+    #[test]
+    fn test_format_date_round_trips() {
+        for days in -1000..30000 {
+            assert_eq!(parse_date(&format_date(days)), Some(days));
+        }
+        assert_eq!(format_date(8036), "1992-01-02");
+        for days in [i32::MIN, i32::MAX] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), Some(days));
+        }
+    }
+
+    #[test]
+    fn test_add_months() {
+        let date = |x| parse_date(x).unwrap();
+        assert_eq!(add_months(date("1994-01-01"), 12), Some(date("1995-01-01")));
+        assert_eq!(add_months(date("1998-01-31"), 1), Some(date("1998-02-28")));
+        assert_eq!(add_months(date("2000-03-31"), -1), Some(date("2000-02-29")));
+        assert_eq!(
+            add_months(date("1998-12-15"), -24),
+            Some(date("1996-12-15"))
+        );
+        assert_eq!(add_months(date("1998-12-15"), i32::MAX), None);
+        assert_eq!(add_months(i32::MIN, -1), None);
+    }
+}
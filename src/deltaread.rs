@@ -1,10 +1,48 @@
 use deltalake::datafusion::execution::context::SessionContext;
+use deltalake::datafusion::prelude::ParquetReadOptions;
 use deltalake::open_table;
+use deltalake::protocol::SaveMode;
+use deltalake::DeltaOps;
 use std::sync::Arc;
 
-pub async fn query_1_delta(sql: &str) {
+const DELTA_TABLE: &str = "./output3.parquet";
+
+/// Replaces the Delta table with the rows of the parquet file at `path`.
+/// Delta has no dictionary type, so the flags are stored as plain strings.
+pub async fn save_delta(path: &str) {
+    let ctx = SessionContext::new();
+    ctx.register_parquet("lineitem", path, ParquetReadOptions::default())
+        .await
+        .expect("Failed to register parquet file");
+    let df = ctx
+        .sql(
+            "SELECT
+                CAST(l_returnflag AS VARCHAR) AS l_returnflag,
+                CAST(l_linestatus AS VARCHAR) AS l_linestatus,
+                l_quantity,
+                l_extendedprice,
+                l_discount,
+                l_tax,
+                l_shipdate
+            FROM lineitem",
+        )
+        .await
+        .expect("Failed to execute query");
+    let batches = df.collect().await.expect("Failed to collect results");
+
+    DeltaOps::try_from_uri(DELTA_TABLE)
+        .await
+        .expect("Failed to open Delta table")
+        .write(batches)
+        .with_save_mode(SaveMode::Overwrite)
+        .await
+        .expect("Failed to write Delta table");
+    println!("Done writing Delta table");
+}
+
+pub async fn query_delta(sql: &str) {
     let ctx = SessionContext::new();
-    let table = open_table("./output3.parquet").await.unwrap();
+    let table = open_table(DELTA_TABLE).await.unwrap();
     ctx.register_table("lineitem", Arc::new(table)).unwrap();
 
     let df = ctx.sql(sql).await.expect("Failed to execute query");
@@ -0,0 +1,112 @@
+use std::fmt;
+
+use datafusion::arrow::error::ArrowError;
+use datafusion::error::DataFusionError;
+
+use crate::f64_column::MAX_DECIMAL_SCALE;
+use crate::header::{FORMAT_VERSION, MAGIC};
+use crate::schema::Schema;
+
+/// Errors from opening, reading and querying column files.
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    /// The file's contents contradict its own metadata
+    Corrupt(String),
+    BadMagic([u8; 4]),
+    UnsupportedVersion(u16),
+    UnknownLogicalType(u8),
+    UnsupportedScale(u8),
+    MissingFooter,
+    SchemaMismatch {
+        expected: Schema,
+        found: Schema,
+    },
+    /// A predicate or projection names a column the file does not have
+    UnknownColumn(String),
+    /// The query cannot be answered from this file
+    InvalidQuery(String),
+    /// Converting to or from Arrow arrays failed
+    Arrow(ArrowError),
+    /// An Arrow column whose type or values the column format cannot hold
+    IncompatibleArrowColumn {
+        column: String,
+        reason: String,
+    },
+    /// A value the column type cannot store
+    InvalidValue(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn corrupt(message: impl Into<String>) -> Self {
+        Error::Corrupt(message.into())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Corrupt(message) => write!(f, "corrupt column file: {}", message),
+            Error::BadMagic(magic) => write!(
+                f,
+                "not an abdb column file (magic {:?}, expected {:?}); use legacy mode for headerless files",
+                magic, MAGIC
+            ),
+            Error::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported format version {} (this build reads version {}); rewrite the file with this build",
+                version, FORMAT_VERSION
+            ),
+            Error::UnknownLogicalType(value) => write!(f, "unknown column type {}", value),
+            Error::UnsupportedScale(scale) => write!(
+                f,
+                "unsupported decimal scale {} (maximum {})",
+                scale, MAX_DECIMAL_SCALE
+            ),
+            Error::MissingFooter => write!(f, "missing or truncated file footer"),
+            Error::SchemaMismatch { expected, found } => write!(
+                f,
+                "column layout mismatch: expected {:?}, found {:?}",
+                expected, found
+            ),
+            Error::UnknownColumn(name) => write!(f, "unknown column {}", name),
+            Error::InvalidQuery(message) => write!(f, "invalid query: {}", message),
+            Error::Arrow(e) => write!(f, "Arrow error: {}", e),
+            Error::IncompatibleArrowColumn { column, reason } => {
+                write!(f, "cannot store Arrow column {}: {}", column, reason)
+            }
+            Error::InvalidValue(message) => write!(f, "invalid value: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Arrow(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<ArrowError> for Error {
+    fn from(e: ArrowError) -> Self {
+        Error::Arrow(e)
+    }
+}
+
+impl From<Error> for DataFusionError {
+    fn from(e: Error) -> Self {
+        DataFusionError::External(Box::new(e))
+    }
+}
@@ -0,0 +1,1204 @@
+use std::fmt;
+use std::io::{Read, Seek, Write};
+use std::ops::Range;
+
+use crate::error::{Error, Result};
+use crate::io;
+use crate::kernels;
+use crate::TrackedWriter;
+
+// Packed values and run lengths are little-endian on disk. Buffered reads
+// and writes swap them on big-endian targets, but viewing them in place, as
+// `DecimalColumnRef::parse` does, needs a little-endian target.
+
+/// Largest supported decimal scale; 10^18 is the largest power of ten that
+/// fits in an i64.
+pub const MAX_DECIMAL_SCALE: u8 = 18;
+
+// Largest magnitude an unscaled value can have and still convert to and from
+// f64 without loss
+pub(crate) const MAX_EXACT_UNSCALED: i64 = 1 << f64::MANTISSA_DIGITS;
+
+// Legacy fixed-point encoding: two decimal places packed into a u16. Still
+// used by the row format in `lineitems.bin` and by headerless column files.
+pub fn compress_f64(f: f64) -> u16 {
+    let compressed = (f * 100.0).round();
+    assert!(
+        (0.0..=u16::MAX as f64).contains(&compressed),
+        "{} is outside the u16 fixed-point range 0..={}",
+        f,
+        decompress_f64(u16::MAX)
+    );
+    compressed as u16
+}
+
+pub fn decompress_f64(f: u16) -> f64 {
+    f as f64 / 100.0
+}
+
+pub fn write_f64_column<W: Write>(
+    values: impl Iterator<Item = f64>,
+    writer: &mut W,
+) -> Result<()> {
+    for value in values {
+        writer.write_all(&compress_f64(value).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn scale_factor(scale: u8) -> i64 {
+    assert!(
+        scale <= MAX_DECIMAL_SCALE,
+        "Decimal scale {} is larger than {}",
+        scale,
+        MAX_DECIMAL_SCALE
+    );
+    10i64.pow(scale as u32)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecimalError {
+    /// The value has more than `scale` decimal places, is not finite or is
+    /// too large to be held exactly.
+    NotRepresentable { value: f64, scale: u8 },
+}
+
+impl fmt::Display for DecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecimalError::NotRepresentable { value, scale } => write!(
+                f,
+                "{} cannot be stored exactly as a decimal with scale {}",
+                value, scale
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecimalError {}
+
+/// Converts `value` to an integer count of `10^-scale` units, failing if
+/// that would lose information.
+pub fn to_unscaled(value: f64, scale: u8) -> std::result::Result<i64, DecimalError> {
+    let unscaled = (value * scale_factor(scale) as f64).round();
+    // The nearest decimal must convert back to the very same f64, otherwise
+    // the value carries more precision than the scale can hold
+    if !unscaled.is_finite()
+        || unscaled.abs() > MAX_EXACT_UNSCALED as f64
+        || from_unscaled(unscaled as i64, scale) != value
+    {
+        return Err(DecimalError::NotRepresentable { value, scale });
+    }
+    Ok(unscaled as i64)
+}
+
+pub fn from_unscaled(value: i64, scale: u8) -> f64 {
+    value as f64 / scale_factor(scale) as f64
+}
+
+/// An exact decimal result, `unscaled / 10^scale`. Wide enough to hold sums
+/// of products of decimal columns without rounding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decimal {
+    pub unscaled: i128,
+    pub scale: u8,
+}
+
+impl Decimal {
+    pub fn new(unscaled: i128, scale: u8) -> Self {
+        Decimal { unscaled, scale }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.unscaled as f64 / scale_factor(self.scale) as f64
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let factor = scale_factor(self.scale) as u128;
+        let sign = if self.unscaled < 0 { "-" } else { "" };
+        let magnitude = self.unscaled.unsigned_abs();
+        if self.scale == 0 {
+            return write!(f, "{}{}", sign, magnitude);
+        }
+        write!(
+            f,
+            "{}{}.{:0width$}",
+            sign,
+            magnitude / factor,
+            magnitude % factor,
+            width = self.scale as usize
+        )
+    }
+}
+
+/// Offsets from a column's reference value, stored in the narrowest unsigned
+/// width that holds the largest offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackedValues {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+    U64(Vec<u64>),
+}
+
+impl Default for PackedValues {
+    fn default() -> Self {
+        PackedValues::U8(Vec::new())
+    }
+}
+
+impl PackedValues {
+    fn pack(offsets: impl Iterator<Item = u64>, max_offset: u64) -> Self {
+        if max_offset <= u8::MAX as u64 {
+            PackedValues::U8(offsets.map(|x| x as u8).collect())
+        } else if max_offset <= u16::MAX as u64 {
+            PackedValues::U16(offsets.map(|x| x as u16).collect())
+        } else if max_offset <= u32::MAX as u64 {
+            PackedValues::U32(offsets.map(|x| x as u32).collect())
+        } else {
+            PackedValues::U64(offsets.collect())
+        }
+    }
+
+    /// Bytes per value
+    pub fn width(&self) -> u8 {
+        match self {
+            PackedValues::U8(_) => 1,
+            PackedValues::U16(_) => 2,
+            PackedValues::U32(_) => 4,
+            PackedValues::U64(_) => 8,
+        }
+    }
+
+    pub fn as_slice(&self) -> PackedSlice<'_> {
+        match self {
+            PackedValues::U8(values) => PackedSlice::U8(values),
+            PackedValues::U16(values) => PackedSlice::U16(values),
+            PackedValues::U32(values) => PackedSlice::U32(values),
+            PackedValues::U64(values) => PackedSlice::U64(values),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> u64 {
+        self.as_slice().get(index)
+    }
+
+    pub fn sum(&self, start: usize, count: usize) -> u128 {
+        self.as_slice().sum(start, count)
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        match self {
+            PackedValues::U8(values) => write_le(writer, values),
+            PackedValues::U16(values) => write_le(writer, values),
+            PackedValues::U32(values) => write_le(writer, values),
+            PackedValues::U64(values) => write_le(writer, values),
+        }
+    }
+
+    /// Reads `len` values of `width` bytes, reusing the current buffer when
+    /// it already has that width.
+    fn read_into<R: Read>(&mut self, reader: &mut R, width: u8, len: usize) -> Result<()> {
+        if width != self.width() {
+            *self = match width {
+                1 => PackedValues::U8(Vec::new()),
+                2 => PackedValues::U16(Vec::new()),
+                4 => PackedValues::U32(Vec::new()),
+                8 => PackedValues::U64(Vec::new()),
+                _ => return Err(invalid_width(width)),
+            };
+        }
+        match self {
+            PackedValues::U8(values) => read_le_into(reader, values, len),
+            PackedValues::U16(values) => read_le_into(reader, values, len),
+            PackedValues::U32(values) => read_le_into(reader, values, len),
+            PackedValues::U64(values) => read_le_into(reader, values, len),
+        }
+    }
+}
+
+/// Packed offsets borrowed from a `PackedValues` or from a mapped file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackedSlice<'a> {
+    U8(&'a [u8]),
+    U16(&'a [u16]),
+    U32(&'a [u32]),
+    U64(&'a [u64]),
+}
+
+impl<'a> PackedSlice<'a> {
+    /// Views the first `len` values of `width` bytes in `bytes`, which must
+    /// be aligned to `width`.
+    #[cfg(target_endian = "little")]
+    fn from_bytes(bytes: &'a [u8], width: u8, len: usize) -> Result<Self> {
+        let bytes = bytes
+            .get(..width as usize * len)
+            .ok_or_else(|| Error::corrupt("packed values are truncated"))?;
+        Ok(match width {
+            1 => PackedSlice::U8(bytes),
+            2 => PackedSlice::U16(cast_aligned(bytes)?),
+            4 => PackedSlice::U32(cast_aligned(bytes)?),
+            8 => PackedSlice::U64(cast_aligned(bytes)?),
+            _ => return Err(invalid_width(width)),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            PackedSlice::U8(values) => values.len(),
+            PackedSlice::U16(values) => values.len(),
+            PackedSlice::U32(values) => values.len(),
+            PackedSlice::U64(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> u64 {
+        match self {
+            PackedSlice::U8(values) => values[index] as u64,
+            PackedSlice::U16(values) => values[index] as u64,
+            PackedSlice::U32(values) => values[index] as u64,
+            PackedSlice::U64(values) => values[index],
+        }
+    }
+
+    pub fn sum(&self, start: usize, count: usize) -> u128 {
+        let range = start..start + count;
+        match self {
+            PackedSlice::U8(values) => values[range].iter().map(|x| *x as u64).sum::<u64>() as u128,
+            PackedSlice::U16(values) => kernels::sum_u16(&values[range]) as u128,
+            PackedSlice::U32(values) => {
+                values[range].iter().map(|x| *x as u64).sum::<u64>() as u128
+            }
+            PackedSlice::U64(values) => values[range].iter().map(|x| *x as u128).sum(),
+        }
+    }
+
+    /// Sum of the values from `start` on whose flag in `selected` is set
+    pub fn masked_sum(&self, start: usize, selected: &[bool]) -> u128 {
+        let range = start..start + selected.len();
+        match self {
+            PackedSlice::U16(values) => kernels::masked_sum_u16(&values[range], selected) as u128,
+            _ => range
+                .zip(selected)
+                .filter(|(_, selected)| **selected)
+                .map(|(i, _)| self.get(i) as u128)
+                .sum(),
+        }
+    }
+}
+
+#[cfg(target_endian = "little")]
+pub(crate) fn cast_aligned<T: bytemuck::Pod>(bytes: &[u8]) -> Result<&[T]> {
+    bytemuck::try_cast_slice(bytes)
+        .map_err(|_| Error::corrupt("column values are not aligned for in-place access"))
+}
+
+/// Integers stored little-endian in column files
+pub(crate) trait LittleEndian: bytemuck::Pod {
+    /// Converts between little-endian and native order, which is the same
+    /// operation both ways
+    fn swap_le(self) -> Self;
+}
+
+macro_rules! impl_little_endian {
+    ($($t:ty),*) => {
+        $(impl LittleEndian for $t {
+            fn swap_le(self) -> Self {
+                <$t>::from_le(self)
+            }
+        })*
+    };
+}
+
+impl_little_endian!(u8, u16, u32, u64);
+
+/// Replaces the contents of `values` with `len` little-endian values read
+/// from `reader`, leaving it empty if the read fails.
+pub(crate) fn read_le_into<R: Read, T: LittleEndian>(
+    reader: &mut R,
+    values: &mut Vec<T>,
+    len: usize,
+) -> Result<()> {
+    values.clear();
+    values.resize(len, T::zeroed());
+    if let Err(e) = reader.read_exact(bytemuck::cast_slice_mut(values)) {
+        values.clear();
+        return Err(e.into());
+    }
+    if cfg!(target_endian = "big") {
+        values.iter_mut().for_each(|x| *x = x.swap_le());
+    }
+    Ok(())
+}
+
+fn write_le<W: Write, T: LittleEndian>(writer: &mut W, values: &[T]) -> Result<()> {
+    if cfg!(target_endian = "little") {
+        writer.write_all(bytemuck::cast_slice(values))?;
+        return Ok(());
+    }
+    for value in values {
+        writer.write_all(bytemuck::bytes_of(&value.swap_le()))?;
+    }
+    Ok(())
+}
+
+fn invalid_width(width: u8) -> Error {
+    Error::corrupt(format!("invalid packed value width {}", width))
+}
+
+/// How a decimal column chunk stores its values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecimalEncoding {
+    /// One packed offset per row
+    FrameOfReference,
+    /// One packed offset per run of equal values
+    RunLength,
+    /// One value shared by every row
+    Constant,
+}
+
+impl DecimalEncoding {
+    fn tag(self) -> u8 {
+        match self {
+            DecimalEncoding::FrameOfReference => 0,
+            DecimalEncoding::RunLength => 1,
+            DecimalEncoding::Constant => 2,
+        }
+    }
+
+    fn read<R: Read>(reader: &mut R) -> Result<Self> {
+        match io::read_u8(reader)? {
+            0 => Ok(DecimalEncoding::FrameOfReference),
+            1 => Ok(DecimalEncoding::RunLength),
+            2 => Ok(DecimalEncoding::Constant),
+            tag => Err(Error::corrupt(format!("invalid decimal encoding {}", tag))),
+        }
+    }
+}
+
+/// A column of fixed-point decimals. Values are frame-of-reference encoded:
+/// each is `reference + packed[i]` units of `10^-scale`. The scale is not
+/// stored with the values; it is part of the column's schema. Repetitive
+/// columns are run-length encoded, with packed value `i` covering
+/// `run_lengths[i]` rows; `run_lengths` is empty when every row has its own
+/// packed value, and a single run is a constant column.
+///
+/// On disk a chunk is the encoding (u8) followed by
+/// - for frame of reference: the packed width (u8), the reference (i64),
+///   padding that aligns the offsets to their width within the file, and
+///   then the packed offsets;
+/// - for run length: the width, the reference, the run count (u32), padding,
+///   the packed offsets of the runs, padding to 4 and the run lengths (u32);
+/// - for constant: the value (i64), padding to 4 and the row count (u32).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecimalColumn {
+    pub scale: u8,
+    pub reference: i64,
+    pub packed: PackedValues,
+    pub run_lengths: Vec<u32>,
+}
+
+impl DecimalColumn {
+    /// Encodes `values` with whichever encoding is smallest.
+    pub fn new(scale: u8, values: &[i64]) -> Self {
+        let column = DecimalColumn::with_encoding(scale, values, DecimalEncoding::RunLength);
+        let width = column.packed.width() as usize;
+        if column.run_lengths.len() <= 1
+            || column.run_lengths.len() * (width + 4) < values.len() * width
+        {
+            column
+        } else {
+            DecimalColumn::with_encoding(scale, values, DecimalEncoding::FrameOfReference)
+        }
+    }
+
+    /// Encodes `values` with `encoding`. Constant columns must not have more
+    /// than one distinct value.
+    pub fn with_encoding(scale: u8, values: &[i64], encoding: DecimalEncoding) -> Self {
+        let reference = values.iter().copied().min().unwrap_or(0);
+        let max = values.iter().copied().max().unwrap_or(0);
+        let max_offset = max.abs_diff(reference);
+        if encoding == DecimalEncoding::FrameOfReference {
+            let offsets = values.iter().map(|x| x.abs_diff(reference));
+            return DecimalColumn {
+                scale,
+                reference,
+                packed: PackedValues::pack(offsets, max_offset),
+                run_lengths: Vec::new(),
+            };
+        }
+        let mut run_values = Vec::new();
+        let mut run_lengths: Vec<u32> = Vec::new();
+        for value in values {
+            match run_lengths.last_mut() {
+                Some(run_length) if run_values.last() == Some(value) => *run_length += 1,
+                _ => {
+                    run_values.push(*value);
+                    run_lengths.push(1);
+                }
+            }
+        }
+        assert!(
+            encoding != DecimalEncoding::Constant || run_lengths.len() <= 1,
+            "Constant decimal columns need equal values"
+        );
+        let offsets = run_values.iter().map(|x| x.abs_diff(reference));
+        DecimalColumn {
+            scale,
+            reference,
+            packed: PackedValues::pack(offsets, max_offset),
+            run_lengths,
+        }
+    }
+
+    pub fn from_f64s(
+        values: impl Iterator<Item = f64>,
+        scale: u8,
+    ) -> std::result::Result<Self, DecimalError> {
+        let values = values
+            .map(|x| to_unscaled(x, scale))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(DecimalColumn::new(scale, &values))
+    }
+
+    pub fn encoding(&self) -> DecimalEncoding {
+        self.view().encoding()
+    }
+
+    pub fn len(&self) -> usize {
+        self.view().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn view(&self) -> DecimalColumnRef<'_> {
+        DecimalColumnRef {
+            scale: self.scale,
+            reference: self.reference,
+            packed: self.packed.as_slice(),
+            run_lengths: &self.run_lengths,
+        }
+    }
+
+    /// The unscaled value at `index`
+    pub fn value(&self, index: usize) -> i64 {
+        self.view().value(index)
+    }
+
+    pub fn to_i64s(&self) -> Vec<i64> {
+        let mut values = Vec::with_capacity(self.len());
+        self.decode_into(&mut values);
+        values
+    }
+
+    /// Replaces the contents of `values` with the unscaled values.
+    pub fn decode_into(&self, values: &mut Vec<i64>) {
+        self.view().decode_into(values)
+    }
+
+    pub fn to_f64s(&self) -> Vec<f64> {
+        self.to_i64s()
+            .into_iter()
+            .map(|x| from_unscaled(x, self.scale))
+            .collect()
+    }
+
+    /// Sum of the unscaled values in `start..start + count`
+    pub fn sum(&self, start: usize, count: usize) -> i128 {
+        self.view().sum(start, count)
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut TrackedWriter<W>) -> Result<()> {
+        let encoding = self.encoding();
+        io::write_u8(writer, encoding.tag())?;
+        match encoding {
+            DecimalEncoding::FrameOfReference | DecimalEncoding::RunLength => {
+                io::write_u8(writer, self.packed.width())?;
+                io::write_u64(writer, self.reference as u64)?;
+                if encoding == DecimalEncoding::RunLength {
+                    io::write_u32(writer, self.run_lengths.len() as u32)?;
+                }
+                io::write_padding(writer, self.packed.width() as usize)?;
+                self.packed.write(writer)?;
+                if encoding == DecimalEncoding::RunLength {
+                    io::write_padding(writer, 4)?;
+                    write_le(writer, &self.run_lengths)?;
+                }
+                Ok(())
+            }
+            DecimalEncoding::Constant => {
+                io::write_u64(writer, self.value(0) as u64)?;
+                io::write_padding(writer, 4)?;
+                io::write_u32(writer, self.run_lengths[0])
+            }
+        }
+    }
+
+    pub fn read<R: Read>(reader: &mut R, scale: u8, row_count: usize) -> Result<Self> {
+        let mut column = DecimalColumn::default();
+        column.read_into(reader, scale, row_count)?;
+        Ok(column)
+    }
+
+    /// Like `read`, but decodes into this column's existing buffers.
+    pub fn read_into<R: Read>(
+        &mut self,
+        reader: &mut R,
+        scale: u8,
+        row_count: usize,
+    ) -> Result<()> {
+        self.scale = scale;
+        self.run_lengths.clear();
+        match DecimalEncoding::read(reader)? {
+            DecimalEncoding::FrameOfReference => {
+                let width = read_width(reader)?;
+                self.reference = io::read_u64(reader)? as i64;
+                io::read_padding(reader)?;
+                self.packed.read_into(reader, width, row_count)
+            }
+            DecimalEncoding::RunLength => {
+                let width = read_width(reader)?;
+                self.reference = io::read_u64(reader)? as i64;
+                let run_count = read_run_count(reader, row_count)?;
+                io::read_padding(reader)?;
+                self.packed.read_into(reader, width, run_count)?;
+                io::read_padding(reader)?;
+                read_le_into(reader, &mut self.run_lengths, run_count)?;
+                check_run_lengths(&self.run_lengths, row_count)
+            }
+            DecimalEncoding::Constant => {
+                self.reference = io::read_u64(reader)? as i64;
+                io::read_padding(reader)?;
+                let rows = io::read_u32(reader)?;
+                match &mut self.packed {
+                    PackedValues::U8(values) => {
+                        values.clear();
+                        values.push(0);
+                    }
+                    packed => *packed = PackedValues::U8(vec![0]),
+                }
+                self.run_lengths.push(rows);
+                check_run_lengths(&self.run_lengths, row_count)
+            }
+        }
+    }
+
+    /// Reads a column of headerless legacy files: `row_count` bare u16
+    /// values with no width or reference.
+    pub fn read_legacy_into<R: Read>(
+        &mut self,
+        reader: &mut R,
+        scale: u8,
+        row_count: usize,
+    ) -> Result<()> {
+        self.reference = 0;
+        self.scale = scale;
+        self.run_lengths.clear();
+        self.packed.read_into(reader, 2, row_count)
+    }
+
+    /// Moves the reader past a decimal column chunk without decoding it.
+    pub fn skip<R: Read + Seek>(
+        reader: &mut std::io::BufReader<R>,
+        row_count: usize,
+    ) -> Result<()> {
+        match DecimalEncoding::read(reader)? {
+            DecimalEncoding::FrameOfReference => {
+                let width = read_width(reader)?;
+                reader.seek_relative(8)?;
+                io::read_padding(reader)?;
+                reader.seek_relative(width as i64 * row_count as i64)?;
+            }
+            DecimalEncoding::RunLength => {
+                let width = read_width(reader)?;
+                reader.seek_relative(8)?;
+                let run_count = read_run_count(reader, row_count)?;
+                io::read_padding(reader)?;
+                reader.seek_relative(width as i64 * run_count as i64)?;
+                io::read_padding(reader)?;
+                reader.seek_relative(4 * run_count as i64)?;
+            }
+            DecimalEncoding::Constant => {
+                reader.seek_relative(8)?;
+                io::read_padding(reader)?;
+                reader.seek_relative(4)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_width<R: Read>(reader: &mut R) -> Result<u8> {
+    let width = io::read_u8(reader)?;
+    if ![1, 2, 4, 8].contains(&width) {
+        return Err(invalid_width(width));
+    }
+    Ok(width)
+}
+
+fn read_run_count<R: Read>(reader: &mut R, row_count: usize) -> Result<usize> {
+    let run_count = io::read_u32(reader)? as usize;
+    if run_count > row_count {
+        return Err(Error::corrupt(format!(
+            "decimal column has {} runs for {} rows",
+            run_count, row_count
+        )));
+    }
+    Ok(run_count)
+}
+
+fn check_run_lengths(run_lengths: &[u32], row_count: usize) -> Result<()> {
+    let rows: u64 = run_lengths.iter().map(|x| *x as u64).sum();
+    if rows != row_count as u64 {
+        return Err(Error::corrupt(format!(
+            "decimal column runs cover {} rows, expected {}",
+            rows, row_count
+        )));
+    }
+    Ok(())
+}
+
+/// A decimal column borrowed from a `DecimalColumn` or parsed in place from
+/// a mapped column chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecimalColumnRef<'a> {
+    pub scale: u8,
+    pub reference: i64,
+    pub packed: PackedSlice<'a>,
+    pub run_lengths: &'a [u32],
+}
+
+impl<'a> DecimalColumnRef<'a> {
+    /// Views a column chunk without copying its values. `chunk` must start
+    /// at an offset of the file that is a multiple of 8.
+    #[cfg(target_endian = "little")]
+    pub fn parse(chunk: &'a [u8], scale: u8, row_count: usize) -> Result<Self> {
+        let mut rest = chunk;
+        let column = match DecimalEncoding::read(&mut rest)? {
+            DecimalEncoding::FrameOfReference => {
+                let width = read_width(&mut rest)?;
+                let reference = io::read_u64(&mut rest)? as i64;
+                io::read_padding(&mut rest)?;
+                DecimalColumnRef {
+                    scale,
+                    reference,
+                    packed: PackedSlice::from_bytes(rest, width, row_count)?,
+                    run_lengths: &[],
+                }
+            }
+            DecimalEncoding::RunLength => {
+                let width = read_width(&mut rest)?;
+                let reference = io::read_u64(&mut rest)? as i64;
+                let run_count = read_run_count(&mut rest, row_count)?;
+                io::read_padding(&mut rest)?;
+                let packed = PackedSlice::from_bytes(rest, width, run_count)?;
+                rest = &rest[width as usize * run_count..];
+                io::read_padding(&mut rest)?;
+                DecimalColumnRef {
+                    scale,
+                    reference,
+                    packed,
+                    run_lengths: parse_run_lengths(rest, run_count)?,
+                }
+            }
+            DecimalEncoding::Constant => {
+                let reference = io::read_u64(&mut rest)? as i64;
+                io::read_padding(&mut rest)?;
+                DecimalColumnRef {
+                    scale,
+                    reference,
+                    packed: PackedSlice::U8(&[0]),
+                    run_lengths: parse_run_lengths(rest, 1)?,
+                }
+            }
+        };
+        if column.encoding() != DecimalEncoding::FrameOfReference {
+            check_run_lengths(column.run_lengths, row_count)?;
+        }
+        Ok(column)
+    }
+
+    pub fn encoding(&self) -> DecimalEncoding {
+        match self.run_lengths.len() {
+            0 => DecimalEncoding::FrameOfReference,
+            1 => DecimalEncoding::Constant,
+            _ => DecimalEncoding::RunLength,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self.encoding() {
+            DecimalEncoding::FrameOfReference => self.packed.len(),
+            _ => self.run_lengths.iter().map(|x| *x as usize).sum(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The unscaled value at `index`
+    pub fn value(&self, index: usize) -> i64 {
+        if self.run_lengths.is_empty() {
+            return self.packed_value(index);
+        }
+        self.cursor()
+            .runs_in(index, index + 1)
+            .next()
+            .unwrap_or_else(|| panic!("Row {} is out of range", index))
+            .0
+    }
+
+    fn packed_value(&self, index: usize) -> i64 {
+        self.reference.wrapping_add(self.packed.get(index) as i64)
+    }
+
+    /// The value of every run with the rows it covers. Each row of a frame
+    /// of reference column is a run of its own.
+    pub fn runs(&self) -> impl Iterator<Item = (i64, Range<usize>)> + 'a {
+        let column = *self;
+        let mut start = 0;
+        (0..self.packed.len()).map(move |i| {
+            let run_length = column.run_lengths.get(i).map_or(1, |x| *x as usize);
+            start += run_length;
+            (column.packed_value(i), start - run_length..start)
+        })
+    }
+
+    /// A cursor for summing ranges of the column in increasing order of row
+    pub fn cursor(&self) -> DecimalCursor<'a> {
+        DecimalCursor {
+            column: *self,
+            run: 0,
+            run_start: 0,
+        }
+    }
+
+    /// Replaces the contents of `values` with the unscaled values.
+    pub fn decode_into(&self, values: &mut Vec<i64>) {
+        values.clear();
+        if self.run_lengths.is_empty() {
+            values.extend((0..self.len()).map(|i| self.packed_value(i)));
+            return;
+        }
+        for (value, rows) in self.runs() {
+            values.resize(rows.end, value);
+        }
+    }
+
+    /// Sum of the unscaled values in `start..start + count`. It is wider
+    /// than the values, so it cannot overflow.
+    pub fn sum(&self, start: usize, count: usize) -> i128 {
+        self.cursor().sum(start, count)
+    }
+
+    /// Sum of the unscaled values from `start` on whose flag in `selected`
+    /// is set
+    pub fn masked_sum(&self, start: usize, selected: &[bool]) -> i128 {
+        self.cursor().masked_sum(start, selected)
+    }
+}
+
+/// Sums ranges of a decimal column that never start before the previous
+/// range. A run length column is then walked once, rather than from its
+/// first run on every call.
+#[derive(Debug, Clone)]
+pub struct DecimalCursor<'a> {
+    column: DecimalColumnRef<'a>,
+    // The earliest run a later range can overlap and the row it starts at
+    run: usize,
+    run_start: usize,
+}
+
+impl<'a> DecimalCursor<'a> {
+    // The runs overlapping `start..end`, clipped to it
+    fn runs_in(&mut self, start: usize, end: usize) -> impl Iterator<Item = (i64, Range<usize>)> + 'a {
+        debug_assert!(start >= self.run_start, "Row {} is before the cursor", start);
+        let column = self.column;
+        while self.run < column.run_lengths.len()
+            && self.run_start + column.run_lengths[self.run] as usize <= start
+        {
+            self.run_start += column.run_lengths[self.run] as usize;
+            self.run += 1;
+        }
+        let mut run_start = self.run_start;
+        (self.run..column.run_lengths.len())
+            .map(move |i| {
+                let run_length = column.run_lengths[i] as usize;
+                run_start += run_length;
+                (column.packed_value(i), run_start - run_length..run_start)
+            })
+            .take_while(move |(_, rows)| rows.start < end)
+            .map(move |(value, rows)| (value, rows.start.max(start)..rows.end.min(end)))
+    }
+
+    /// Sum of the unscaled values in `start..start + count`
+    pub fn sum(&mut self, start: usize, count: usize) -> i128 {
+        let column = self.column;
+        if !column.run_lengths.is_empty() {
+            return self
+                .runs_in(start, start + count)
+                .map(|(value, rows)| value as i128 * rows.len() as i128)
+                .sum();
+        }
+        column.reference as i128 * count as i128 + column.packed.sum(start, count) as i128
+    }
+
+    /// Sum of the unscaled values from `start` on whose flag in `selected`
+    /// is set
+    pub fn masked_sum(&mut self, start: usize, selected: &[bool]) -> i128 {
+        let column = self.column;
+        if !column.run_lengths.is_empty() {
+            return self
+                .runs_in(start, start + selected.len())
+                .map(|(value, rows)| {
+                    let selected = &selected[rows.start - start..rows.end - start];
+                    value as i128 * kernels::count_selected(selected) as i128
+                })
+                .sum();
+        }
+        let count = kernels::count_selected(selected);
+        column.reference as i128 * count as i128 + column.packed.masked_sum(start, selected) as i128
+    }
+}
+
+#[cfg(target_endian = "little")]
+fn parse_run_lengths(bytes: &[u8], run_count: usize) -> Result<&[u32]> {
+    let bytes = bytes
+        .get(..4 * run_count)
+        .ok_or_else(|| Error::corrupt("decimal column run lengths are truncated"))?;
+    cast_aligned(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_scale_factor() {
+        assert_eq!(scale_factor(0), 1);
+        assert_eq!(scale_factor(2), 100);
+        assert_eq!(scale_factor(18), 1_000_000_000_000_000_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside the u16 fixed-point range")]
+    fn test_compress_f64_rejects_overflow() {
+        compress_f64(655.36);
+    }
+
+    #[test]
+    fn test_to_unscaled_rejects_inexact_values() {
+        assert_eq!(to_unscaled(0.07, 2), Ok(7));
+        assert_eq!(to_unscaled(104949.5, 2), Ok(10494950));
+        assert_eq!(
+            to_unscaled(2.345, 2),
+            Err(DecimalError::NotRepresentable {
+                value: 2.345,
+                scale: 2
+            })
+        );
+        assert!(to_unscaled(f64::NAN, 2).is_err());
+        assert!(to_unscaled(1e300, 2).is_err());
+    }
+
+    #[test]
+    fn test_decimal_display_is_exact() {
+        assert_eq!(
+            Decimal::new(123456789012345678901, 6).to_string(),
+            "123456789012345.678901"
+        );
+        assert_eq!(Decimal::new(-5, 2).to_string(), "-0.05");
+        assert_eq!(Decimal::new(42, 0).to_string(), "42");
+    }
+
+    #[test]
+    fn test_read_into_reuses_column() {
+        let wide = DecimalColumn::new(2, &[10494950, 65536, 0, -1225]);
+        let narrow = DecimalColumn::new(2, &[5, 7]);
+        let mut writer = TrackedWriter::new(Vec::new());
+        wide.write(&mut writer).unwrap();
+        narrow.write(&mut writer).unwrap();
+        narrow.write(&mut writer).unwrap();
+        let buffer = writer.into_inner().into_inner().unwrap();
+        let mut reader = buffer.as_slice();
+        let mut column = DecimalColumn::default();
+
+        column.read_into(&mut reader, 2, 4).unwrap();
+        assert_eq!(column, wide);
+        column.read_into(&mut reader, 2, 2).unwrap();
+        assert_eq!(column, narrow);
+        column.read_into(&mut reader, 2, 2).unwrap();
+        assert_eq!(column, narrow);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_decimal_column_beyond_u16() {
+        let values = vec![104949.5, 655.36, 0.0, -12.25];
+        let column = DecimalColumn::from_f64s(values.iter().copied(), 2).unwrap();
+
+        assert_eq!(column.to_i64s(), vec![10494950, 65536, 0, -1225]);
+        assert_eq!(column.to_f64s(), values);
+    }
+
+    #[test]
+    fn test_decimal_column_chooses_width() {
+        let width = |values: &[i64]| DecimalColumn::new(2, values).packed.width();
+
+        assert_eq!(width(&[]), 1);
+        assert_eq!(width(&[1_000_000, 1_000_255]), 1);
+        assert_eq!(width(&[100, 5000]), 2);
+        assert_eq!(width(&[-1, 10494950]), 4);
+        assert_eq!(width(&[i64::MIN, i64::MAX]), 8);
+    }
+
+    #[test]
+    fn test_decimal_column_chooses_encoding() {
+        let encoding = |values: &[i64]| DecimalColumn::new(2, values).encoding();
+        let runs: Vec<i64> = (0..100).map(|x| x / 10 * 1000).collect();
+
+        assert_eq!(encoding(&[]), DecimalEncoding::FrameOfReference);
+        assert_eq!(encoding(&[5; 100]), DecimalEncoding::Constant);
+        assert_eq!(encoding(&runs), DecimalEncoding::RunLength);
+        assert_eq!(
+            encoding(&[5, 5, 6, 6, 7]),
+            DecimalEncoding::FrameOfReference
+        );
+        assert_eq!(DecimalColumn::new(2, &runs).run_lengths, vec![10; 10]);
+    }
+
+    #[test]
+    fn test_read_rejects_runs_not_covering_rows() {
+        for values in [vec![5; 10], [vec![1; 10], vec![2; 10]].concat()] {
+            let column = DecimalColumn::new(2, &values);
+            let mut writer = TrackedWriter::new(Vec::new());
+            column.write(&mut writer).unwrap();
+            let buffer = writer.into_inner().into_inner().unwrap();
+
+            let result = DecimalColumn::read(&mut buffer.as_slice(), 2, values.len() - 1);
+
+            assert!(matches!(result, Err(Error::Corrupt(_))), "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn test_decimal_column_sum() {
+        let column = DecimalColumn::new(2, &[-5, 10, 20, 30]);
+
+        assert_eq!(column.sum(0, 4), 55);
+        assert_eq!(column.sum(1, 2), 30);
+    }
+
+    #[test]
+    fn test_decimal_column_sum_is_wider_than_the_values() {
+        let values = [i64::MAX - 1, i64::MAX, i64::MAX];
+        let expected = 3 * i64::MAX as i128 - 1;
+        for encoding in [DecimalEncoding::FrameOfReference, DecimalEncoding::RunLength] {
+            let column = DecimalColumn::with_encoding(0, &values, encoding);
+
+            assert_eq!(column.sum(0, 3), expected);
+            assert_eq!(column.view().masked_sum(0, &[true; 3]), expected);
+        }
+    }
+
+    #[test]
+    fn test_decimal_column_masked_sum() {
+        for values in [vec![-5, 10, 20, 30], vec![-5, 10, 20, 60_000]] {
+            let column = DecimalColumn::new(2, &values);
+            let view = column.view();
+
+            assert_eq!(
+                view.masked_sum(0, &[true, false, true, true]),
+                (values[0] + 20 + values[3]) as i128
+            );
+            assert_eq!(view.masked_sum(1, &[false, true]), 20);
+            assert_eq!(view.masked_sum(1, &[false, false, false]), 0);
+        }
+    }
+
+    #[test]
+    fn test_on_disk_layout_is_little_endian() {
+        let column = DecimalColumn::with_encoding(2, &[300, 300, 1000], DecimalEncoding::RunLength);
+        let mut writer = TrackedWriter::new(Vec::new());
+        column.write(&mut writer).unwrap();
+        let written = writer.into_inner().into_inner().unwrap();
+
+        let mut expected = vec![1, 2];
+        expected.extend_from_slice(&300u64.to_le_bytes());
+        expected.extend_from_slice(&2u32.to_le_bytes());
+        // Padding puts the offsets at 16 and the run lengths at 24
+        expected.extend_from_slice(&[1, 0]);
+        expected.extend_from_slice(&0u16.to_le_bytes());
+        expected.extend_from_slice(&700u16.to_le_bytes());
+        expected.extend_from_slice(&[3, 0, 0, 0]);
+        expected.extend_from_slice(&2u32.to_le_bytes());
+        expected.extend_from_slice(&1u32.to_le_bytes());
+        assert_eq!(written, expected);
+        assert_eq!(
+            DecimalColumn::read(&mut written.as_slice(), 2, 3).unwrap(),
+            column
+        );
+    }
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn test_parse_rejects_misaligned_chunk() {
+        let column = DecimalColumn::new(2, &[100, 5000, 7]);
+        let mut writer = TrackedWriter::new(Vec::new());
+        column.write(&mut writer).unwrap();
+        let buffer = writer.into_inner().into_inner().unwrap();
+        let mut aligned = vec![0u64; 4];
+        bytemuck::cast_slice_mut::<u64, u8>(&mut aligned)[1..1 + buffer.len()]
+            .copy_from_slice(&buffer);
+
+        // The padding was computed for a chunk starting at offset 0
+        let result = DecimalColumnRef::parse(&bytemuck::cast_slice(&aligned)[1..], 2, 3);
+
+        assert!(matches!(result, Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn test_read_rejects_invalid_width() {
+        let mut buffer = vec![0u8, 3];
+        buffer.extend_from_slice(&0u64.to_le_bytes());
+
+        let result = DecimalColumn::read(&mut buffer.as_slice(), 2, 0);
+
+        assert!(matches!(result, Err(Error::Corrupt(_))));
+    }
+
+    proptest! {
+        #[test]
+        fn test_write_read_decimal_column(
+            values in proptest::collection::vec(any::<i64>(), 0..100),
+            scale in 0u8..=6,
+        ) {
+            let column = DecimalColumn::new(scale, &values);
+            let mut writer = TrackedWriter::new(Vec::new());
+            column.write(&mut writer).unwrap();
+            let buffer = writer.into_inner().into_inner().unwrap();
+
+            let result = DecimalColumn::read(&mut buffer.as_slice(), scale, values.len()).unwrap();
+
+            prop_assert_eq!(&result, &column);
+            prop_assert_eq!(result.to_i64s(), values.clone());
+            #[cfg(target_endian = "little")]
+            {
+                // Copy into u64s so the chunk starts 8-byte aligned, as in a file
+                let mut aligned = vec![0u64; buffer.len().div_ceil(8)];
+                bytemuck::cast_slice_mut(&mut aligned)[..buffer.len()].copy_from_slice(&buffer);
+                let aligned = &bytemuck::cast_slice::<u64, u8>(&aligned)[..buffer.len()];
+                let view = DecimalColumnRef::parse(aligned, scale, values.len()).unwrap();
+                prop_assert_eq!(view, column.view());
+            }
+        }
+
+        #[test]
+        fn test_run_length_encodings_match_frame_of_reference(
+            runs in proptest::collection::vec((-1000i64..1000, 1usize..20), 0..20),
+            start in 0usize..100,
+            selected in proptest::collection::vec(any::<bool>(), 0..100),
+        ) {
+            let values: Vec<i64> = runs
+                .iter()
+                .flat_map(|(value, length)| std::iter::repeat_n(*value, *length))
+                .collect();
+            let plain = DecimalColumn::with_encoding(2, &values, DecimalEncoding::FrameOfReference);
+            let encoded = DecimalColumn::with_encoding(2, &values, DecimalEncoding::RunLength);
+            let start = start.min(values.len());
+            let selected = &selected[..selected.len().min(values.len() - start)];
+            let mut writer = TrackedWriter::new(Vec::new());
+            encoded.write(&mut writer).unwrap();
+            plain.write(&mut writer).unwrap();
+            let buffer = writer.into_inner().into_inner().unwrap();
+            let mut reader = std::io::BufReader::new(std::io::Cursor::new(&buffer));
+
+            DecimalColumn::skip(&mut reader, values.len()).unwrap();
+            let after_skip = DecimalColumn::read(&mut reader, 2, values.len()).unwrap();
+
+            prop_assert_eq!(&after_skip, &plain);
+            #[cfg(target_endian = "little")]
+            {
+                let mut aligned = vec![0u64; buffer.len().div_ceil(8)];
+                bytemuck::cast_slice_mut(&mut aligned)[..buffer.len()].copy_from_slice(&buffer);
+                let aligned = &bytemuck::cast_slice::<u64, u8>(&aligned)[..buffer.len()];
+                let view = DecimalColumnRef::parse(aligned, 2, values.len()).unwrap();
+                prop_assert_eq!(view, encoded.view());
+            }
+            prop_assert_eq!(encoded.len(), values.len());
+            prop_assert_eq!(encoded.to_i64s(), values.clone());
+            prop_assert_eq!(
+                encoded.sum(start, values.len() - start),
+                plain.sum(start, values.len() - start)
+            );
+            prop_assert_eq!(
+                encoded.view().masked_sum(start, selected),
+                plain.view().masked_sum(start, selected)
+            );
+            for (index, value) in values.iter().enumerate() {
+                prop_assert_eq!(encoded.value(index), *value);
+            }
+        }
+
+        #[test]
+        fn test_cursor_sums_consecutive_ranges(
+            runs in proptest::collection::vec((-1000i64..1000, 1usize..4), 0..200),
+            lengths in proptest::collection::vec(0usize..10, 1..50),
+        ) {
+            let values: Vec<i64> = runs
+                .iter()
+                .flat_map(|(value, length)| std::iter::repeat_n(*value, *length))
+                .collect();
+            let column = DecimalColumn::with_encoding(2, &values, DecimalEncoding::RunLength);
+            let mut cursor = column.view().cursor();
+            let mut masked_cursor = column.view().cursor();
+
+            let mut start = 0;
+            for length in lengths.iter().cycle().take(values.len()) {
+                let count = (*length).min(values.len() - start);
+                let selected: Vec<bool> = (start..start + count).map(|x| x % 3 != 0).collect();
+                let expected: i128 = values[start..start + count].iter().map(|x| *x as i128).sum();
+                let expected_masked: i128 = values[start..start + count]
+                    .iter()
+                    .zip(&selected)
+                    .filter(|(_, selected)| **selected)
+                    .map(|(x, _)| *x as i128)
+                    .sum();
+
+                prop_assert_eq!(cursor.sum(start, count), expected);
+                prop_assert_eq!(masked_cursor.masked_sum(start, &selected), expected_masked);
+                start += count;
+            }
+        }
+
+        #[test]
+        fn test_decimal_f64_round_trip(
+            values in proptest::collection::vec(-1_000_000_000_000i64..1_000_000_000_000, 0..100),
+            scale in 0u8..=4,
+        ) {
+            let floats = DecimalColumn::new(scale, &values).to_f64s();
+
+            let result = DecimalColumn::from_f64s(floats.into_iter(), scale).unwrap();
+
+            prop_assert_eq!(result.to_i64s(), values);
+        }
+    }
+}
@@ -0,0 +1,614 @@
+use std::io::{Read, Seek};
+
+use ahash::AHashMap;
+
+use crate::column_file::{required_column_index, ColumnFileReader};
+use crate::error::{Error, Result};
+use crate::predicate::Predicate;
+use crate::scan::{ColumnData, ProjectedRowGroup};
+use crate::schema::LogicalType;
+use crate::selection::Selection;
+use crate::string_column::StringColumnReader;
+use crate::varchar_column::VarcharColumn;
+
+/// Group id of the rows that are not selected
+pub const NO_GROUP: u32 = u32::MAX;
+
+/// One column of a group key. Char columns give their byte, decimal and date
+/// columns their unscaled value and varchar columns their string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum GroupValue {
+    Byte(u8),
+    Int(i64),
+    Str(String),
+}
+
+enum GroupIndex {
+    /// The group id of every byte combination, or `NO_GROUP` until one is
+    /// seen. Two keys are laid out like `get_state_index`.
+    Dense(Vec<u32>),
+    /// Keyed on the key values as `i64`s, with varchar values replaced by
+    /// their id in `GroupTable::strings`
+    Hash(AHashMap<Vec<i64>, u32>),
+}
+
+/// Numbers the distinct keys of the rows it is given, in order of first
+/// appearance. Keys of one or two char columns are looked up in a dense
+/// table of 256 or 256 * 256 slots; any other combination goes through a
+/// hash table.
+pub struct GroupTable {
+    key_types: Vec<LogicalType>,
+    index: GroupIndex,
+    keys: Vec<Vec<GroupValue>>,
+    key: Vec<i64>,
+    // Decoded key columns, with varchar columns as string ids
+    decoded: Vec<Vec<i64>>,
+    // Id of every varchar key value seen so far
+    strings: AHashMap<String, i64>,
+}
+
+impl GroupTable {
+    pub fn new(key_types: &[LogicalType]) -> Self {
+        let dense =
+            matches!(key_types.len(), 1 | 2) && key_types.iter().all(|x| *x == LogicalType::Char);
+        let index = if dense {
+            GroupIndex::Dense(vec![NO_GROUP; 1 << (8 * key_types.len())])
+        } else {
+            GroupIndex::Hash(AHashMap::new())
+        };
+        GroupTable {
+            key_types: key_types.to_vec(),
+            index,
+            keys: Vec::new(),
+            key: Vec::new(),
+            decoded: vec![Vec::new(); key_types.len()],
+            strings: AHashMap::new(),
+        }
+    }
+
+    pub fn is_dense(&self) -> bool {
+        matches!(self.index, GroupIndex::Dense(_))
+    }
+
+    /// Number of groups seen so far
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// The keys, indexed by group id
+    pub fn keys(&self) -> &[Vec<GroupValue>] {
+        &self.keys
+    }
+
+    /// The id of the group with `key`, adding it if it is new.
+    pub fn group_id(&mut self, key: &[GroupValue]) -> u32 {
+        assert_eq!(key.len(), self.key_types.len());
+        match &mut self.index {
+            GroupIndex::Dense(slots) => {
+                let slot = key.iter().fold(0, |slot, value| match value {
+                    GroupValue::Byte(byte) => slot * 256 + *byte as usize,
+                    _ => panic!("Dense group keys are bytes, got {:?}", value),
+                });
+                if slots[slot] == NO_GROUP {
+                    slots[slot] = self.keys.len() as u32;
+                    self.keys.push(key.to_vec());
+                }
+                slots[slot]
+            }
+            GroupIndex::Hash(map) => {
+                let mut values = std::mem::take(&mut self.key);
+                values.clear();
+                values.extend(key.iter().map(|value| match value {
+                    GroupValue::Byte(byte) => *byte as i64,
+                    GroupValue::Int(value) => *value,
+                    GroupValue::Str(value) => string_id(&mut self.strings, value),
+                }));
+                let id = hash_group_id(map, &mut self.keys, &values, || key.to_vec());
+                self.key = values;
+                id
+            }
+        }
+    }
+
+    /// Sets `group_ids` to the group of every row of `row_group`, keyed on
+    /// the `key_columns`, which must be projected. Rows not flagged in
+    /// `selected` get `NO_GROUP` and do not create groups.
+    pub fn assign(
+        &mut self,
+        row_group: &ProjectedRowGroup,
+        key_columns: &[usize],
+        selected: Option<&[bool]>,
+        group_ids: &mut Vec<u32>,
+    ) -> Result<()> {
+        assert_eq!(key_columns.len(), self.key_types.len());
+        let row_count = row_group.row_count as usize;
+        if let Some(selected) = selected {
+            assert_eq!(selected.len(), row_count);
+        }
+        let skipped = |row: usize| selected.is_some_and(|x| !x[row]);
+        group_ids.clear();
+
+        if let GroupIndex::Dense(slots) = &mut self.index {
+            // Build the slot of every row one key byte at a time, then swap
+            // the slots for group ids
+            group_ids.resize(row_count, 0);
+            for &column in key_columns {
+                for_each_run(row_group.string_column(column), row_count, |value, run| {
+                    for slot in &mut group_ids[run] {
+                        *slot = *slot * 256 + value as u32;
+                    }
+                })?;
+            }
+            for (row, slot) in group_ids.iter_mut().enumerate() {
+                if skipped(row) {
+                    *slot = NO_GROUP;
+                    continue;
+                }
+                let id = &mut slots[*slot as usize];
+                if *id == NO_GROUP {
+                    *id = self.keys.len() as u32;
+                    self.keys.push(dense_key(*slot, key_columns.len()));
+                }
+                *slot = *id;
+            }
+            return Ok(());
+        }
+
+        for (&column, decoded) in key_columns.iter().zip(&mut self.decoded) {
+            match &row_group.columns[column] {
+                Some(ColumnData::String(column)) => {
+                    decoded.clear();
+                    for_each_run(column, row_count, |value, run| {
+                        decoded.resize(run.end, value as i64)
+                    })?;
+                }
+                Some(ColumnData::Decimal(column)) | Some(ColumnData::Date(column)) => {
+                    column.view().decode_into(decoded)
+                }
+                Some(ColumnData::Varchar(column)) => {
+                    decoded.clear();
+                    match column {
+                        // Look up each dictionary entry once rather than
+                        // every row
+                        VarcharColumn::Dictionary { dictionary, codes } => {
+                            let ids: Vec<i64> = dictionary
+                                .iter()
+                                .map(|x| string_id(&mut self.strings, x))
+                                .collect();
+                            decoded.extend(codes.iter().map(|x| ids[*x as usize]));
+                        }
+                        VarcharColumn::Plain(values) => {
+                            decoded.extend(values.iter().map(|x| string_id(&mut self.strings, x)))
+                        }
+                    }
+                }
+                None => panic!("Column {} was not projected", column),
+            }
+        }
+        let GroupIndex::Hash(map) = &mut self.index else {
+            unreachable!("dense tables return above");
+        };
+        let mut values = std::mem::take(&mut self.key);
+        for row in 0..row_count {
+            if skipped(row) {
+                group_ids.push(NO_GROUP);
+                continue;
+            }
+            values.clear();
+            values.extend(self.decoded.iter().map(|x| x[row]));
+            // Only a new group needs its key values
+            let key = || {
+                key_columns
+                    .iter()
+                    .zip(&self.key_types)
+                    .zip(&values)
+                    .map(|((&column, key_type), value)| match key_type {
+                        LogicalType::Char => GroupValue::Byte(*value as u8),
+                        LogicalType::Decimal { .. } | LogicalType::Date => GroupValue::Int(*value),
+                        LogicalType::Varchar => {
+                            GroupValue::Str(row_group.varchar_column(column).get(row).to_string())
+                        }
+                    })
+                    .collect()
+            };
+            group_ids.push(hash_group_id(map, &mut self.keys, &values, key));
+        }
+        self.key = values;
+        Ok(())
+    }
+}
+
+// The id of the varchar key value `value`, allocating only for new values
+fn string_id(strings: &mut AHashMap<String, i64>, value: &str) -> i64 {
+    if let Some(id) = strings.get(value) {
+        return *id;
+    }
+    let id = strings.len() as i64;
+    strings.insert(value.to_string(), id);
+    id
+}
+
+// The id of the group with key `values`, adding the group with the key
+// built by `key` if it is new
+fn hash_group_id(
+    map: &mut AHashMap<Vec<i64>, u32>,
+    keys: &mut Vec<Vec<GroupValue>>,
+    values: &[i64],
+    key: impl FnOnce() -> Vec<GroupValue>,
+) -> u32 {
+    if let Some(id) = map.get(values) {
+        return *id;
+    }
+    let id = keys.len() as u32;
+    keys.push(key());
+    map.insert(values.to_vec(), id);
+    id
+}
+
+// Calls `f` with the value and row range of every run, checking that the
+// runs cover exactly `row_count` rows
+pub(crate) fn for_each_run(
+    column: &StringColumnReader,
+    row_count: usize,
+    mut f: impl FnMut(u8, std::ops::Range<usize>),
+) -> Result<()> {
+    let mut start = 0;
+    for (value, run_length) in column.compressed_iterator() {
+        let end = start + run_length as usize;
+        if end > row_count {
+            return Err(Error::corrupt(
+                "string column runs cover more rows than the row group",
+            ));
+        }
+        f(value, start..end);
+        start = end;
+    }
+    if start != row_count {
+        return Err(Error::corrupt("string column ended early"));
+    }
+    Ok(())
+}
+
+fn dense_key(slot: u32, key_count: usize) -> Vec<GroupValue> {
+    (0..key_count)
+        .rev()
+        .map(|i| GroupValue::Byte((slot >> (8 * i)) as u8))
+        .collect()
+}
+
+/// A hash aggregation: one state per group of a `GroupTable`. Callers
+/// aggregate each row group into the states by group id in `update`, and
+/// per-thread aggregates are combined with `merge`.
+pub struct HashAggregate<S> {
+    table: GroupTable,
+    group_ids: Vec<u32>,
+    states: Vec<S>,
+}
+
+impl<S: Default> HashAggregate<S> {
+    pub fn new(key_types: &[LogicalType]) -> Self {
+        HashAggregate {
+            table: GroupTable::new(key_types),
+            group_ids: Vec::new(),
+            states: Vec::new(),
+        }
+    }
+
+    pub fn table(&self) -> &GroupTable {
+        &self.table
+    }
+
+    pub fn states(&self) -> &[S] {
+        &self.states
+    }
+
+    /// Assigns the rows of `row_group` to their groups, then calls `update`
+    /// with the group id of every row (see `GroupTable::assign`) and the
+    /// states indexed by group id.
+    pub fn update(
+        &mut self,
+        row_group: &ProjectedRowGroup,
+        key_columns: &[usize],
+        selected: Option<&[bool]>,
+        update: impl FnOnce(&[u32], &mut [S]),
+    ) -> Result<()> {
+        self.table
+            .assign(row_group, key_columns, selected, &mut self.group_ids)?;
+        self.states.resize_with(self.table.len(), S::default);
+        update(&self.group_ids, &mut self.states);
+        Ok(())
+    }
+
+    /// Adds the groups of `other`, combining the states of keys in both.
+    pub fn merge(&mut self, other: HashAggregate<S>, mut merge: impl FnMut(&mut S, S)) {
+        for (key, state) in other.table.keys.into_iter().zip(other.states) {
+            let id = self.table.group_id(&key) as usize;
+            self.states.resize_with(self.table.len(), S::default);
+            merge(&mut self.states[id], state);
+        }
+    }
+
+    /// The groups and their states, ordered by key
+    pub fn into_groups(self) -> Vec<(Vec<GroupValue>, S)> {
+        let mut groups: Vec<_> = self.table.keys.into_iter().zip(self.states).collect();
+        groups.sort_by(|a, b| a.0.cmp(&b.0));
+        groups
+    }
+}
+
+/// Groups the rows satisfying `predicates` by the `keys` columns. `update`
+/// is called for every matching row group with the `columns` projected, the
+/// group id of every row (`NO_GROUP` for filtered rows) and the states.
+pub fn group_by<R: Read + Seek, S: Default>(
+    reader: &mut ColumnFileReader<R>,
+    keys: &[&str],
+    columns: &[&str],
+    predicates: &[Predicate],
+    mut update: impl FnMut(&ProjectedRowGroup, &[u32], &mut [S]),
+) -> Result<Vec<(Vec<GroupValue>, S)>> {
+    let schema = reader.schema().clone();
+    let key_columns: Vec<usize> = keys
+        .iter()
+        .map(|name| required_column_index(&schema, name))
+        .collect::<Result<_>>()?;
+    let mut projection = key_columns.clone();
+    for name in columns
+        .iter()
+        .copied()
+        .chain(predicates.iter().map(|x| x.column.as_str()))
+    {
+        let index = required_column_index(&schema, name)?;
+        if !projection.contains(&index) {
+            projection.push(index);
+        }
+    }
+    let key_types: Vec<LogicalType> = key_columns
+        .iter()
+        .map(|x| schema.fields[*x].logical_type)
+        .collect();
+
+    let mut aggregate = HashAggregate::new(&key_types);
+    let mut selection = Selection::new();
+    for index in reader.matching_row_groups(predicates)? {
+        let row_group = reader.read_columns(index, &projection)?;
+        row_group.select(&schema, predicates, &mut selection)?;
+        let selected = (!selection.all_selected()).then_some(selection.flags());
+        aggregate.update(&row_group, &key_columns, selected, |group_ids, states| {
+            update(&row_group, group_ids, states)
+        })?;
+    }
+    Ok(aggregate.into_groups())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::column_file::ColumnFileWriter;
+    use crate::row_group::ColumnValues;
+    use crate::schema::{Field, Schema};
+    use crate::{get_state_index, LineItem};
+
+    fn orders_schema() -> Schema {
+        Schema::new(vec![
+            Field::new("o_orderstatus", LogicalType::Char),
+            Field::new("o_totalprice", LogicalType::Decimal { scale: 2 }),
+            Field::new("o_orderdate", LogicalType::Date),
+            Field::new("o_orderpriority", LogicalType::Varchar),
+        ])
+    }
+
+    // Rows of (status, price in cents, date, priority)
+    fn orders(rows: usize) -> Vec<(String, i64, i32, String)> {
+        (0..rows)
+            .map(|i| {
+                (
+                    ["F", "O", "P"][i % 3].to_string(),
+                    (i * 7919 % 1000) as i64,
+                    9000 + (i % 5) as i32,
+                    format!("{}-PRIORITY", i % 4),
+                )
+            })
+            .collect()
+    }
+
+    fn write_orders(rows: &[(String, i64, i32, String)], row_group_size: usize) -> Vec<u8> {
+        let mut writer = ColumnFileWriter::with_schema(Vec::new(), orders_schema());
+        for chunk in rows.chunks(row_group_size) {
+            writer
+                .write_row_group(&[
+                    ColumnValues::Strings(chunk.iter().map(|x| x.0.clone()).collect()),
+                    ColumnValues::Decimals(chunk.iter().map(|x| x.1 as f64 / 100.0).collect()),
+                    ColumnValues::Dates(chunk.iter().map(|x| x.2).collect()),
+                    ColumnValues::Strings(chunk.iter().map(|x| x.3.clone()).collect()),
+                ])
+                .unwrap();
+        }
+        writer.finish().unwrap().into_inner().into_inner().unwrap()
+    }
+
+    // Sums o_totalprice into the states of the selected rows
+    fn sum_price(row_group: &ProjectedRowGroup, group_ids: &[u32], states: &mut [i64]) {
+        let price = row_group.decimal_column(1).to_i64s();
+        for (id, price) in group_ids.iter().zip(price) {
+            if *id != NO_GROUP {
+                states[*id as usize] += price;
+            }
+        }
+    }
+
+    #[test]
+    fn test_group_by_hash_keys_matches_row_by_row() {
+        let rows = orders(1000);
+        let mut reader = ColumnFileReader::new(Cursor::new(write_orders(&rows, 128))).unwrap();
+        let predicates = [Predicate::lt("o_orderdate", 9003.0)];
+
+        let groups = group_by(
+            &mut reader,
+            &["o_orderpriority", "o_orderstatus", "o_orderdate"],
+            &["o_totalprice"],
+            &predicates,
+            sum_price,
+        )
+        .unwrap();
+
+        let mut expected = BTreeMap::new();
+        for (status, price, date, priority) in rows.iter().filter(|x| x.2 < 9003) {
+            let key = vec![
+                GroupValue::Str(priority.clone()),
+                GroupValue::Byte(status.as_bytes()[0]),
+                GroupValue::Int(*date as i64),
+            ];
+            *expected.entry(key).or_insert(0) += price;
+        }
+        assert_eq!(groups, expected.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_group_by_mostly_distinct_varchar_keys() {
+        // 90 distinct priorities per row group of 100 are stored plain
+        let mut rows = orders(1000);
+        for (i, row) in rows.iter_mut().enumerate() {
+            row.3 = format!("{}-PRIORITY", i % 90);
+        }
+        let mut reader = ColumnFileReader::new(Cursor::new(write_orders(&rows, 100))).unwrap();
+
+        let groups = group_by(
+            &mut reader,
+            &["o_orderpriority"],
+            &["o_totalprice"],
+            &[],
+            sum_price,
+        )
+        .unwrap();
+
+        let mut expected = BTreeMap::new();
+        for (_, price, _, priority) in &rows {
+            *expected
+                .entry(vec![GroupValue::Str(priority.clone())])
+                .or_insert(0) += price;
+        }
+        assert_eq!(groups, expected.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_group_by_char_keys_uses_dense_table() {
+        let lineitem = |i: usize| LineItem {
+            l_returnflag: ["A", "N", "R"][i % 3].to_string(),
+            l_linestatus: ["F", "O"][i % 2].to_string(),
+            l_quantity: (i % 50) as f64,
+            l_extendedprice: 100.0,
+            l_discount: 0.0,
+            l_tax: 0.0,
+            l_shipdate: 10000,
+        };
+        let mut writer = ColumnFileWriter::new(Vec::new()).with_row_group_size(100);
+        writer
+            .write_batch(&mut (0..500).map(lineitem).collect::<Vec<_>>())
+            .unwrap();
+        let bytes = writer.finish().unwrap().into_inner().into_inner().unwrap();
+        let mut reader = ColumnFileReader::new(Cursor::new(bytes)).unwrap();
+        let mut state = vec![0; 256 * 256];
+        for i in 0..500 {
+            let item = lineitem(i);
+            state[get_state_index(
+                &item.l_returnflag.as_bytes()[0],
+                &item.l_linestatus.as_bytes()[0],
+            )] += (i % 50) as i64 * 100;
+        }
+
+        let groups = group_by(
+            &mut reader,
+            &["l_returnflag", "l_linestatus"],
+            &["l_quantity"],
+            &[],
+            |row_group, group_ids, states: &mut [i64]| {
+                let quantity = row_group.decimal_column(2).to_i64s();
+                for (id, quantity) in group_ids.iter().zip(quantity) {
+                    states[*id as usize] += quantity;
+                }
+            },
+        )
+        .unwrap();
+
+        assert!(GroupTable::new(&[LogicalType::Char, LogicalType::Char]).is_dense());
+        assert!(!GroupTable::new(&[LogicalType::Char, LogicalType::Date]).is_dense());
+        assert_eq!(groups.len(), 6);
+        for (key, sum) in groups {
+            let [GroupValue::Byte(returnflag), GroupValue::Byte(linestatus)] = key[..] else {
+                panic!("Expected byte keys, got {:?}", key);
+            };
+            assert_eq!(sum, state[get_state_index(&returnflag, &linestatus)]);
+        }
+    }
+
+    #[test]
+    fn test_merge_combines_groups() {
+        let rows = orders(300);
+        let mut reader = ColumnFileReader::new(Cursor::new(write_orders(&rows, 100))).unwrap();
+        let schema = reader.schema().clone();
+        for key_columns in [vec![0], vec![0, 3]] {
+            let key_types: Vec<_> = key_columns
+                .iter()
+                .map(|x| schema.fields[*x].logical_type)
+                .collect();
+            let mut all = HashAggregate::<i64>::new(&key_types);
+            let mut parts = [
+                HashAggregate::<i64>::new(&key_types),
+                HashAggregate::<i64>::new(&key_types),
+            ];
+            for index in 0..reader.row_group_count() {
+                let row_group = reader.read_columns(index, &[0, 1, 3]).unwrap();
+                all.update(&row_group, &key_columns, None, |ids, states| {
+                    sum_price(&row_group, ids, states)
+                })
+                .unwrap();
+                parts[index % 2]
+                    .update(&row_group, &key_columns, None, |ids, states| {
+                        sum_price(&row_group, ids, states)
+                    })
+                    .unwrap();
+            }
+
+            let [mut merged, other] = parts;
+            merged.merge(other, |state, other| *state += other);
+
+            assert_eq!(merged.table().len(), all.table().len());
+            assert_eq!(merged.into_groups(), all.into_groups());
+        }
+    }
+
+    #[test]
+    fn test_assign_skips_unselected_rows() {
+        let rows = orders(6);
+        let mut reader = ColumnFileReader::new(Cursor::new(write_orders(&rows, 6))).unwrap();
+        let row_group = reader.read_columns(0, &[0, 2]).unwrap();
+        let mut group_ids = Vec::new();
+        let cases = [
+            (vec![0], vec![0, NO_GROUP, 1, 0, NO_GROUP, NO_GROUP], 2),
+            (vec![2], vec![0, NO_GROUP, 1, 2, NO_GROUP, NO_GROUP], 3),
+        ];
+        for (key_columns, expected, group_count) in cases {
+            let key_type = reader.schema().fields[key_columns[0]].logical_type;
+            let mut table = GroupTable::new(&[key_type]);
+
+            table
+                .assign(
+                    &row_group,
+                    &key_columns,
+                    Some(&[true, false, true, true, false, false]),
+                    &mut group_ids,
+                )
+                .unwrap();
+
+            assert_eq!(group_ids, expected);
+            assert_eq!(table.len(), group_count);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::io::{Read, Write};
+
+use crate::error::{Error, Result};
+use crate::f64_column::MAX_DECIMAL_SCALE;
+use crate::io;
+use crate::schema::{lineitem_schema, Field, LogicalType, Schema};
+
+pub const MAGIC: [u8; 4] = *b"ABDB";
+/// Bumped whenever the layout changes. Readers only accept files of this
+/// version; files written by an earlier version must be rewritten, e.g. with
+/// `abdb write-line-items-column`.
+pub const FORMAT_VERSION: u16 = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHeader {
+    pub version: u16,
+    pub schema: Schema,
+}
+
+/// Selects how a column file is opened. `Legacy` skips header validation and
+/// treats the file as the headerless row group stream written before
+/// `FORMAT_VERSION` 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileMode {
+    #[default]
+    Checked,
+    Legacy,
+}
+
+impl FileHeader {
+    pub fn new(schema: Schema) -> Self {
+        FileHeader {
+            version: FORMAT_VERSION,
+            schema,
+        }
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&self.version.to_le_bytes())?;
+        let column_count = u8::try_from(self.schema.len()).expect("Schema has too many columns");
+        io::write_u8(writer, column_count)?;
+        for field in &self.schema.fields {
+            let name_len = u8::try_from(field.name.len()).expect("Column name is too long");
+            io::write_u8(writer, name_len)?;
+            writer.write_all(field.name.as_bytes())?;
+            io::write_u8(writer, field.logical_type.tag())?;
+            if let LogicalType::Decimal { scale } = field.logical_type {
+                io::write_u8(writer, scale)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(Error::BadMagic(magic));
+        }
+
+        let mut version = [0u8; 2];
+        reader.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let column_count = io::read_u8(reader)?;
+        let mut fields = Vec::with_capacity(column_count as usize);
+        for _ in 0..column_count {
+            let name_len = io::read_u8(reader)?;
+            let mut name = vec![0u8; name_len as usize];
+            reader.read_exact(&mut name)?;
+            let logical_type = match io::read_u8(reader)? {
+                1 => LogicalType::Char,
+                3 => match io::read_u8(reader)? {
+                    scale if scale <= MAX_DECIMAL_SCALE => LogicalType::Decimal { scale },
+                    scale => return Err(Error::UnsupportedScale(scale)),
+                },
+                4 => LogicalType::Date,
+                5 => LogicalType::Varchar,
+                tag => return Err(Error::UnknownLogicalType(tag)),
+            };
+            fields.push(Field {
+                name: String::from_utf8_lossy(&name).into_owned(),
+                logical_type,
+            });
+        }
+
+        Ok(FileHeader {
+            version,
+            schema: Schema::new(fields),
+        })
+    }
+
+    pub fn validate(&self, expected: &Schema) -> Result<()> {
+        if &self.schema != expected {
+            return Err(Error::SchemaMismatch {
+                expected: expected.clone(),
+                found: self.schema.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The header written by `write_row_group`.
+pub fn lineitem_header() -> FileHeader {
+    FileHeader::new(lineitem_schema())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_read_header() {
+        let header = lineitem_header();
+        let mut buffer = Vec::new();
+        header.write(&mut buffer).unwrap();
+
+        let result = FileHeader::read(&mut buffer.as_slice()).unwrap();
+
+        assert_eq!(result, header);
+        assert!(result.validate(&lineitem_schema()).is_ok());
+    }
+
+    #[test]
+    fn test_header_records_decimal_scale() {
+        let header = FileHeader::new(Schema::new(vec![
+            Field::new("price", LogicalType::Decimal { scale: 4 }),
+            Field::new("flag", LogicalType::Char),
+        ]));
+        let mut buffer = Vec::new();
+        header.write(&mut buffer).unwrap();
+
+        let result = FileHeader::read(&mut buffer.as_slice()).unwrap();
+
+        assert_eq!(
+            result.schema.fields[0].logical_type,
+            LogicalType::Decimal { scale: 4 }
+        );
+        assert_eq!(result, header);
+    }
+
+    #[test]
+    #[should_panic(expected = "is longer than 255 bytes")]
+    fn test_schema_rejects_names_the_header_cannot_hold() {
+        Schema::new(vec![Field::new(&"x".repeat(256), LogicalType::Date)]);
+    }
+
+    #[test]
+    fn test_read_header_rejects_foreign_file() {
+        let buffer = vec![0u8; 32];
+
+        let result = FileHeader::read(&mut buffer.as_slice());
+
+        assert!(matches!(result, Err(Error::BadMagic(_))));
+    }
+
+    #[test]
+    fn test_read_header_rejects_newer_version() {
+        let mut buffer = Vec::new();
+        FileHeader {
+            version: FORMAT_VERSION + 1,
+            schema: Schema::default(),
+        }
+        .write(&mut buffer)
+        .unwrap();
+
+        let result = FileHeader::read(&mut buffer.as_slice());
+
+        assert!(matches!(
+            result,
+            Err(Error::UnsupportedVersion(v)) if v == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn test_read_header_rejects_older_version() {
+        let mut buffer = Vec::new();
+        FileHeader {
+            version: 7,
+            schema: lineitem_schema(),
+        }
+        .write(&mut buffer)
+        .unwrap();
+
+        let result = FileHeader::read(&mut buffer.as_slice());
+
+        assert!(matches!(result, Err(Error::UnsupportedVersion(7))));
+    }
+
+    #[test]
+    fn test_validate_detects_schema_mismatch() {
+        let header = FileHeader::new(Schema::new(vec![Field::new(
+            "o_totalprice",
+            LogicalType::Decimal { scale: 2 },
+        )]));
+
+        let result = header.validate(&lineitem_schema());
+
+        assert!(matches!(result, Err(Error::SchemaMismatch { .. })));
+    }
+}
@@ -1,56 +1,79 @@
 use std::io::{Read, Write};
 
-pub fn read_u16<R: Read>(reader: &mut std::io::BufReader<R>) -> u16 {
+use crate::error::{Error, Result};
+use crate::TrackedWriter;
+
+pub fn read_u16<R: Read>(reader: &mut std::io::BufReader<R>) -> Result<u16> {
     let mut buffer = [0u8; 2];
-    reader.read_exact(&mut buffer).expect("Failed to read u16");
-    u16::from_le_bytes(buffer)
+    reader.read_exact(&mut buffer)?;
+    Ok(u16::from_le_bytes(buffer))
 }
 
-pub fn read_u32<R: Read>(reader: &mut R) -> u32 {
+pub fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
     let mut buffer = [0u8; 4];
-    reader.read_exact(&mut buffer).expect("Failed to read");
-    u32::from_le_bytes(buffer)
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
 }
 
-pub fn write_u32<W: Write>(writer: &mut W, value: u32) {
-    writer
-        .write_all(&value.to_le_bytes())
-        .expect("Failed to write");
+pub fn write_u32<W: Write>(writer: &mut W, value: u32) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
 }
 
-pub fn write_u64<W: Write>(writer: &mut W, value: u64) {
-    writer
-        .write_all(&value.to_le_bytes())
-        .expect("Failed to write");
+pub fn write_u64<W: Write>(writer: &mut W, value: u64) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
 }
 
-pub fn read_u64<R: Read>(reader: &mut R) -> u64 {
+pub fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
     let mut buffer = [0u8; 8];
-    reader.read_exact(&mut buffer).expect("Failed to read");
-    u64::from_le_bytes(buffer)
+    reader.read_exact(&mut buffer)?;
+    Ok(u64::from_le_bytes(buffer))
 }
 
-pub fn read_u8<R: Read>(reader: &mut R) -> u8 {
+pub fn read_u8<R: Read>(reader: &mut R) -> Result<u8> {
     let mut buffer = [0u8; 1];
-    reader.read_exact(&mut buffer).expect("Failed to read");
-    u8::from_le_bytes(buffer)
+    reader.read_exact(&mut buffer)?;
+    Ok(u8::from_le_bytes(buffer))
+}
+
+pub fn write_u8<W: Write>(writer: &mut W, value: u8) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
 }
 
-pub fn write_u8<W: Write>(writer: &mut W, value: u8) {
-    writer
-        .write_all(&value.to_le_bytes())
-        .expect("Failed to write");
+/// Writes a padding length (u8) and that many zero bytes, so that the next
+/// byte lands on a multiple of `align` from the start of the file. Arrays
+/// written after the padding can be borrowed in place from a mapped file.
+pub fn write_padding<W: Write>(writer: &mut TrackedWriter<W>, align: usize) -> Result<()> {
+    let padding = (align - (writer.bytes_written() + 1) % align) % align;
+    write_u8(writer, padding as u8)?;
+    writer.write_all(&[0u8; 7][..padding])?;
+    Ok(())
 }
 
-pub fn write_repeated_string<W: Write>(writer: &mut W, value: u8, count: u32) {
-    writer.write_all(&[value]).expect("Failed to write");
-    write_u32(writer, count);
+pub fn read_padding<R: Read>(reader: &mut R) -> Result<()> {
+    let padding = read_u8(reader)? as usize;
+    if padding > 7 {
+        return Err(Error::corrupt(format!(
+            "invalid padding length {}",
+            padding
+        )));
+    }
+    let mut buffer = [0u8; 7];
+    reader.read_exact(&mut buffer[..padding])?;
+    Ok(())
 }
 
-pub fn read_repeated_string<R: Read>(reader: &mut R) -> (u8, u32) {
-    let value = read_u8(reader);
-    let count = read_u32(reader);
-    (value, count)
+pub fn write_repeated_string<W: Write>(writer: &mut W, value: u8, count: u32) -> Result<()> {
+    writer.write_all(&[value])?;
+    write_u32(writer, count)
+}
+
+pub fn read_repeated_string<R: Read>(reader: &mut R) -> Result<(u8, u32)> {
+    let value = read_u8(reader)?;
+    let count = read_u32(reader)?;
+    Ok((value, count))
 }
 
 #[cfg(test)]
@@ -66,7 +89,7 @@ mod tests {
             let mut buffer = Cursor::new(Vec::new());
             let mut writer = std::io::BufWriter::new(&mut buffer);
 
-            write_u8(&mut writer, test_value);
+            write_u8(&mut writer, test_value).unwrap();
             writer.flush().unwrap();
 
             let binding = writer.into_inner().unwrap().clone().into_inner();
@@ -74,7 +97,7 @@ mod tests {
 
             buffer.set_position(0);
             let mut reader = std::io::BufReader::new(buffer);
-            let result = read_u8(&mut reader);
+            let result = read_u8(&mut reader).unwrap();
 
             prop_assert_eq!(result, test_value);
         }
@@ -84,7 +107,7 @@ mod tests {
             let mut buffer = Cursor::new(Vec::new());
             let mut writer = std::io::BufWriter::new(&mut buffer);
 
-            write_u32(&mut writer, test_value);
+            write_u32(&mut writer, test_value).unwrap();
             writer.flush().unwrap();
 
             let binding = writer.into_inner().unwrap().clone().into_inner();
@@ -92,7 +115,7 @@ mod tests {
 
             buffer.set_position(0);
             let mut reader = std::io::BufReader::new(buffer);
-            let result = read_u32(&mut reader);
+            let result = read_u32(&mut reader).unwrap();
 
             prop_assert_eq!(result, test_value);
         }
@@ -102,7 +125,7 @@ mod tests {
             let mut buffer = Cursor::new(Vec::new());
             let mut writer = std::io::BufWriter::new(&mut buffer);
 
-            write_repeated_string(&mut writer, value, count);
+            write_repeated_string(&mut writer, value, count).unwrap();
             writer.flush().unwrap();
 
             let binding = writer.into_inner().unwrap().clone().into_inner();
@@ -110,9 +133,35 @@ mod tests {
 
             buffer.set_position(0);
             let mut reader = std::io::BufReader::new(buffer);
-            let result = read_repeated_string(&mut reader);
+            let result = read_repeated_string(&mut reader).unwrap();
 
             prop_assert_eq!(result, (value, count));
         }
     }
+
+    #[test]
+    fn test_padding_aligns_next_byte() {
+        for (offset, align) in [(0, 4), (3, 4), (5, 8), (10, 2), (6, 1)] {
+            let mut writer = TrackedWriter::new(Vec::new());
+            writer.write_all(&vec![0xFF; offset]).unwrap();
+
+            write_padding(&mut writer, align).unwrap();
+
+            assert_eq!(writer.bytes_written() % align, 0);
+            let written = writer.into_inner().into_inner().unwrap();
+            let mut reader = &written[offset..];
+            read_padding(&mut reader).unwrap();
+            assert!(reader.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_read_truncated_input_is_an_error() {
+        let mut reader = std::io::BufReader::new(&[1u8, 2, 3][..]);
+
+        assert!(matches!(
+            read_u64(&mut reader),
+            Err(crate::error::Error::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof
+        ));
+    }
 }
@@ -0,0 +1,864 @@
+//! Aggregation kernels for the scan loops.
+//!
+//! Every kernel has a scalar implementation plus AVX2, AVX-512 and NEON
+//! implementations. The free functions use the fastest one the running CPU
+//! supports; `Kernel` picks one explicitly, which the tests and benchmarks
+//! use to compare them.
+//!
+//! Masks are `bool` slices of the same length as the values, and only the
+//! values whose flag is set are summed.
+
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kernel {
+    Scalar,
+    Avx2,
+    Avx512,
+    Neon,
+}
+
+// `sum_disc_price` multiplies in 32-bit lanes, which is exact when prices fit
+// in an i32 and `one - discount` stays below 2^16. Products are then below
+// 2^47, so a block of 2^15 rows cannot overflow an i64 accumulator.
+const PRODUCT_BLOCK: usize = 1 << 15;
+const MAX_FACTOR: u64 = 1 << 16;
+
+impl Kernel {
+    /// The fastest kernel the running CPU supports
+    pub fn detect() -> Kernel {
+        static DETECTED: OnceLock<Kernel> = OnceLock::new();
+        *DETECTED.get_or_init(|| *Kernel::available().last().unwrap())
+    }
+
+    /// The kernels the running CPU supports, slowest first
+    pub fn available() -> Vec<Kernel> {
+        [Kernel::Scalar, Kernel::Avx2, Kernel::Avx512, Kernel::Neon]
+            .into_iter()
+            .filter(|kernel| kernel.is_supported())
+            .collect()
+    }
+
+    pub fn is_supported(self) -> bool {
+        match self {
+            Kernel::Scalar => true,
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx512 => {
+                is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512bw")
+            }
+            #[cfg(target_arch = "aarch64")]
+            Kernel::Neon => std::arch::is_aarch64_feature_detected!("neon"),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    fn checked(self) -> Kernel {
+        assert!(
+            self.is_supported(),
+            "{:?} kernels are not supported by this CPU",
+            self
+        );
+        self
+    }
+
+    pub fn sum_u16(self, values: &[u16]) -> u64 {
+        match self.checked() {
+            // SAFETY: `checked` verified the CPU features of each kernel
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => unsafe { avx2::sum_u16(values) },
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx512 => unsafe { avx512::sum_u16(values) },
+            #[cfg(target_arch = "aarch64")]
+            Kernel::Neon => unsafe { neon::sum_u16(values) },
+            _ => scalar::sum_u16(values),
+        }
+    }
+
+    pub fn masked_sum_u16(self, values: &[u16], selected: &[bool]) -> u64 {
+        assert_eq!(values.len(), selected.len());
+        match self.checked() {
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => unsafe { avx2::masked_sum_u16(values, selected) },
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx512 => unsafe { avx512::masked_sum_u16(values, selected) },
+            #[cfg(target_arch = "aarch64")]
+            Kernel::Neon => unsafe { neon::masked_sum_u16(values, selected) },
+            _ => scalar::masked_sum_u16(values, selected),
+        }
+    }
+
+    /// Wrapping sum of the selected values
+    pub fn masked_sum_i64(self, values: &[i64], selected: &[bool]) -> i64 {
+        assert_eq!(values.len(), selected.len());
+        match self.checked() {
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => unsafe { avx2::masked_sum_i64(values, selected) },
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx512 => unsafe { avx512::masked_sum_i64(values, selected) },
+            #[cfg(target_arch = "aarch64")]
+            Kernel::Neon => unsafe { neon::masked_sum_i64(values, selected) },
+            _ => scalar::masked_sum_i64(values, selected),
+        }
+    }
+
+    /// Sum of `price * (one - discount)`, where `one` is the unscaled 1 of
+    /// the discount column
+    pub fn sum_disc_price(self, price: &[i64], discount: &[i64], one: i64) -> i128 {
+        assert_eq!(price.len(), discount.len());
+        let kernel = self.checked();
+        price
+            .chunks(PRODUCT_BLOCK)
+            .zip(discount.chunks(PRODUCT_BLOCK))
+            .map(|(price, discount)| {
+                if !products_fit(price, discount, one) {
+                    return scalar::sum_disc_price(price, discount, one);
+                }
+                (match kernel {
+                    #[cfg(target_arch = "x86_64")]
+                    Kernel::Avx2 => unsafe { avx2::sum_disc_price(price, discount, one, None) },
+                    #[cfg(target_arch = "x86_64")]
+                    Kernel::Avx512 => unsafe { avx512::sum_disc_price(price, discount, one, None) },
+                    #[cfg(target_arch = "aarch64")]
+                    Kernel::Neon => unsafe { neon::sum_disc_price(price, discount, one, None) },
+                    _ => return scalar::sum_disc_price(price, discount, one),
+                }) as i128
+            })
+            .sum()
+    }
+
+    /// Same as `sum_disc_price` over the selected rows
+    pub fn masked_sum_disc_price(
+        self,
+        price: &[i64],
+        discount: &[i64],
+        one: i64,
+        selected: &[bool],
+    ) -> i128 {
+        assert_eq!(price.len(), discount.len());
+        assert_eq!(price.len(), selected.len());
+        let kernel = self.checked();
+        price
+            .chunks(PRODUCT_BLOCK)
+            .zip(discount.chunks(PRODUCT_BLOCK))
+            .zip(selected.chunks(PRODUCT_BLOCK))
+            .map(|((price, discount), selected)| {
+                if !products_fit(price, discount, one) {
+                    return scalar::masked_sum_disc_price(price, discount, one, selected);
+                }
+                (match kernel {
+                    #[cfg(target_arch = "x86_64")]
+                    Kernel::Avx2 => unsafe {
+                        avx2::sum_disc_price(price, discount, one, Some(selected))
+                    },
+                    #[cfg(target_arch = "x86_64")]
+                    Kernel::Avx512 => unsafe {
+                        avx512::sum_disc_price(price, discount, one, Some(selected))
+                    },
+                    #[cfg(target_arch = "aarch64")]
+                    Kernel::Neon => unsafe {
+                        neon::sum_disc_price(price, discount, one, Some(selected))
+                    },
+                    _ => return scalar::masked_sum_disc_price(price, discount, one, selected),
+                }) as i128
+            })
+            .sum()
+    }
+
+    /// Sum of `a * b` over the selected rows
+    pub fn masked_sum_products(self, a: &[i64], b: &[i64], selected: &[bool]) -> i128 {
+        // a * (0 - b) is the disc price kernel with a zero `one`
+        -self.masked_sum_disc_price(a, b, 0, selected)
+    }
+
+    /// Clears the flags of the rows whose value is outside `min..=max`
+    pub fn and_in_range_u8(self, values: &[u8], min: u8, max: u8, selected: &mut [bool]) {
+        assert_eq!(values.len(), selected.len());
+        match self.checked() {
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => unsafe { avx2::and_in_range_u8(values, min, max, selected) },
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx512 => unsafe { avx512::and_in_range_u8(values, min, max, selected) },
+            #[cfg(target_arch = "aarch64")]
+            Kernel::Neon => unsafe { neon::and_in_range_u8(values, min, max, selected) },
+            _ => scalar::and_in_range_u8(values, min, max, selected),
+        }
+    }
+
+    /// Clears the flags of the rows whose value is outside `min..=max`
+    pub fn and_in_range_u16(self, values: &[u16], min: u16, max: u16, selected: &mut [bool]) {
+        assert_eq!(values.len(), selected.len());
+        match self.checked() {
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => unsafe { avx2::and_in_range_u16(values, min, max, selected) },
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx512 => unsafe { avx512::and_in_range_u16(values, min, max, selected) },
+            #[cfg(target_arch = "aarch64")]
+            Kernel::Neon => unsafe { neon::and_in_range_u16(values, min, max, selected) },
+            _ => scalar::and_in_range_u16(values, min, max, selected),
+        }
+    }
+}
+
+fn products_fit(price: &[i64], discount: &[i64], one: i64) -> bool {
+    price.iter().all(|x| i32::try_from(*x).is_ok())
+        && discount.iter().all(|x| {
+            one.checked_sub(*x)
+                .is_some_and(|factor| factor.unsigned_abs() < MAX_FACTOR)
+        })
+}
+
+pub fn sum_u16(values: &[u16]) -> u64 {
+    Kernel::detect().sum_u16(values)
+}
+
+pub fn masked_sum_u16(values: &[u16], selected: &[bool]) -> u64 {
+    Kernel::detect().masked_sum_u16(values, selected)
+}
+
+pub fn masked_sum_i64(values: &[i64], selected: &[bool]) -> i64 {
+    Kernel::detect().masked_sum_i64(values, selected)
+}
+
+pub fn sum_disc_price(price: &[i64], discount: &[i64], one: i64) -> i128 {
+    Kernel::detect().sum_disc_price(price, discount, one)
+}
+
+pub fn masked_sum_disc_price(price: &[i64], discount: &[i64], one: i64, selected: &[bool]) -> i128 {
+    Kernel::detect().masked_sum_disc_price(price, discount, one, selected)
+}
+
+pub fn masked_sum_products(a: &[i64], b: &[i64], selected: &[bool]) -> i128 {
+    Kernel::detect().masked_sum_products(a, b, selected)
+}
+
+pub fn and_in_range_u8(values: &[u8], min: u8, max: u8, selected: &mut [bool]) {
+    Kernel::detect().and_in_range_u8(values, min, max, selected)
+}
+
+pub fn and_in_range_u16(values: &[u16], min: u16, max: u16, selected: &mut [bool]) {
+    Kernel::detect().and_in_range_u16(values, min, max, selected)
+}
+
+/// Number of set flags
+pub fn count_selected(selected: &[bool]) -> u64 {
+    selected.iter().filter(|x| **x).count() as u64
+}
+
+mod scalar {
+    pub fn sum_u16(values: &[u16]) -> u64 {
+        values.iter().map(|x| *x as u64).sum()
+    }
+
+    pub fn masked_sum_u16(values: &[u16], selected: &[bool]) -> u64 {
+        values
+            .iter()
+            .zip(selected)
+            .filter(|(_, selected)| **selected)
+            .map(|(x, _)| *x as u64)
+            .sum()
+    }
+
+    pub fn masked_sum_i64(values: &[i64], selected: &[bool]) -> i64 {
+        values
+            .iter()
+            .zip(selected)
+            .filter(|(_, selected)| **selected)
+            .fold(0i64, |sum, (x, _)| sum.wrapping_add(*x))
+    }
+
+    pub fn sum_disc_price(price: &[i64], discount: &[i64], one: i64) -> i128 {
+        price
+            .iter()
+            .zip(discount)
+            .map(|(price, discount)| *price as i128 * (one as i128 - *discount as i128))
+            .sum()
+    }
+
+    pub fn masked_sum_disc_price(
+        price: &[i64],
+        discount: &[i64],
+        one: i64,
+        selected: &[bool],
+    ) -> i128 {
+        price
+            .iter()
+            .zip(discount)
+            .zip(selected)
+            .filter(|(_, selected)| **selected)
+            .map(|((price, discount), _)| *price as i128 * (one as i128 - *discount as i128))
+            .sum()
+    }
+
+    pub fn and_in_range_u8(values: &[u8], min: u8, max: u8, selected: &mut [bool]) {
+        for (value, selected) in values.iter().zip(selected) {
+            *selected &= (min..=max).contains(value);
+        }
+    }
+
+    pub fn and_in_range_u16(values: &[u16], min: u16, max: u16, selected: &mut [bool]) {
+        for (value, selected) in values.iter().zip(selected) {
+            *selected &= (min..=max).contains(value);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    use super::scalar;
+
+    // Sums the u16 lanes of `v` into four u64 lanes: `sad_epu8` adds the low
+    // bytes and the high bytes separately, and the high bytes weigh 256.
+    #[target_feature(enable = "avx2")]
+    unsafe fn add_u16s(low: &mut __m256i, high: &mut __m256i, v: __m256i) {
+        let zero = _mm256_setzero_si256();
+        let low_bytes = _mm256_and_si256(v, _mm256_set1_epi16(0xFF));
+        *low = _mm256_add_epi64(*low, _mm256_sad_epu8(low_bytes, zero));
+        *high = _mm256_add_epi64(*high, _mm256_sad_epu8(_mm256_srli_epi16(v, 8), zero));
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn horizontal_sum(v: __m256i) -> u64 {
+        let mut lanes = [0u64; 4];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, v);
+        lanes.iter().fold(0u64, |sum, x| sum.wrapping_add(*x))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sum_u16(values: &[u16]) -> u64 {
+        let mut low = _mm256_setzero_si256();
+        let mut high = _mm256_setzero_si256();
+        let chunks = values.chunks_exact(16);
+        let rest = chunks.remainder();
+        for chunk in chunks {
+            let v = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+            add_u16s(&mut low, &mut high, v);
+        }
+        horizontal_sum(low) + 256 * horizontal_sum(high) + scalar::sum_u16(rest)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn masked_sum_u16(values: &[u16], selected: &[bool]) -> u64 {
+        let zero = _mm256_setzero_si256();
+        let mut low = _mm256_setzero_si256();
+        let mut high = _mm256_setzero_si256();
+        let chunks = values.chunks_exact(16);
+        let rest = chunks.remainder();
+        let flags = selected.chunks_exact(16);
+        let rest_flags = flags.remainder();
+        for (chunk, flags) in chunks.zip(flags) {
+            let v = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+            // Flags are 0 or 1, so 0 - flag is an all-zeros or all-ones lane
+            let flags = _mm256_cvtepu8_epi16(_mm_loadu_si128(flags.as_ptr() as *const __m128i));
+            let mask = _mm256_sub_epi16(zero, flags);
+            add_u16s(&mut low, &mut high, _mm256_and_si256(v, mask));
+        }
+        horizontal_sum(low) + 256 * horizontal_sum(high) + scalar::masked_sum_u16(rest, rest_flags)
+    }
+
+    // All-ones lanes for the selected rows among the four at `flags`
+    #[target_feature(enable = "avx2")]
+    unsafe fn mask_i64x4(flags: &[bool]) -> __m256i {
+        let flags = (flags.as_ptr() as *const i32).read_unaligned();
+        let flags = _mm256_cvtepu8_epi64(_mm_cvtsi32_si128(flags));
+        _mm256_sub_epi64(_mm256_setzero_si256(), flags)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn masked_sum_i64(values: &[i64], selected: &[bool]) -> i64 {
+        let mut sum = _mm256_setzero_si256();
+        let chunks = values.chunks_exact(4);
+        let rest = chunks.remainder();
+        let flags = selected.chunks_exact(4);
+        let rest_flags = flags.remainder();
+        for (chunk, flags) in chunks.zip(flags) {
+            let v = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+            sum = _mm256_add_epi64(sum, _mm256_and_si256(v, mask_i64x4(flags)));
+        }
+        (horizontal_sum(sum) as i64).wrapping_add(scalar::masked_sum_i64(rest, rest_flags))
+    }
+
+    /// The caller guarantees the products fit 32-bit lanes and their sum an
+    /// i64, see `products_fit`.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sum_disc_price(
+        price: &[i64],
+        discount: &[i64],
+        one: i64,
+        selected: Option<&[bool]>,
+    ) -> i64 {
+        let ones = _mm256_set1_epi64x(one);
+        let mut sum = _mm256_setzero_si256();
+        let full = price.len() / 4 * 4;
+        for start in (0..full).step_by(4) {
+            let p = _mm256_loadu_si256(price.as_ptr().add(start) as *const __m256i);
+            let d = _mm256_loadu_si256(discount.as_ptr().add(start) as *const __m256i);
+            let mut product = _mm256_mul_epi32(p, _mm256_sub_epi64(ones, d));
+            if let Some(selected) = selected {
+                product = _mm256_and_si256(product, mask_i64x4(&selected[start..]));
+            }
+            sum = _mm256_add_epi64(sum, product);
+        }
+        let rest = match selected {
+            None => scalar::sum_disc_price(&price[full..], &discount[full..], one),
+            Some(selected) => scalar::masked_sum_disc_price(
+                &price[full..],
+                &discount[full..],
+                one,
+                &selected[full..],
+            ),
+        };
+        horizontal_sum(sum) as i64 + rest as i64
+    }
+
+    // Unsigned range checks: `v` is in range when clamping it changes nothing.
+    // Flags are 0 or 1, so and-ing them with all-ones or all-zeros bytes
+    // keeps them valid bools.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn and_in_range_u8(values: &[u8], min: u8, max: u8, selected: &mut [bool]) {
+        let min_lanes = _mm256_set1_epi8(min as i8);
+        let max_lanes = _mm256_set1_epi8(max as i8);
+        let full = values.len() / 32 * 32;
+        for start in (0..full).step_by(32) {
+            let v = _mm256_loadu_si256(values.as_ptr().add(start) as *const __m256i);
+            let in_range = _mm256_and_si256(
+                _mm256_cmpeq_epi8(_mm256_max_epu8(v, min_lanes), v),
+                _mm256_cmpeq_epi8(_mm256_min_epu8(v, max_lanes), v),
+            );
+            let flags = selected.as_mut_ptr().add(start) as *mut __m256i;
+            _mm256_storeu_si256(flags, _mm256_and_si256(_mm256_loadu_si256(flags), in_range));
+        }
+        scalar::and_in_range_u8(&values[full..], min, max, &mut selected[full..]);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn and_in_range_u16(values: &[u16], min: u16, max: u16, selected: &mut [bool]) {
+        let min_lanes = _mm256_set1_epi16(min as i16);
+        let max_lanes = _mm256_set1_epi16(max as i16);
+        let full = values.len() / 16 * 16;
+        for start in (0..full).step_by(16) {
+            let v = _mm256_loadu_si256(values.as_ptr().add(start) as *const __m256i);
+            let in_range = _mm256_and_si256(
+                _mm256_cmpeq_epi16(_mm256_max_epu16(v, min_lanes), v),
+                _mm256_cmpeq_epi16(_mm256_min_epu16(v, max_lanes), v),
+            );
+            // Narrow the 16-bit lanes to bytes; packing works per 128-bit
+            // half, so gather the two halves' results into the low half
+            let packed = _mm256_packs_epi16(in_range, in_range);
+            let in_range = _mm256_castsi256_si128(_mm256_permute4x64_epi64(packed, 0b1000));
+            let flags = selected.as_mut_ptr().add(start) as *mut __m128i;
+            _mm_storeu_si128(flags, _mm_and_si128(_mm_loadu_si128(flags), in_range));
+        }
+        scalar::and_in_range_u16(&values[full..], min, max, &mut selected[full..]);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx512 {
+    use std::arch::x86_64::*;
+
+    use super::scalar;
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    unsafe fn add_u16s(low: &mut __m512i, high: &mut __m512i, v: __m512i) {
+        let zero = _mm512_setzero_si512();
+        let low_bytes = _mm512_and_si512(v, _mm512_set1_epi16(0xFF));
+        *low = _mm512_add_epi64(*low, _mm512_sad_epu8(low_bytes, zero));
+        *high = _mm512_add_epi64(*high, _mm512_sad_epu8(_mm512_srli_epi16(v, 8), zero));
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn sum_u16(values: &[u16]) -> u64 {
+        let mut low = _mm512_setzero_si512();
+        let mut high = _mm512_setzero_si512();
+        let chunks = values.chunks_exact(32);
+        let rest = chunks.remainder();
+        for chunk in chunks {
+            let v = _mm512_loadu_si512(chunk.as_ptr() as *const __m512i);
+            add_u16s(&mut low, &mut high, v);
+        }
+        let low = _mm512_reduce_add_epi64(low) as u64;
+        let high = _mm512_reduce_add_epi64(high) as u64;
+        low + 256 * high + scalar::sum_u16(rest)
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn masked_sum_u16(values: &[u16], selected: &[bool]) -> u64 {
+        let mut low = _mm512_setzero_si512();
+        let mut high = _mm512_setzero_si512();
+        let chunks = values.chunks_exact(32);
+        let rest = chunks.remainder();
+        let flags = selected.chunks_exact(32);
+        let rest_flags = flags.remainder();
+        for (chunk, flags) in chunks.zip(flags) {
+            let v = _mm512_loadu_si512(chunk.as_ptr() as *const __m512i);
+            let flags = _mm512_cvtepu8_epi16(_mm256_loadu_si256(flags.as_ptr() as *const __m256i));
+            let mask = _mm512_test_epi16_mask(flags, flags);
+            add_u16s(&mut low, &mut high, _mm512_maskz_mov_epi16(mask, v));
+        }
+        let low = _mm512_reduce_add_epi64(low) as u64;
+        let high = _mm512_reduce_add_epi64(high) as u64;
+        low + 256 * high + scalar::masked_sum_u16(rest, rest_flags)
+    }
+
+    // Mask bits for the selected rows among the eight at `flags`
+    #[target_feature(enable = "avx512f,avx512bw")]
+    unsafe fn mask_8(flags: &[bool]) -> __mmask8 {
+        let flags = (flags.as_ptr() as *const i64).read_unaligned();
+        let flags = _mm512_cvtepu8_epi64(_mm_cvtsi64_si128(flags));
+        _mm512_test_epi64_mask(flags, flags)
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn masked_sum_i64(values: &[i64], selected: &[bool]) -> i64 {
+        let mut sum = _mm512_setzero_si512();
+        let chunks = values.chunks_exact(8);
+        let rest = chunks.remainder();
+        let flags = selected.chunks_exact(8);
+        let rest_flags = flags.remainder();
+        for (chunk, flags) in chunks.zip(flags) {
+            let v = _mm512_loadu_si512(chunk.as_ptr() as *const __m512i);
+            sum = _mm512_mask_add_epi64(sum, mask_8(flags), sum, v);
+        }
+        _mm512_reduce_add_epi64(sum).wrapping_add(scalar::masked_sum_i64(rest, rest_flags))
+    }
+
+    /// The caller guarantees the products fit 32-bit lanes and their sum an
+    /// i64, see `products_fit`.
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn sum_disc_price(
+        price: &[i64],
+        discount: &[i64],
+        one: i64,
+        selected: Option<&[bool]>,
+    ) -> i64 {
+        let ones = _mm512_set1_epi64(one);
+        let mut sum = _mm512_setzero_si512();
+        let full = price.len() / 8 * 8;
+        for start in (0..full).step_by(8) {
+            let p = _mm512_loadu_si512(price.as_ptr().add(start) as *const __m512i);
+            let d = _mm512_loadu_si512(discount.as_ptr().add(start) as *const __m512i);
+            let product = _mm512_mul_epi32(p, _mm512_sub_epi64(ones, d));
+            let mask = match selected {
+                None => 0xFF,
+                Some(selected) => mask_8(&selected[start..]),
+            };
+            sum = _mm512_mask_add_epi64(sum, mask, sum, product);
+        }
+        let rest = match selected {
+            None => scalar::sum_disc_price(&price[full..], &discount[full..], one),
+            Some(selected) => scalar::masked_sum_disc_price(
+                &price[full..],
+                &discount[full..],
+                one,
+                &selected[full..],
+            ),
+        };
+        _mm512_reduce_add_epi64(sum) + rest as i64
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn and_in_range_u8(values: &[u8], min: u8, max: u8, selected: &mut [bool]) {
+        let min_lanes = _mm512_set1_epi8(min as i8);
+        let max_lanes = _mm512_set1_epi8(max as i8);
+        let full = values.len() / 64 * 64;
+        for start in (0..full).step_by(64) {
+            let v = _mm512_loadu_si512(values.as_ptr().add(start) as *const __m512i);
+            let in_range =
+                _mm512_cmpge_epu8_mask(v, min_lanes) & _mm512_cmple_epu8_mask(v, max_lanes);
+            let flags = selected.as_mut_ptr().add(start) as *mut __m512i;
+            _mm512_storeu_si512(
+                flags,
+                _mm512_maskz_mov_epi8(in_range, _mm512_loadu_si512(flags)),
+            );
+        }
+        scalar::and_in_range_u8(&values[full..], min, max, &mut selected[full..]);
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw")]
+    pub unsafe fn and_in_range_u16(values: &[u16], min: u16, max: u16, selected: &mut [bool]) {
+        let min_lanes = _mm512_set1_epi16(min as i16);
+        let max_lanes = _mm512_set1_epi16(max as i16);
+        let full = values.len() / 32 * 32;
+        for start in (0..full).step_by(32) {
+            let v = _mm512_loadu_si512(values.as_ptr().add(start) as *const __m512i);
+            let in_range =
+                _mm512_cmpge_epu16_mask(v, min_lanes) & _mm512_cmple_epu16_mask(v, max_lanes);
+            let flags = selected.as_mut_ptr().add(start) as *mut __m256i;
+            let widened = _mm512_cvtepu8_epi16(_mm256_loadu_si256(flags));
+            let kept = _mm512_maskz_mov_epi16(in_range, widened);
+            _mm256_storeu_si256(flags, _mm512_cvtepi16_epi8(kept));
+        }
+        scalar::and_in_range_u16(&values[full..], min, max, &mut selected[full..]);
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    use super::scalar;
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn sum_u16(values: &[u16]) -> u64 {
+        let mut sum = vdupq_n_u64(0);
+        let chunks = values.chunks_exact(8);
+        let rest = chunks.remainder();
+        for chunk in chunks {
+            let v = vld1q_u16(chunk.as_ptr());
+            sum = vpadalq_u32(sum, vpaddlq_u16(v));
+        }
+        vaddvq_u64(sum) + scalar::sum_u16(rest)
+    }
+
+    // All-ones lanes for the selected rows among the eight at `flags`
+    #[target_feature(enable = "neon")]
+    unsafe fn mask_u16x8(flags: &[bool]) -> uint16x8_t {
+        let flags = vmovl_u8(vld1_u8(flags.as_ptr() as *const u8));
+        vtstq_u16(flags, flags)
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn masked_sum_u16(values: &[u16], selected: &[bool]) -> u64 {
+        let mut sum = vdupq_n_u64(0);
+        let chunks = values.chunks_exact(8);
+        let rest = chunks.remainder();
+        let flags = selected.chunks_exact(8);
+        let rest_flags = flags.remainder();
+        for (chunk, flags) in chunks.zip(flags) {
+            let v = vandq_u16(vld1q_u16(chunk.as_ptr()), mask_u16x8(flags));
+            sum = vpadalq_u32(sum, vpaddlq_u16(v));
+        }
+        vaddvq_u64(sum) + scalar::masked_sum_u16(rest, rest_flags)
+    }
+
+    // The `mask_u16x8` lanes widened to four vectors of two i64 lanes
+    #[target_feature(enable = "neon")]
+    unsafe fn mask_i64x2x4(flags: &[bool]) -> [int64x2_t; 4] {
+        let mask = mask_u16x8(flags);
+        let low = vmovl_u16(vget_low_u16(mask));
+        let high = vmovl_u16(vget_high_u16(mask));
+        // Sign-extending the all-ones u32 lanes keeps them all-ones
+        [
+            vmovl_s32(vget_low_s32(vreinterpretq_s32_u32(low))),
+            vmovl_s32(vget_high_s32(vreinterpretq_s32_u32(low))),
+            vmovl_s32(vget_low_s32(vreinterpretq_s32_u32(high))),
+            vmovl_s32(vget_high_s32(vreinterpretq_s32_u32(high))),
+        ]
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn masked_sum_i64(values: &[i64], selected: &[bool]) -> i64 {
+        let mut sum = vdupq_n_s64(0);
+        let chunks = values.chunks_exact(8);
+        let rest = chunks.remainder();
+        let flags = selected.chunks_exact(8);
+        let rest_flags = flags.remainder();
+        for (chunk, flags) in chunks.zip(flags) {
+            for (i, mask) in mask_i64x2x4(flags).into_iter().enumerate() {
+                let v = vld1q_s64(chunk.as_ptr().add(2 * i));
+                sum = vaddq_s64(sum, vandq_s64(v, mask));
+            }
+        }
+        vaddvq_s64(sum).wrapping_add(scalar::masked_sum_i64(rest, rest_flags))
+    }
+
+    /// The caller guarantees the products fit 32-bit lanes and their sum an
+    /// i64, see `products_fit`.
+    #[target_feature(enable = "neon")]
+    pub unsafe fn sum_disc_price(
+        price: &[i64],
+        discount: &[i64],
+        one: i64,
+        selected: Option<&[bool]>,
+    ) -> i64 {
+        let ones = vdupq_n_s64(one);
+        let mut sum = vdupq_n_s64(0);
+        let full = price.len() / 8 * 8;
+        for start in (0..full).step_by(8) {
+            let masks = selected.map(|selected| mask_i64x2x4(&selected[start..]));
+            for i in 0..4 {
+                let p = vld1q_s64(price.as_ptr().add(start + 2 * i));
+                let d = vld1q_s64(discount.as_ptr().add(start + 2 * i));
+                let factor = vsubq_s64(ones, d);
+                let mut product = vmull_s32(vmovn_s64(p), vmovn_s64(factor));
+                if let Some(masks) = masks {
+                    product = vandq_s64(product, masks[i]);
+                }
+                sum = vaddq_s64(sum, product);
+            }
+        }
+        let rest = match selected {
+            None => scalar::sum_disc_price(&price[full..], &discount[full..], one),
+            Some(selected) => scalar::masked_sum_disc_price(
+                &price[full..],
+                &discount[full..],
+                one,
+                &selected[full..],
+            ),
+        };
+        vaddvq_s64(sum) + rest as i64
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn and_in_range_u8(values: &[u8], min: u8, max: u8, selected: &mut [bool]) {
+        let min_lanes = vdupq_n_u8(min);
+        let max_lanes = vdupq_n_u8(max);
+        let full = values.len() / 16 * 16;
+        for start in (0..full).step_by(16) {
+            let v = vld1q_u8(values.as_ptr().add(start));
+            let in_range = vandq_u8(vcgeq_u8(v, min_lanes), vcleq_u8(v, max_lanes));
+            let flags = selected.as_mut_ptr().add(start) as *mut u8;
+            vst1q_u8(flags, vandq_u8(vld1q_u8(flags), in_range));
+        }
+        scalar::and_in_range_u8(&values[full..], min, max, &mut selected[full..]);
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn and_in_range_u16(values: &[u16], min: u16, max: u16, selected: &mut [bool]) {
+        let min_lanes = vdupq_n_u16(min);
+        let max_lanes = vdupq_n_u16(max);
+        let full = values.len() / 8 * 8;
+        for start in (0..full).step_by(8) {
+            let v = vld1q_u16(values.as_ptr().add(start));
+            let in_range = vandq_u16(vcgeq_u16(v, min_lanes), vcleq_u16(v, max_lanes));
+            let flags = selected.as_mut_ptr().add(start) as *mut u8;
+            vst1_u8(flags, vand_u8(vld1_u8(flags), vmovn_u16(in_range)));
+        }
+        scalar::and_in_range_u16(&values[full..], min, max, &mut selected[full..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_scalar_is_always_available() {
+        assert_eq!(Kernel::available()[0], Kernel::Scalar);
+        assert!(Kernel::available().contains(&Kernel::detect()));
+    }
+
+    #[test]
+    fn test_sum_u16_does_not_overflow_lanes() {
+        let values = vec![u16::MAX; 100_003];
+        let selected = vec![true; values.len()];
+
+        for kernel in Kernel::available() {
+            assert_eq!(kernel.sum_u16(&values), 100_003 * u16::MAX as u64);
+            assert_eq!(
+                kernel.masked_sum_u16(&values, &selected),
+                100_003 * u16::MAX as u64
+            );
+        }
+    }
+
+    #[test]
+    fn test_sum_disc_price_falls_back_for_wide_values() {
+        let price = vec![i64::MAX / 2, 10, -(1 << 40), 7, 3];
+        let discount = vec![1, 5, 0, -(1 << 20), 100];
+
+        for kernel in Kernel::available() {
+            assert_eq!(
+                kernel.sum_disc_price(&price, &discount, 100),
+                scalar::sum_disc_price(&price, &discount, 100)
+            );
+        }
+    }
+
+    fn flags(len: usize) -> impl Strategy<Value = Vec<bool>> {
+        prop::collection::vec(any::<bool>(), len)
+    }
+
+    proptest! {
+        #[test]
+        fn test_kernels_match_scalar(
+            (values, selected) in (0usize..200).prop_flat_map(|len| {
+                (prop::collection::vec(any::<u16>(), len), flags(len))
+            })
+        ) {
+            for kernel in Kernel::available() {
+                prop_assert_eq!(kernel.sum_u16(&values), scalar::sum_u16(&values));
+                prop_assert_eq!(
+                    kernel.masked_sum_u16(&values, &selected),
+                    scalar::masked_sum_u16(&values, &selected)
+                );
+            }
+        }
+
+        #[test]
+        fn test_i64_kernels_match_scalar(
+            (price, discount, selected) in (0usize..200).prop_flat_map(|len| {
+                (
+                    prop::collection::vec(-10_000_000i64..10_000_000, len),
+                    prop::collection::vec(-20i64..120, len),
+                    flags(len),
+                )
+            })
+        ) {
+            for kernel in Kernel::available() {
+                prop_assert_eq!(
+                    kernel.masked_sum_i64(&price, &selected),
+                    scalar::masked_sum_i64(&price, &selected)
+                );
+                prop_assert_eq!(
+                    kernel.sum_disc_price(&price, &discount, 100),
+                    scalar::sum_disc_price(&price, &discount, 100)
+                );
+                prop_assert_eq!(
+                    kernel.masked_sum_disc_price(&price, &discount, 100, &selected),
+                    scalar::masked_sum_disc_price(&price, &discount, 100, &selected)
+                );
+                let products: i128 = price
+                    .iter()
+                    .zip(&discount)
+                    .zip(&selected)
+                    .filter(|(_, selected)| **selected)
+                    .map(|((price, discount), _)| *price as i128 * *discount as i128)
+                    .sum();
+                prop_assert_eq!(kernel.masked_sum_products(&price, &discount, &selected), products);
+            }
+        }
+
+        #[test]
+        fn test_range_kernels_match_scalar(
+            (bytes, selected) in (0usize..300).prop_flat_map(|len| {
+                (prop::collection::vec(any::<u8>(), len), flags(len))
+            }),
+            words in prop::collection::vec(any::<u16>(), 0..300),
+            (min, max) in (any::<u16>(), any::<u16>()),
+        ) {
+            let word_flags: Vec<bool> = words.iter().map(|x| x % 3 != 0).collect();
+            let mut expected_bytes = selected.clone();
+            scalar::and_in_range_u8(&bytes, min as u8, max as u8, &mut expected_bytes);
+            let mut expected_words = word_flags.clone();
+            scalar::and_in_range_u16(&words, min, max, &mut expected_words);
+
+            for kernel in Kernel::available() {
+                let mut result = selected.clone();
+                kernel.and_in_range_u8(&bytes, min as u8, max as u8, &mut result);
+                prop_assert_eq!(&result, &expected_bytes);
+                let mut result = word_flags.clone();
+                kernel.and_in_range_u16(&words, min, max, &mut result);
+                prop_assert_eq!(&result, &expected_words);
+            }
+        }
+
+        #[test]
+        fn test_masked_sum_i64_wraps(
+            values in prop::collection::vec(any::<i64>(), 0..100)
+        ) {
+            let selected = vec![true; values.len()];
+            let expected = values.iter().fold(0i64, |sum, x| sum.wrapping_add(*x));
+
+            for kernel in Kernel::available() {
+                prop_assert_eq!(kernel.masked_sum_i64(&values, &selected), expected);
+            }
+        }
+    }
+}
@@ -1,13 +1,50 @@
+pub mod aggregate;
+pub mod column_file;
+pub mod date_column;
+pub mod error;
+pub mod group_by;
+pub mod header;
 pub mod io;
+pub mod kernels;
+pub mod mapped_file;
+pub mod predicate;
+pub mod query;
+pub mod record_batch;
+pub mod row_group;
+pub mod scan;
+pub mod schema;
+pub mod selection;
+pub mod sql;
 pub mod string_column;
+pub mod table_provider;
 pub mod f64_column;
+pub mod varchar_column;
+#[cfg(test)]
+mod test_data;
 use std::{
     cmp::min,
     io::{BufRead, Read, Write},
+    sync::atomic::{AtomicUsize, Ordering},
 };
-static MAX_ROW_GROUP_SIZE: usize = 8000;
-use string_column::StringColumnReader;
-use f64_column::write_f64_column;
+/// Rows per row group unless the writer is configured otherwise.
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 8000;
+/// Upper bound on the rows of any row group, for writers and readers.
+pub const MAX_ROW_GROUP_SIZE: usize = 1 << 24;
+// Headerless files were always written in row groups of at most 8000 rows
+static LEGACY_ROW_GROUP_SIZE: usize = 8000;
+use column_file::{ColumnFileReader, RowGroupMeta};
+use f64_column::{scale_factor, DecimalColumn, DecimalColumnRef};
+pub use f64_column::Decimal;
+pub use f64_column::{compress_f64, decompress_f64};
+pub use error::Error;
+use error::Result;
+use header::FileMode;
+use mapped_file::MappedColumnFile;
+use predicate::{CompareOp, Predicate, ValueRange};
+use row_group::{read_row_count, read_row_group_columns, write_row_group_columns, ColumnValues};
+use schema::lineitem_schema;
+use selection::Selection;
+use string_column::{StringColumnReader, StringColumnRef};
 #[derive(Debug, Default, PartialEq, Clone)]
 pub struct QueryOneState {
     pub count: u64,
@@ -20,46 +57,483 @@ pub struct QueryOneState {
 #[derive(Debug, Default, PartialEq, Clone)]
 pub struct QueryOneStateColumn {
     pub count: u64,
-    pub sum_qty: u64,
-    pub sum_base_price: u64,
-    pub sum_discount: u64,
-    pub sum_tax: u64,
+    pub sum_qty: i128,
+    pub sum_base_price: i128,
+    pub sum_discount: i128,
+    pub sum_tax: i128,
+    /// Sum of `extendedprice * (1 - discount)`, at the price scale plus the
+    /// discount scale
+    pub sum_disc_price: i128,
+    /// Sum of `extendedprice * (1 - discount) * (1 + tax)`, at the price,
+    /// discount and tax scales added together
+    pub sum_charge: i128,
 }
 
-pub fn query_1_column(path: &str) -> Vec<Option<QueryOneStateColumn>> {
-    let file = std::fs::File::open(path).expect("Failed to open file");
-    let mut reader = std::io::BufReader::new(file);
+impl QueryOneStateColumn {
+    pub fn merge(&mut self, other: &QueryOneStateColumn) {
+        self.count += other.count;
+        self.sum_qty += other.sum_qty;
+        self.sum_base_price += other.sum_base_price;
+        self.sum_discount += other.sum_discount;
+        self.sum_tax += other.sum_tax;
+        self.sum_disc_price += other.sum_disc_price;
+        self.sum_charge += other.sum_charge;
+    }
+}
+
+/// One output row of TPC-H Query 1. Sums are exact; averages are derived
+/// from the exact sums.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryOneRow {
+    pub l_returnflag: String,
+    pub l_linestatus: String,
+    pub sum_qty: Decimal,
+    pub sum_base_price: Decimal,
+    pub sum_disc_price: Decimal,
+    pub sum_charge: Decimal,
+    pub avg_qty: f64,
+    pub avg_price: f64,
+    pub avg_disc: f64,
+    pub count_order: u64,
+}
+
+/// The `l_shipdate` cutoff of TPC-H Query 1 with the validation DELTA of 90
+/// days. Legacy files were filtered with it when they were exported.
+pub const QUERY_1_SHIPDATE_CUTOFF: &str = "1998-09-02";
+
+/// Runs Query 1 over the rows with `l_shipdate <= shipdate_cutoff`, given as
+/// days since 1970-01-01.
+pub fn query_1_column(path: &str, shipdate_cutoff: i32) -> Result<Vec<QueryOneRow>> {
+    query_1_column_with_mode(path, FileMode::Checked, shipdate_cutoff)
+}
+
+pub fn query_1_column_with_mode(
+    path: &str,
+    mode: FileMode,
+    shipdate_cutoff: i32,
+) -> Result<Vec<QueryOneRow>> {
+    Ok(query_1_result(&query_1_state(path, mode, shipdate_cutoff)?))
+}
+
+pub fn query_1_state(
+    path: &str,
+    mode: FileMode,
+    shipdate_cutoff: i32,
+) -> Result<Vec<Option<QueryOneStateColumn>>> {
     let mut state: Vec<Option<QueryOneStateColumn>> = vec![None; 256 * 256];
 
-    loop {
-        if reader.fill_buf().unwrap().is_empty() {
-            break;
+    match mode {
+        FileMode::Checked => {
+            let mut reader = open_column_file(path)?;
+            let predicates = [Predicate::lt_eq("l_shipdate", shipdate_cutoff as f64)];
+            let mut context = ScanContext::new();
+            for index in reader.matching_row_groups(&predicates)? {
+                context.update_state_from_row_group(
+                    reader.seek_to_row_group(index)?,
+                    shipdate_cutoff,
+                    &mut state,
+                )?;
+            }
+        }
+        FileMode::Legacy => {
+            // Legacy files have no l_shipdate column, so only cutoffs that
+            // keep every exported row can be answered
+            let legacy_cutoff = date_column::parse_date(QUERY_1_SHIPDATE_CUTOFF).unwrap();
+            if shipdate_cutoff < legacy_cutoff {
+                return Err(Error::InvalidQuery(format!(
+                    "legacy files only hold rows with l_shipdate <= {}",
+                    QUERY_1_SHIPDATE_CUTOFF
+                )));
+            }
+            let file = std::fs::File::open(path)?;
+            let mut reader = std::io::BufReader::new(file);
+            let mut context = ScanContext::new();
+            while !reader.fill_buf()?.is_empty() {
+                context.update_state_from_legacy_row_group(&mut reader, &mut state)?;
+            }
         }
-        update_state_from_row_group(&mut reader, &mut state);
     }
-    state
+    Ok(state)
 }
 
-fn sum_u16s(data: &U16column, start: usize, count: usize) -> u64 {
-    data.data[start..start + count]
-        .iter()
-        .map(|x| *x as u64)
-        .sum::<u64>()
+/// Runs Query 1 like `query_1_column`, spreading the row groups over
+/// `threads` worker threads.
+pub fn query_1_column_parallel(
+    path: &str,
+    shipdate_cutoff: i32,
+    threads: usize,
+) -> Result<Vec<QueryOneRow>> {
+    Ok(query_1_result(&query_1_state_parallel(
+        path,
+        shipdate_cutoff,
+        threads,
+    )?))
+}
+
+pub fn query_1_state_parallel(
+    path: &str,
+    shipdate_cutoff: i32,
+    threads: usize,
+) -> Result<Vec<Option<QueryOneStateColumn>>> {
+    let predicates = [Predicate::lt_eq("l_shipdate", shipdate_cutoff as f64)];
+    let row_groups = open_column_file(path)?.matching_row_groups(&predicates)?;
+    scan_parallel(
+        &row_groups,
+        threads,
+        || open_column_file(path),
+        |reader, context, index, state| {
+            context.update_state_from_row_group(
+                reader.seek_to_row_group(index)?,
+                shipdate_cutoff,
+                state,
+            )
+        },
+    )
+}
+
+/// Runs Query 1 like `query_1_column_parallel` over a memory-mapped file,
+/// reading the columns in place instead of copying them into buffers.
+pub fn query_1_column_mapped(
+    path: &str,
+    shipdate_cutoff: i32,
+    threads: usize,
+) -> Result<Vec<QueryOneRow>> {
+    Ok(query_1_result(&query_1_state_mapped(
+        path,
+        shipdate_cutoff,
+        threads,
+    )?))
+}
+
+pub fn query_1_state_mapped(
+    path: &str,
+    shipdate_cutoff: i32,
+    threads: usize,
+) -> Result<Vec<Option<QueryOneStateColumn>>> {
+    let file = MappedColumnFile::open(path)?;
+    file.header().validate(&lineitem_schema())?;
+    let predicates = [Predicate::lt_eq("l_shipdate", shipdate_cutoff as f64)];
+    let row_groups = file.matching_row_groups(&predicates)?;
+    scan_parallel(
+        &row_groups,
+        threads,
+        || Ok(&file),
+        |file, context, index, state| {
+            context.update_state_from_mapped_row_group(file, index, shipdate_cutoff, state)
+        },
+    )
+}
+
+/// Each worker gets its own source from `open`, takes the next row group
+/// from a shared counter and aggregates it with `update` into its own state
+/// table. The tables are merged once every worker is done.
+fn scan_parallel<S>(
+    row_groups: &[usize],
+    threads: usize,
+    open: impl Fn() -> Result<S> + Sync,
+    update: impl Fn(&mut S, &mut ScanContext, usize, &mut [Option<QueryOneStateColumn>]) -> Result<()>
+        + Sync,
+) -> Result<Vec<Option<QueryOneStateColumn>>> {
+    if threads == 0 {
+        return Err(Error::InvalidQuery(
+            "at least one thread is required".to_string(),
+        ));
+    }
+    let next_row_group = AtomicUsize::new(0);
+    let worker = || -> Result<Vec<Option<QueryOneStateColumn>>> {
+        let mut source = open()?;
+        let mut context = ScanContext::new();
+        let mut state = vec![None; 256 * 256];
+        while let Some(&index) = row_groups.get(next_row_group.fetch_add(1, Ordering::Relaxed)) {
+            update(&mut source, &mut context, index, &mut state)?;
+        }
+        Ok(state)
+    };
+    let states = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(row_groups.len()).max(1))
+            .map(|_| scope.spawn(worker))
+            .collect();
+        workers
+            .into_iter()
+            .map(|x| x.join().expect("Query 1 worker panicked"))
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    let mut states = states.into_iter();
+    let mut state = states.next().unwrap();
+    for other in states {
+        for (group, other_group) in state.iter_mut().zip(other) {
+            if let Some(other_group) = other_group {
+                group
+                    .get_or_insert_with(QueryOneStateColumn::default)
+                    .merge(&other_group);
+            }
+        }
+    }
+    Ok(state)
+}
+
+/// The `l_shipdate` start of the TPC-H Query 6 validation parameters.
+pub const QUERY_6_SHIPDATE: &str = "1994-01-01";
+
+/// Substitution parameters of TPC-H Query 6. The default is the validation
+/// query: DATE 1994-01-01, DISCOUNT 0.06 and QUANTITY 24.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuerySixParams {
+    /// First l_shipdate of the one-year range, as days since 1970-01-01
+    pub shipdate: i32,
+    pub discount: f64,
+    pub quantity: f64,
+}
+
+impl Default for QuerySixParams {
+    fn default() -> Self {
+        QuerySixParams {
+            shipdate: date_column::parse_date(QUERY_6_SHIPDATE).unwrap(),
+            discount: 0.06,
+            quantity: 24.0,
+        }
+    }
+}
+
+impl QuerySixParams {
+    /// The WHERE clause of Query 6, ANDed
+    pub fn predicates(&self) -> Result<Vec<Predicate>> {
+        let shipdate_end = date_column::add_months(self.shipdate, 12).ok_or_else(|| {
+            Error::InvalidQuery(format!(
+                "shipdate {} is out of range",
+                date_column::format_date(self.shipdate)
+            ))
+        })?;
+        // BETWEEN discount - 0.01 AND discount + 0.01, computed in cents so
+        // the bounds are the exact decimals
+        let discount = f64_column::to_unscaled(self.discount, 2)
+            .map_err(|e| Error::InvalidQuery(e.to_string()))?;
+        Ok(vec![
+            Predicate::gt_eq("l_shipdate", self.shipdate as f64),
+            Predicate::lt("l_shipdate", shipdate_end as f64),
+            Predicate::gt_eq("l_discount", f64_column::from_unscaled(discount - 1, 2)),
+            Predicate::lt_eq("l_discount", f64_column::from_unscaled(discount + 1, 2)),
+            Predicate::lt("l_quantity", self.quantity),
+        ])
+    }
+}
+
+/// Runs TPC-H Query 6, returning `sum(l_extendedprice * l_discount)` over
+/// the matching rows. Row groups are pruned with the footer statistics and
+/// the remaining rows filtered with a `Selection`.
+pub fn query_6_column(path: &str, params: &QuerySixParams) -> Result<Decimal> {
+    let mut reader = open_column_file(path)?;
+    let schema = reader.schema().clone();
+    let predicates = params.predicates()?;
+    let [quantity, discount, extendedprice, shipdate] =
+        ["l_quantity", "l_discount", "l_extendedprice", "l_shipdate"]
+            .map(|name| schema.index_of(name).unwrap());
+    let projection = [quantity, discount, extendedprice, shipdate];
+    let scale = match (
+        schema.fields[extendedprice].logical_type,
+        schema.fields[discount].logical_type,
+    ) {
+        (
+            schema::LogicalType::Decimal { scale: price_scale },
+            schema::LogicalType::Decimal {
+                scale: discount_scale,
+            },
+        ) => price_scale + discount_scale,
+        _ => unreachable!("lineitem_schema prices and discounts are decimals"),
+    };
+
+    let mut selection = Selection::new();
+    let mut price_values = Vec::new();
+    let mut discount_values = Vec::new();
+    let mut revenue = 0i128;
+    for index in reader.matching_row_groups(&predicates)? {
+        let row_group = reader.read_columns(index, &projection)?;
+        row_group.select(&schema, &predicates, &mut selection)?;
+        if selection.none_selected() {
+            continue;
+        }
+        row_group
+            .decimal_column(extendedprice)
+            .view()
+            .decode_into(&mut price_values);
+        row_group
+            .decimal_column(discount)
+            .view()
+            .decode_into(&mut discount_values);
+        revenue += kernels::masked_sum_products(&price_values, &discount_values, selection.flags());
+    }
+    Ok(Decimal::new(revenue, scale))
+}
+
+/// Opens a lineitem column file, validating its header against the layout
+/// this build writes so stale or foreign files are rejected up front.
+pub fn open_column_file(path: &str) -> Result<ColumnFileReader<std::fs::File>> {
+    let reader = ColumnFileReader::open(path)?;
+    reader.header().validate(&lineitem_schema())?;
+    Ok(reader)
+}
+
+/// Column buffers reused across the row groups of a Query 1 scan. Reading a
+/// row group into the context overwrites the previous one in place, so a
+/// scan allocates once per column rather than once per row group.
+#[derive(Default)]
+pub struct ScanContext {
+    linestatus: StringColumnReader,
+    returnflag: StringColumnReader,
+    // quantity, discount, tax and extendedprice
+    values: [DecimalColumn; 4],
+    shipdate: DecimalColumn,
+    selection: Selection,
+    // Decoded discount, tax and extendedprice
+    decoded: [Vec<i64>; 3],
+}
+
+impl ScanContext {
+    pub fn new() -> Self {
+        ScanContext::default()
+    }
+
+    pub fn update_state_from_row_group<R: Read>(
+        &mut self,
+        reader: &mut std::io::BufReader<R>,
+        shipdate_cutoff: i32,
+        state: &mut [Option<QueryOneStateColumn>],
+    ) -> Result<()> {
+        let item_count = read_row_count(reader)? as usize;
+        self.linestatus.read(reader)?;
+        self.returnflag.read(reader)?;
+        for (column, scale) in self.values.iter_mut().zip(lineitem_value_scales()) {
+            column.read_into(reader, scale, item_count)?;
+        }
+        self.shipdate.read_into(reader, 0, item_count)?;
+        select_shipped_by(&mut self.selection, self.shipdate.view(), shipdate_cutoff);
+        aggregate_row_group(
+            item_count,
+            self.linestatus.view(),
+            self.returnflag.view(),
+            self.values.each_ref().map(|x| x.view()),
+            (!self.selection.all_selected()).then_some(self.selection.flags()),
+            &mut self.decoded,
+            state,
+        )
+    }
+
+    /// Same as `update_state_from_row_group` for row group `index` of a
+    /// mapped lineitem file. The columns are read in place; only the row
+    /// selection and decoded values go through the context's buffers.
+    pub fn update_state_from_mapped_row_group(
+        &mut self,
+        file: &MappedColumnFile,
+        index: usize,
+        shipdate_cutoff: i32,
+        state: &mut [Option<QueryOneStateColumn>],
+    ) -> Result<()> {
+        let item_count = file.row_groups()[index].row_count as usize;
+        // Columns in `lineitem_schema` order
+        let linestatus = file.string_column(index, 0)?;
+        let returnflag = file.string_column(index, 1)?;
+        let values = [
+            file.decimal_column(index, 2)?,
+            file.decimal_column(index, 3)?,
+            file.decimal_column(index, 4)?,
+            file.decimal_column(index, 5)?,
+        ];
+        let shipdate = file.decimal_column(index, 6)?;
+        select_shipped_by(&mut self.selection, shipdate, shipdate_cutoff);
+        aggregate_row_group(
+            item_count,
+            linestatus,
+            returnflag,
+            values,
+            (!self.selection.all_selected()).then_some(self.selection.flags()),
+            &mut self.decoded,
+            state,
+        )
+    }
+
+    /// Same as `update_state_from_row_group` for headerless files, whose
+    /// decimal columns are u16 values with an implied scale of 2.
+    pub fn update_state_from_legacy_row_group<R: Read>(
+        &mut self,
+        reader: &mut std::io::BufReader<R>,
+        state: &mut [Option<QueryOneStateColumn>],
+    ) -> Result<()> {
+        let item_count = read_u16(reader)? as usize;
+        if item_count > LEGACY_ROW_GROUP_SIZE {
+            return Err(Error::corrupt(format!(
+                "legacy row group has {} rows, at most {} are supported",
+                item_count, LEGACY_ROW_GROUP_SIZE
+            )));
+        }
+        self.linestatus.read_legacy(reader)?;
+        self.returnflag.read_legacy(reader)?;
+        for column in &mut self.values {
+            column.read_legacy_into(reader, 2, item_count)?;
+        }
+        aggregate_row_group(
+            item_count,
+            self.linestatus.view(),
+            self.returnflag.view(),
+            self.values.each_ref().map(|x| x.view()),
+            None,
+            &mut self.decoded,
+            state,
+        )
+    }
+}
+
+// Selects the rows shipped on or before the cutoff
+fn select_shipped_by(selection: &mut Selection, shipdate: DecimalColumnRef, shipdate_cutoff: i32) {
+    selection.reset(shipdate.len());
+    selection.filter_decimal(
+        shipdate,
+        ValueRange::new(CompareOp::LtEq, shipdate_cutoff as f64, 0),
+    );
 }
 
+/// Decodes and aggregates one row group. Scans over many row groups should
+/// reuse a `ScanContext` instead.
 pub fn update_state_from_row_group<R: Read>(
     reader: &mut std::io::BufReader<R>,
-    state: &mut Vec<Option<QueryOneStateColumn>>,
-) -> () {
-    let item_count = read_u16(reader);
-    let linestatus_column = StringColumnReader::new(reader);
+    shipdate_cutoff: i32,
+    state: &mut [Option<QueryOneStateColumn>],
+) -> Result<()> {
+    ScanContext::new().update_state_from_row_group(reader, shipdate_cutoff, state)
+}
+
+pub fn update_state_from_legacy_row_group<R: Read>(
+    reader: &mut std::io::BufReader<R>,
+    state: &mut [Option<QueryOneStateColumn>],
+) -> Result<()> {
+    ScanContext::new().update_state_from_legacy_row_group(reader, state)
+}
+
+// `values` holds quantity, discount, tax and extendedprice in that order.
+// When `selected` is given only the rows flagged in it are aggregated.
+// `decoded` is scratch space for the discount, tax and price values.
+fn aggregate_row_group(
+    item_count: usize,
+    linestatus_column: StringColumnRef,
+    returnflag_column: StringColumnRef,
+    values: [DecimalColumnRef; 4],
+    selected: Option<&[bool]>,
+    decoded: &mut [Vec<i64>; 3],
+    state: &mut [Option<QueryOneStateColumn>],
+) -> Result<()> {
+    let [quantity, discount, tax, extendedprice] = values;
+    // The derived sums need every row's product, so decode those columns once
+    let (one, tax_one) = (scale_factor(discount.scale), scale_factor(tax.scale));
+    let [discount_values, tax_values, price_values] = decoded;
+    discount.decode_into(discount_values);
+    tax.decode_into(tax_values);
+    extendedprice.decode_into(price_values);
+    // The runs of flags and statuses are visited in order, so cursors sum the
+    // values without rescanning run length columns from their first run
+    let [mut quantity_sum, mut discount_sum, mut tax_sum, mut price_sum] =
+        [quantity, discount, tax, extendedprice].map(|x| x.cursor());
     let mut linestatus = linestatus_column.compressed_iterator();
-    let returnflag_column = StringColumnReader::new(reader);
     let mut returnflag = returnflag_column.compressed_iterator();
-    let quantity = read_u16_column(reader, item_count);
-    let discount = read_u16_column(reader, item_count);
-    let tax = read_u16_column(reader, item_count);
-    let extendedprice = read_u16_column(reader, item_count);
 
     let mut index: usize = 0;
     let mut current_returnflag = None;
@@ -67,34 +541,73 @@ pub fn update_state_from_row_group<R: Read>(
     let mut current_linestatus = None;
     let mut current_linestatus_count = 0;
     
-    while index < item_count as usize {
+    while index < item_count {
         // Get new values if we've used up the current ones
         if current_returnflag_count == 0 {
-            let (rf_char, rf_count) = returnflag.next().expect("Returnflag ended early");
+            let (rf_char, rf_count) = returnflag
+                .next()
+                .ok_or_else(|| Error::corrupt("l_returnflag ended early"))?;
             current_returnflag = Some(rf_char);
-            current_returnflag_count = *rf_count;
+            current_returnflag_count = rf_count;
         }
         
         if current_linestatus_count == 0 {
-            let (ls_char, ls_count) = linestatus.next().expect("Linestatus ended early");
+            let (ls_char, ls_count) = linestatus
+                .next()
+                .ok_or_else(|| Error::corrupt("l_linestatus ended early"))?;
             current_linestatus = Some(ls_char);
-            current_linestatus_count = *ls_count;
+            current_linestatus_count = ls_count;
         }
         
         let run_length = min(current_returnflag_count as usize, current_linestatus_count as usize);
+        if index + run_length > item_count {
+            return Err(Error::corrupt("string column runs cover more rows than the row group"));
+        }
         
         let rf_char = current_returnflag.unwrap();
         let ls_char = current_linestatus.unwrap();
-        let current_index = get_state_index(rf_char, ls_char);
-        
-        let current_state = state[current_index].get_or_insert_with(|| QueryOneStateColumn::default());
+        let current_index = get_state_index(&rf_char, &ls_char);
         
-        // Update the state with this run
-        current_state.count += run_length as u64;
-        current_state.sum_qty += sum_u16s(&quantity, index, run_length);
-        current_state.sum_base_price += sum_u16s(&extendedprice, index, run_length);
-        current_state.sum_discount += sum_u16s(&discount, index, run_length);
-        current_state.sum_tax += sum_u16s(&tax, index, run_length);
+        let run = index..index + run_length;
+        let (price, discount_run, tax_run) = (
+            &price_values[run.clone()],
+            &discount_values[run.clone()],
+            &tax_values[run.clone()],
+        );
+        match selected {
+            None => {
+                let current_state = state[current_index].get_or_insert_with(QueryOneStateColumn::default);
+
+                // Update the state with this run
+                current_state.count += run_length as u64;
+                current_state.sum_qty += quantity_sum.sum(index, run_length);
+                current_state.sum_base_price += price_sum.sum(index, run_length);
+                current_state.sum_discount += discount_sum.sum(index, run_length);
+                current_state.sum_tax += tax_sum.sum(index, run_length);
+                current_state.sum_disc_price += kernels::sum_disc_price(price, discount_run, one);
+                current_state.sum_charge +=
+                    sum_charge(price, discount_run, tax_run, [one, tax_one], None);
+            }
+            Some(selected) => {
+                let selected = &selected[run];
+                let count = kernels::count_selected(selected);
+                // Groups only appear in the result once a row passes the filter
+                if count > 0 {
+                    let current_state = state[current_index].get_or_insert_with(QueryOneStateColumn::default);
+                    current_state.count += count;
+                    current_state.sum_qty += quantity_sum.masked_sum(index, selected);
+                    current_state.sum_base_price +=
+                        kernels::masked_sum_i64(price, selected) as i128;
+                    current_state.sum_discount +=
+                        kernels::masked_sum_i64(discount_run, selected) as i128;
+                    current_state.sum_tax += kernels::masked_sum_i64(tax_run, selected) as i128;
+                    current_state.sum_disc_price +=
+                        kernels::masked_sum_disc_price(price, discount_run, one, selected);
+                    current_state.sum_charge +=
+                        sum_charge(price, discount_run, tax_run, [one, tax_one], Some(selected));
+                }
+            }
+        }
 
         // Update the remaining counts
         current_returnflag_count -= run_length as u32;
@@ -102,67 +615,159 @@ pub fn update_state_from_row_group<R: Read>(
         
         index += run_length;
     }
+    Ok(())
+}
+
+// Sum of `price * (one - discount) * (one + tax)`, where `ones` holds one in
+// the discount and tax scales. The triple product needs more than 64 bits,
+// so unlike the other sums it has no SIMD kernel.
+fn sum_charge(
+    price: &[i64],
+    discount: &[i64],
+    tax: &[i64],
+    ones: [i64; 2],
+    selected: Option<&[bool]>,
+) -> i128 {
+    let [one, tax_one] = ones;
+    let charges = price
+        .iter()
+        .zip(discount)
+        .zip(tax)
+        .map(|((price, discount), tax)| {
+            *price as i128 * (one - discount) as i128 * (tax_one + tax) as i128
+        });
+    match selected {
+        None => charges.sum(),
+        Some(selected) => charges
+            .zip(selected)
+            .filter(|(_, selected)| **selected)
+            .map(|(charge, _)| charge)
+            .sum(),
+    }
 }
 
-pub fn print_state_column(state: Vec<Option<QueryOneStateColumn>>) {
+// Scales of quantity, discount, tax and extendedprice in lineitem_schema, in
+// the order `ScanContext` reads them.
+fn lineitem_value_scales() -> [u8; 4] {
+    let schema = lineitem_schema();
+    ["l_quantity", "l_discount", "l_tax", "l_extendedprice"].map(|name| {
+        match schema.fields[schema.index_of(name).unwrap()].logical_type {
+            schema::LogicalType::Decimal { scale } => scale,
+            _ => unreachable!("lineitem_schema values are decimals")
+        }
+    })
+}
+
+/// Turns the aggregation state into Query 1 rows, ordered by returnflag and
+/// then linestatus. Each sum is in the scale of the product that produced it.
+pub fn query_1_result(state: &[Option<QueryOneStateColumn>]) -> Vec<QueryOneRow> {
+    let [quantity_scale, discount_scale, tax_scale, price_scale] = lineitem_value_scales();
+    let mut rows = Vec::new();
     for i in 0..256 {
         for j in 0..256 {
             if let Some(state_column) = &state[i * 256 + j] {
-                let l_returnflag = String::from_utf8(vec![i as u8]).unwrap();
-                let l_linestatus = String::from_utf8(vec![j as u8]).unwrap();
-                let state = QueryOneState {
-                    count: state_column.count,
-                    sum_qty: state_column.sum_qty as f64 / 100.0,
-                    sum_base_price: state_column.sum_base_price as f64 / 100.0,
-                    sum_disc_price: state_column.sum_base_price as f64 / 100.0
-                        * (1.0 - state_column.sum_discount as f64 / 100.0),
-                    sum_charge: state_column.sum_base_price as f64 / 100.0
-                        * (1.0 - state_column.sum_discount as f64 / 100.0)
-                        * (1.0 + state_column.sum_tax as f64 / 100.0),
+                let count = state_column.count;
+                let average = |sum: i128, scale: u8| {
+                    sum as f64 / (count as f64 * scale_factor(scale) as f64)
                 };
-                println!("{}, {}, {:?}", l_returnflag, l_linestatus, state);
+                rows.push(QueryOneRow {
+                    l_returnflag: (i as u8 as char).to_string(),
+                    l_linestatus: (j as u8 as char).to_string(),
+                    sum_qty: Decimal::new(state_column.sum_qty, quantity_scale),
+                    sum_base_price: Decimal::new(state_column.sum_base_price, price_scale),
+                    sum_disc_price: Decimal::new(
+                        state_column.sum_disc_price,
+                        price_scale + discount_scale,
+                    ),
+                    sum_charge: Decimal::new(
+                        state_column.sum_charge,
+                        price_scale + discount_scale + tax_scale,
+                    ),
+                    avg_qty: average(state_column.sum_qty, quantity_scale),
+                    avg_price: average(state_column.sum_base_price, price_scale),
+                    avg_disc: average(state_column.sum_discount, discount_scale),
+                    count_order: count,
+                });
             }
         }
     }
+    rows
+}
+
+pub fn print_query_1(rows: &[QueryOneRow]) {
+    for row in rows {
+        println!(
+            "{}, {}, sum_qty: {}, sum_base_price: {}, sum_disc_price: {}, sum_charge: {}, avg_qty: {}, avg_price: {}, avg_disc: {}, count_order: {}",
+            row.l_returnflag,
+            row.l_linestatus,
+            row.sum_qty,
+            row.sum_base_price,
+            row.sum_disc_price,
+            row.sum_charge,
+            row.avg_qty,
+            row.avg_price,
+            row.avg_disc,
+            row.count_order
+        );
+    }
 }
 
-fn read_u16<R: Read>(reader: &mut std::io::BufReader<R>) -> u16 {
-    let mut buffer = [0u8; 2];
-    reader.read_exact(&mut buffer).expect("Failed to read");
-    let item_count = u16::from_le_bytes(buffer);
-    item_count
+pub fn print_query_6(revenue: &Decimal) {
+    println!("revenue: {}", revenue);
 }
 
-pub fn read_u16_column<R: Read>(reader: &mut std::io::BufReader<R>, item_count: u16) -> U16column {
-    let mut data = [0u16; MAX_ROW_GROUP_SIZE];
-    reader
-        .read_exact(bytemuck::cast_slice_mut(&mut data[0..item_count as usize]))
-        .expect("Failed to read");
-    U16column {
-        data,
-        size: item_count as usize,
-    }
+fn read_u16<R: Read>(reader: &mut std::io::BufReader<R>) -> Result<u16> {
+    io::read_u16(reader)
+}
+
+pub fn read_u16_column<R: Read>(
+    reader: &mut std::io::BufReader<R>,
+    item_count: u16,
+) -> Result<U16column> {
+    let mut column = U16column::default();
+    column.read(reader, item_count)?;
+    Ok(column)
 }
 
-pub fn read_f64_column<R: Read>(reader: &mut std::io::BufReader<R>, item_count: u16) -> Vec<f64> {
-    read_u16_column(reader, item_count)
-        .data
+pub fn read_f64_column<R: Read>(
+    reader: &mut std::io::BufReader<R>,
+    item_count: u16,
+) -> Result<Vec<f64>> {
+    Ok(read_u16_column(reader, item_count)?
+        .values()
         .iter()
         .map(|x| decompress_f64(*x))
-        .collect()
+        .collect())
 }
 
 pub fn get_state_index(returnflag: &u8, linestatus: &u8) -> usize {
     (*returnflag as usize) * 256 + (*linestatus as usize)
 }
+
+/// A legacy u16 column. The buffer is reused by `read`.
+#[derive(Debug, Default, Clone)]
 pub struct U16column {
-    pub data: [u16; MAX_ROW_GROUP_SIZE],
-    #[allow(dead_code)]
-    size: usize,
+    data: Vec<u16>,
 }
 
-pub fn decompress_f64(f: u16) -> f64 {
-    f as f64 / 100.0
+impl U16column {
+    pub fn read<R: Read>(
+        &mut self,
+        reader: &mut std::io::BufReader<R>,
+        item_count: u16,
+    ) -> Result<()> {
+        if item_count as usize > LEGACY_ROW_GROUP_SIZE {
+            return Err(Error::corrupt(format!(
+                "legacy row group has {} rows, at most {} are supported",
+                item_count, LEGACY_ROW_GROUP_SIZE
+            )));
+        }
+        f64_column::read_le_into(reader, &mut self.data, item_count as usize)
+    }
+
+    pub fn values(&self) -> &[u16] {
+        &self.data
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -173,28 +778,85 @@ pub struct LineItem {
     pub l_extendedprice: f64,
     pub l_discount: f64,
     pub l_tax: f64,
+    /// Days since 1970-01-01
+    pub l_shipdate: i32,
 }
 
-pub fn write_batch(writer: &mut TrackedWriter<std::io::BufWriter<std::fs::File>>, batch: &mut Vec<LineItem>) {
+pub fn write_batch<W: Write>(
+    writer: &mut TrackedWriter<W>,
+    batch: &mut [LineItem],
+) -> Result<RowGroupMeta> {
     batch.sort_by(|a, b| {
         a.l_returnflag
             .cmp(&b.l_returnflag)
             .then(a.l_linestatus.cmp(&b.l_linestatus))
     });
-    write_row_group(&*batch, writer);
+    write_row_group(&*batch, writer)
+}
+
+pub fn write_row_group<W: Write>(
+    lineitems: &[LineItem],
+    writer: &mut TrackedWriter<W>,
+) -> Result<RowGroupMeta> {
+    write_row_group_columns(&lineitem_schema(), &lineitem_columns(lineitems), writer)
+}
+
+pub fn read_row_group<R: Read>(reader: &mut std::io::BufReader<R>) -> Result<Vec<LineItem>> {
+    Ok(lineitems_from_columns(read_row_group_columns(
+        reader,
+        &lineitem_schema(),
+    )?))
+}
+
+/// Splits lineitems into columns in `lineitem_schema` order.
+pub fn lineitem_columns(lineitems: &[LineItem]) -> Vec<ColumnValues> {
+    let strings = |f: fn(&LineItem) -> &String| {
+        ColumnValues::Strings(lineitems.iter().map(|x| f(x).clone()).collect())
+    };
+    let decimals =
+        |f: fn(&LineItem) -> f64| ColumnValues::Decimals(lineitems.iter().map(f).collect());
+    vec![
+        strings(|x| &x.l_linestatus),
+        strings(|x| &x.l_returnflag),
+        decimals(|x| x.l_quantity),
+        decimals(|x| x.l_discount),
+        decimals(|x| x.l_tax),
+        decimals(|x| x.l_extendedprice),
+        ColumnValues::Dates(lineitems.iter().map(|x| x.l_shipdate).collect()),
+    ]
 }
 
-pub fn write_row_group<W: Write>(lineitems: &[LineItem], writer: &mut TrackedWriter<W>) {
-    let item_count = (lineitems.len() as u16).to_le_bytes();
-    writer.write_all(&item_count).expect("Failed to write");
-    let lineitems_column = StringColumnReader::new_from_strings(lineitems.iter().map(|x| x.l_linestatus.as_str()).collect());
-    lineitems_column.write(writer);
-    let returnflag_column = StringColumnReader::new_from_strings(lineitems.iter().map(|x| x.l_returnflag.as_str()).collect());
-    returnflag_column.write(writer);
-    write_f64_column(lineitems.iter().map(|x| x.l_quantity), writer);
-    write_f64_column(lineitems.iter().map(|x| x.l_discount), writer);
-    write_f64_column(lineitems.iter().map(|x| x.l_tax), writer);
-    write_f64_column(lineitems.iter().map(|x| x.l_extendedprice), writer);
+pub fn lineitems_from_columns(columns: Vec<ColumnValues>) -> Vec<LineItem> {
+    let mut columns = columns.into_iter();
+    let mut strings = || match columns.next() {
+        Some(ColumnValues::Strings(values)) => values,
+        _ => panic!("Columns do not match the lineitem schema"),
+    };
+    let linestatus = strings();
+    let returnflag = strings();
+    let mut decimals = || match columns.next() {
+        Some(ColumnValues::Decimals(values)) => values,
+        _ => panic!("Columns do not match the lineitem schema"),
+    };
+    let quantity = decimals();
+    let discount = decimals();
+    let tax = decimals();
+    let extendedprice = decimals();
+    let shipdate = match columns.next() {
+        Some(ColumnValues::Dates(values)) => values,
+        _ => panic!("Columns do not match the lineitem schema"),
+    };
+    (0..quantity.len())
+        .map(|i| LineItem {
+            l_returnflag: returnflag[i].clone(),
+            l_linestatus: linestatus[i].clone(),
+            l_quantity: quantity[i],
+            l_extendedprice: extendedprice[i],
+            l_discount: discount[i],
+            l_tax: tax[i],
+            l_shipdate: shipdate[i],
+        })
+        .collect()
 }
 
 pub struct TrackedWriter<W: Write> {
@@ -210,8 +872,7 @@ impl<W: Write> TrackedWriter<W> {
         }
     }
 
-    #[allow(dead_code)]
-    fn bytes_written(&self) -> usize {
+    pub fn bytes_written(&self) -> usize {
         self.bytes_written
     }
 }
@@ -233,3 +894,128 @@ impl<W: Write> TrackedWriter<W> {
         self.writer
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{lineitems, TempColumnFile};
+
+    #[test]
+    fn test_query_1_column_with_latin_1_flags() {
+        // Char columns hold U+0000 to U+00FF, stored as one byte
+        let mut lineitems = lineitems();
+        for lineitem in lineitems.iter_mut().step_by(5) {
+            lineitem.l_returnflag = "é".to_string();
+        }
+        let file = TempColumnFile::new("query_1_latin_1", &lineitems);
+
+        let rows = query_1_column(file.path(), i32::MAX).unwrap();
+
+        let flags: Vec<&str> = rows.iter().map(|x| x.l_returnflag.as_str()).collect();
+        assert_eq!(flags, ["A", "A", "N", "N", "R", "R", "é", "é"]);
+        let count: u64 = rows
+            .iter()
+            .filter(|x| x.l_returnflag == "é")
+            .map(|x| x.count_order)
+            .sum();
+        assert_eq!(count, lineitems.len() as u64 / 5);
+    }
+
+    #[test]
+    fn test_query_1_column_over_many_short_runs() {
+        // Flags and statuses change every few rows while the values repeat
+        // in runs of five, so the value columns are run length encoded
+        let mut lineitems = lineitems();
+        for (i, lineitem) in lineitems.iter_mut().enumerate() {
+            lineitem.l_returnflag = ["A", "N", "R"][i / 2 % 3].to_string();
+            lineitem.l_linestatus = ["F", "O"][i / 7 % 2].to_string();
+            lineitem.l_quantity = (i / 5 % 50) as f64;
+            lineitem.l_tax = (i / 5 % 9) as f64 / 100.0;
+        }
+        let file = TempColumnFile::new("query_1_short_runs", &lineitems);
+
+        let rows = query_1_column(file.path(), i32::MAX).unwrap();
+
+        let cents = |x: f64| f64_column::to_unscaled(x, 2).unwrap() as i128;
+        for row in &rows {
+            let group: Vec<&LineItem> = lineitems
+                .iter()
+                .filter(|x| {
+                    x.l_returnflag == row.l_returnflag && x.l_linestatus == row.l_linestatus
+                })
+                .collect();
+            let sum_qty: i128 = group.iter().map(|x| cents(x.l_quantity)).sum();
+            let sum_charge: i128 = group
+                .iter()
+                .map(|x| {
+                    cents(x.l_extendedprice)
+                        * (100 - cents(x.l_discount))
+                        * (100 + cents(x.l_tax))
+                })
+                .sum();
+            assert_eq!(row.count_order, group.len() as u64);
+            assert_eq!(row.sum_qty, Decimal::new(sum_qty, 2));
+            assert_eq!(row.sum_charge, Decimal::new(sum_charge, 6));
+        }
+        assert_eq!(rows.len(), 6);
+    }
+
+    #[test]
+    fn test_query_1_result_scales_each_sum_by_its_product() {
+        let mut state = vec![None; 256 * 256];
+        state[get_state_index(&b'A', &b'F')] = Some(QueryOneStateColumn {
+            count: 2,
+            sum_qty: 300,
+            sum_base_price: 2000,
+            sum_discount: 10,
+            sum_tax: 4,
+            sum_disc_price: 190000,
+            sum_charge: 19380000,
+        });
+
+        let rows = query_1_result(&state);
+
+        assert_eq!(rows.len(), 1);
+        let row = &rows[0];
+        assert_eq!(row.sum_qty, Decimal::new(300, 2));
+        assert_eq!(row.sum_base_price, Decimal::new(2000, 2));
+        assert_eq!(row.sum_disc_price, Decimal::new(190000, 4));
+        assert_eq!(row.sum_charge, Decimal::new(19380000, 6));
+        assert_eq!(row.avg_qty, 1.5);
+        assert_eq!(row.avg_price, 10.0);
+        assert_eq!(row.avg_disc, 0.05);
+    }
+
+    #[test]
+    fn test_query_6_column_matches_row_by_row_sum() {
+        let lineitems = lineitems();
+        let file = TempColumnFile::new("query_6", &lineitems);
+        let cents = |x: f64| f64_column::to_unscaled(x, 2).unwrap() as i128;
+        let expected = |params: &QuerySixParams| {
+            let shipdate_end = date_column::parse_date("1995-01-01").unwrap();
+            let discount = cents(params.discount);
+            let revenue: i128 = lineitems
+                .iter()
+                .filter(|x| x.l_shipdate >= params.shipdate && x.l_shipdate < shipdate_end)
+                .filter(|x| (discount - 1..=discount + 1).contains(&cents(x.l_discount)))
+                .filter(|x| x.l_quantity < params.quantity)
+                .map(|x| cents(x.l_extendedprice) * cents(x.l_discount))
+                .sum();
+            Decimal::new(revenue, 4)
+        };
+        let validation = QuerySixParams::default();
+        let low_discount = QuerySixParams {
+            discount: 0.01,
+            quantity: 10.0,
+            ..validation
+        };
+
+        let results = [
+            query_6_column(file.path(), &validation).unwrap(),
+            query_6_column(file.path(), &low_discount).unwrap(),
+        ];
+
+        assert_eq!(results, [expected(&validation), expected(&low_discount)]);
+        assert!(results.iter().all(|x| x.unscaled > 0));
+    }
+}
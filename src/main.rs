@@ -6,8 +6,8 @@ use std::{
 mod deltaread;
 
 use abdb::*;
-use clap::{command, Parser, Subcommand};
-use datafusion::arrow::array::{Float64Array, StringDictionaryBuilder};
+use clap::{Parser, Subcommand};
+use datafusion::arrow::array::{Date32Array, Float64Array, StringDictionaryBuilder};
 use datafusion::arrow::record_batch::RecordBatch;
 use datafusion::parquet::arrow::ArrowWriter;
 use datafusion::parquet::basic::Compression;
@@ -23,6 +23,9 @@ use duckdb::{Connection, Row};
 use std::sync::Arc;
 
 mod tests;
+#[cfg(test)]
+#[path = "test_data.rs"]
+mod test_data;
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
@@ -33,12 +36,53 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     WriteLineItems,
-    WriteLineItemsColumn,
+    WriteLineItemsColumn {
+        /// Most rows per row group, up to MAX_ROW_GROUP_SIZE
+        #[arg(long, default_value_t = DEFAULT_ROW_GROUP_SIZE)]
+        row_group_size: usize,
+    },
     WriteLineItemsParquet,
+    /// Copy the parquet export into the Delta table read by RunQuery*Delta
+    WriteLineItemsDelta,
     RunQuery1,
-    RunQuery1Column,
+    RunQuery1Column {
+        /// Read a headerless file written before the format was versioned
+        #[arg(long)]
+        legacy: bool,
+        /// Only aggregate rows with l_shipdate on or before this YYYY-MM-DD date
+        #[arg(long, default_value = QUERY_1_SHIPDATE_CUTOFF)]
+        shipdate: String,
+        /// Worker threads scanning row groups; legacy files use one thread
+        #[arg(long, default_value_t = default_threads())]
+        threads: usize,
+        /// Scan the memory-mapped file in place instead of reading it
+        #[arg(long, conflicts_with = "legacy")]
+        mmap: bool,
+    },
     RunQuery1Parquet,
     RunQuery1Delta,
+    /// Run Query 1 through DataFusion on the column file
+    RunQuery1Datafusion,
+    RunQuery6Column {
+        /// First l_shipdate of the one-year YYYY-MM-DD range
+        #[arg(long, default_value = QUERY_6_SHIPDATE)]
+        shipdate: String,
+        /// Centre of the l_discount range, which spans +-0.01
+        #[arg(long, default_value_t = 0.06)]
+        discount: f64,
+        /// Only rows with l_quantity below this
+        #[arg(long, default_value_t = 24.0)]
+        quantity: f64,
+    },
+    RunQuery6Parquet,
+    RunQuery6Delta,
+    RunQuery6Duckdb,
+    /// Run a SQL query on a column file with the native engine
+    Query {
+        sql: String,
+        #[arg(long, default_value = "lineitems_column.bin")]
+        file: String,
+    },
     ReadFile,
 }
 
@@ -102,7 +146,7 @@ fn query_1() {
 fn print_state(state: [Option<QueryOneState>; 256 * 256]) {
     for i in 0..256 {
         for j in 0..256 {
-            if state[i * 256 + j] != None {
+            if state[i * 256 + j].is_some() {
                 let l_returnflag = String::from_utf8(vec![i as u8]).unwrap();
                 let l_linestatus = String::from_utf8(vec![j as u8]).unwrap();
                 println!(
@@ -124,6 +168,7 @@ fn lineitem_from_row(row: &Row) -> LineItem {
         l_extendedprice: row.get(3).unwrap(),
         l_discount: row.get(4).unwrap(),
         l_tax: row.get(5).unwrap(),
+        l_shipdate: row.get(6).unwrap(),
     }
 }
 
@@ -132,21 +177,31 @@ pub struct QueryResult<'a> {
 }
 
 impl<'a> QueryResult<'a> {
-    fn new(conn: &'a Connection) -> Result<QueryResult<'a>, duckdb::Error> {
-        let stmt = conn.prepare("SELECT l_returnflag, l_linestatus, l_quantity, l_extendedprice, l_discount, l_tax FROM lineitem where l_shipdate <= CAST('1998-09-02' AS date)")?;
+    /// Selects lineitems, keeping only rows shipped on or before
+    /// `shipdate_cutoff` when one is given. l_shipdate is returned as days
+    /// since 1970-01-01.
+    fn new(
+        conn: &'a Connection,
+        shipdate_cutoff: Option<&str>,
+    ) -> Result<QueryResult<'a>, duckdb::Error> {
+        let mut sql = String::from("SELECT l_returnflag, l_linestatus, l_quantity, l_extendedprice, l_discount, l_tax, CAST(l_shipdate - DATE '1970-01-01' AS INTEGER) FROM lineitem");
+        if let Some(cutoff) = shipdate_cutoff {
+            sql.push_str(&format!(" where l_shipdate <= CAST('{}' AS date)", cutoff));
+        }
+        let stmt = conn.prepare(&sql)?;
         Ok(QueryResult { stmt })
     }
 
     fn iter_records(
         &'a mut self,
     ) -> Result<impl Iterator<Item = Result<LineItem, duckdb::Error>> + 'a, duckdb::Error> {
-        Ok(self.stmt.query_map([], |row| Ok(lineitem_from_row(row)))?)
+        self.stmt.query_map([], |row| Ok(lineitem_from_row(row)))
     }
 }
 
 fn save_data() {
     let conn = duckdb::Connection::open("db").unwrap();
-    let mut result = QueryResult::new(&conn).unwrap();
+    let mut result = QueryResult::new(&conn, Some(QUERY_1_SHIPDATE_CUTOFF)).unwrap();
 
     let file = std::fs::File::create("lineitems.bin").expect("Failed to create file");
     let mut writer = std::io::BufWriter::new(file);
@@ -160,7 +215,7 @@ fn save_data() {
 fn write_line_item(writer: &mut std::io::BufWriter<std::fs::File>, lineitem: LineItem) {
     let ls_byte: u8 = lineitem.l_linestatus.as_bytes()[0];
     writer
-        .write(&[lineitem.l_returnflag.as_bytes()[0], ls_byte])
+        .write_all(&[lineitem.l_returnflag.as_bytes()[0], ls_byte])
         .expect("Failed to write");
     writer
         .write_all(&compress_f64(lineitem.l_quantity).to_le_bytes())
@@ -181,29 +236,75 @@ fn main() {
     let cli = Cli::parse();
 
     match &cli.command {
-        Some(Commands::WriteLineItems {}) => {
+        Some(Commands::WriteLineItems) => {
             save_data();
         }
-        Some(Commands::WriteLineItemsColumn {}) => {
-            save_data_column();
+        Some(Commands::WriteLineItemsColumn { row_group_size }) => {
+            save_data_column(*row_group_size);
         }
-        Some(Commands::WriteLineItemsParquet {}) => {
+        Some(Commands::WriteLineItemsParquet) => {
             //save_data_parquet();
             save_data_parquet_with_dictionary();
         }
-        Some(Commands::RunQuery1Column {}) => {
-            query_1_column();
+        Some(Commands::WriteLineItemsDelta) => {
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(deltaread::save_delta(PARQUET_FILE));
+        }
+        Some(Commands::RunQuery1Column {
+            legacy,
+            shipdate,
+            threads,
+            mmap,
+        }) => {
+            run_query_1_column(*legacy, shipdate, *threads, *mmap);
         }
         Some(Commands::RunQuery1Parquet) => {
             tokio::runtime::Runtime::new()
                 .unwrap()
-                .block_on(query_1_column_parquet());
+                .block_on(query_column_parquet(QUERY1_SQL));
         }
         Some(Commands::RunQuery1Delta) => {
             tokio::runtime::Runtime::new()
                 .unwrap()
-                .block_on(deltaread::query_1_delta(QUERY1_SQL));
+                .block_on(deltaread::query_delta(QUERY1_SQL));
+        }
+        Some(Commands::RunQuery1Datafusion) => {
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(query_column_datafusion(QUERY1_SQL));
+        }
+        Some(Commands::RunQuery6Column {
+            shipdate,
+            discount,
+            quantity,
+        }) => {
+            run_query_6_column(shipdate, *discount, *quantity);
+        }
+        Some(Commands::RunQuery6Parquet) => {
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(query_column_parquet(QUERY6_SQL));
         }
+        Some(Commands::RunQuery6Delta) => {
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(deltaread::query_delta(QUERY6_SQL));
+        }
+        Some(Commands::RunQuery6Duckdb) => {
+            let conn = duckdb::Connection::open("db").unwrap();
+            println!(
+                "revenue: {}",
+                query_6_duckdb(&conn).expect("Failed to execute query")
+            );
+        }
+        Some(Commands::Query { sql, file }) => match query::query_file(file, sql) {
+            Ok(output) => print!("{}", output),
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                std::process::exit(1);
+            }
+        },
         Some(Commands::RunQuery1) => {
             query_1();
         }
@@ -216,36 +317,101 @@ fn main() {
     //query_1();
 }
 
-fn query_1_column() {
-    let state = abdb::query_1_column("lineitems_column.bin");
-    print_state_column(state);
+fn default_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |x| x.get())
+}
+
+fn run_query_1_column(legacy: bool, shipdate: &str, threads: usize, mmap: bool) {
+    let shipdate_cutoff = date_column::parse_date(shipdate)
+        .unwrap_or_else(|| panic!("Invalid --shipdate {}, expected YYYY-MM-DD", shipdate));
+    let result = if legacy {
+        abdb::query_1_column_with_mode(
+            "lineitems_column.bin",
+            header::FileMode::Legacy,
+            shipdate_cutoff,
+        )
+    } else if mmap {
+        abdb::query_1_column_mapped("lineitems_column.bin", shipdate_cutoff, threads)
+    } else {
+        abdb::query_1_column_parallel("lineitems_column.bin", shipdate_cutoff, threads)
+    };
+    match result {
+        Ok(rows) => print_query_1(&rows),
+        Err(e) => {
+            eprintln!("lineitems_column.bin: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_query_6_column(shipdate: &str, discount: f64, quantity: f64) {
+    let params = QuerySixParams {
+        shipdate: date_column::parse_date(shipdate)
+            .unwrap_or_else(|| panic!("Invalid --shipdate {}, expected YYYY-MM-DD", shipdate)),
+        discount,
+        quantity,
+    };
+    match abdb::query_6_column("lineitems_column.bin", &params) {
+        Ok(revenue) => print_query_6(&revenue),
+        Err(e) => {
+            eprintln!("lineitems_column.bin: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Runs Query 6 on DuckDB, returning the exact decimal revenue as text.
+fn query_6_duckdb(conn: &Connection) -> Result<String, duckdb::Error> {
+    let sql = format!("SELECT CAST(revenue AS VARCHAR) FROM ({})", QUERY6_SQL);
+    conn.query_row(&sql, [], |row| row.get(0))
 }
 
-fn save_data_column() {
+fn save_data_column(row_group_size: usize) {
+    if !(1..=MAX_ROW_GROUP_SIZE).contains(&row_group_size) {
+        eprintln!(
+            "--row-group-size must be between 1 and {}, got {}",
+            MAX_ROW_GROUP_SIZE, row_group_size
+        );
+        std::process::exit(1);
+    }
     let conn = duckdb::Connection::open("db").unwrap();
-    let mut result = QueryResult::new(&conn).unwrap();
-    let file = std::fs::File::create("lineitems_column.bin").expect("Failed to create file");
-    let mut writer = TrackedWriter::new(std::io::BufWriter::new(file));
-    let mut batch = Vec::with_capacity(8000);
+    let mut result = QueryResult::new(&conn, None).unwrap();
     println!("save_data_column");
+    let lineitems = result.iter_records().unwrap().map(|x| x.unwrap());
+    if let Err(e) = write_column_file("lineitems_column.bin", lineitems, row_group_size) {
+        eprintln!("lineitems_column.bin: {}", e);
+        std::process::exit(1);
+    }
+}
 
-    for row_result in result.iter_records().unwrap() {
-        let lineitem = row_result.unwrap();
+fn write_column_file(
+    path: &str,
+    lineitems: impl Iterator<Item = LineItem>,
+    row_group_size: usize,
+) -> error::Result<()> {
+    let file = std::fs::File::create(path)?;
+    let mut writer = column_file::ColumnFileWriter::new(file).with_row_group_size(row_group_size);
+    let mut batch = Vec::with_capacity(row_group_size);
+
+    for lineitem in lineitems {
         batch.push(lineitem);
 
-        if batch.len() == 8000 {
-            write_batch(&mut writer, &mut batch);
+        if batch.len() == row_group_size {
+            writer.write_batch(&mut batch)?;
             batch.clear();
         }
     }
 
     if !batch.is_empty() {
-        write_batch(&mut writer, &mut batch);
+        writer.write_batch(&mut batch)?;
     }
+    writer.finish()?;
+    Ok(())
 }
 
+// Every source holds all the rows, so the cutoff is applied in the query
 const QUERY1_SQL: &str = "
-        SELECT 
+        SELECT
             l_returnflag,
             l_linestatus,
             COUNT(*) as count,
@@ -257,23 +423,33 @@ const QUERY1_SQL: &str = "
             AVG(l_extendedprice) as avg_price,
             AVG(l_discount) as avg_disc
         FROM lineitem
+        WHERE l_shipdate <= DATE '1998-09-02'
         GROUP BY l_returnflag, l_linestatus
         ORDER BY l_returnflag, l_linestatus";
 
-async fn query_1_column_parquet() {
+// The validation parameters, with the discount range written out so engines
+// reading discounts as floats compare against the same bounds
+const QUERY6_SQL: &str = "
+        SELECT
+            SUM(l_extendedprice * l_discount) as revenue
+        FROM lineitem
+        WHERE l_shipdate >= DATE '1994-01-01'
+            AND l_shipdate < DATE '1995-01-01'
+            AND l_discount BETWEEN 0.05 AND 0.07
+            AND l_quantity < 24";
+
+// Written by WriteLineItemsParquet
+const PARQUET_FILE: &str = "lineitems_with_dictionary.parquet";
+
+async fn query_column_parquet(sql: &str) {
     let ctx = SessionContext::new();
 
     // Register the parquet file as a table
-    ctx.register_parquet(
-        "lineitem",
-        //"lineitems_with_dictionary.parquet",
-        "lineitems_with_dictionary_orig.parquet",
-        ParquetReadOptions::default(),
-    )
+    ctx.register_parquet("lineitem", PARQUET_FILE, ParquetReadOptions::default())
     .await
     .expect("Failed to register parquet file");
 
-    let df = ctx.sql(QUERY1_SQL).await.expect("Failed to execute query");
+    let df = ctx.sql(sql).await.expect("Failed to execute query");
     let results = df.collect().await.expect("Failed to collect results");
 
     // Print results
@@ -282,9 +458,24 @@ async fn query_1_column_parquet() {
     }
 }
 
+async fn query_column_datafusion(sql: &str) {
+    let ctx = SessionContext::new();
+    let table = table_provider::AbdbTable::open("lineitems_column.bin")
+        .expect("Failed to open column file");
+    ctx.register_table("lineitem", Arc::new(table))
+        .expect("Failed to register column file");
+
+    let df = ctx.sql(sql).await.expect("Failed to execute query");
+    let results = df.collect().await.expect("Failed to collect results");
+
+    for batch in results {
+        println!("{:?}", batch);
+    }
+}
+
 fn save_data_parquet_with_dictionary() {
     let conn = duckdb::Connection::open("db").unwrap();
-    let mut result = QueryResult::new(&conn).unwrap();
+    let mut result = QueryResult::new(&conn, None).unwrap();
 
     let schema = Arc::new(Schema::new(vec![
         Field::new(
@@ -301,6 +492,7 @@ fn save_data_parquet_with_dictionary() {
         Field::new("l_extendedprice", DataType::Float64, false),
         Field::new("l_discount", DataType::Float64, false),
         Field::new("l_tax", DataType::Float64, false),
+        Field::new("l_shipdate", DataType::Date32, false),
     ]));
 
     let returnflag_col = ColumnPath::new(vec![String::from("l_returnflag")]);
@@ -312,8 +504,7 @@ fn save_data_parquet_with_dictionary() {
         .set_column_dictionary_enabled(linestatus_col, true)
         .build();
 
-    let file =
-        std::fs::File::create("lineitems_with_dictionary.parquet").expect("Failed to create file");
+    let file = std::fs::File::create(PARQUET_FILE).expect("Failed to create file");
     let mut writer = ArrowWriter::try_new(file, schema.clone(), Some(writer_properties))
         .expect("Failed to create writer");
 
@@ -325,6 +516,7 @@ fn save_data_parquet_with_dictionary() {
     let mut l_extendedprice = Vec::new();
     let mut l_discount = Vec::new();
     let mut l_tax = Vec::new();
+    let mut l_shipdate = Vec::new();
 
     let batch_size = 2048;
 
@@ -336,6 +528,7 @@ fn save_data_parquet_with_dictionary() {
         l_extendedprice.push(lineitem.l_extendedprice);
         l_discount.push(lineitem.l_discount);
         l_tax.push(lineitem.l_tax);
+        l_shipdate.push(lineitem.l_shipdate);
 
         if l_quantity.len() == batch_size {
             write_parquet_batch_with_dictionary(
@@ -347,11 +540,13 @@ fn save_data_parquet_with_dictionary() {
                 l_extendedprice,
                 l_discount,
                 l_tax,
+                l_shipdate,
             );
             l_quantity = Vec::new();
             l_extendedprice = Vec::new();
             l_discount = Vec::new();
             l_tax = Vec::new();
+            l_shipdate = Vec::new();
         }
     }
 
@@ -364,11 +559,13 @@ fn save_data_parquet_with_dictionary() {
         l_extendedprice,
         l_discount,
         l_tax,
+        l_shipdate,
     );
     writer.close().expect("Failed to close writer");
     println!("Done writing parquet file");
 }
 
+#[allow(clippy::too_many_arguments)]
 fn write_parquet_batch_with_dictionary(
     schema: Arc<Schema>,
     writer: &mut ArrowWriter<std::fs::File>,
@@ -378,6 +575,7 @@ fn write_parquet_batch_with_dictionary(
     l_extendedprice: Vec<f64>,
     l_discount: Vec<f64>,
     l_tax: Vec<f64>,
+    l_shipdate: Vec<i32>,
 ) {
     let l_returnflag = l_returnflag_builder.finish();
     let l_linestatus = l_linestatus_builder.finish();
@@ -391,6 +589,7 @@ fn write_parquet_batch_with_dictionary(
             Arc::new(Float64Array::from(l_extendedprice)),
             Arc::new(Float64Array::from(l_discount)),
             Arc::new(Float64Array::from(l_tax)),
+            Arc::new(Date32Array::from(l_shipdate)),
         ],
     )
     .expect("Failed to create record batch");
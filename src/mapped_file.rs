@@ -0,0 +1,235 @@
+use std::io::Cursor;
+#[cfg(target_endian = "big")]
+use std::sync::OnceLock;
+
+use memmap2::Mmap;
+
+use crate::column_file::{Footer, RowGroupMeta};
+use crate::error::{Error, Result};
+#[cfg(target_endian = "big")]
+use crate::f64_column::DecimalColumn;
+use crate::f64_column::DecimalColumnRef;
+use crate::header::FileHeader;
+use crate::predicate::Predicate;
+use crate::schema::{LogicalType, Schema};
+#[cfg(target_endian = "big")]
+use crate::string_column::StringColumnReader;
+use crate::string_column::StringColumnRef;
+
+/// A column file mapped into memory. Column chunks are parsed in place:
+/// packed decimals and string run lengths are borrowed from the mapping
+/// rather than copied, so repeated scans are served from the page cache.
+///
+/// The writer pads those arrays to their natural alignment within the file,
+/// and the mapping starts on a page boundary, so they can be viewed as typed
+/// slices directly. The arrays are little-endian, so on big-endian targets
+/// each chunk is instead decoded into a copy the first time it is read.
+pub struct MappedColumnFile {
+    map: Mmap,
+    header: FileHeader,
+    footer: Footer,
+    // Decoded chunks by row group and column
+    #[cfg(target_endian = "big")]
+    decoded: Vec<Vec<OnceLock<DecodedChunk>>>,
+}
+
+#[cfg(target_endian = "big")]
+enum DecodedChunk {
+    String(StringColumnReader),
+    Decimal(DecimalColumn),
+}
+
+impl MappedColumnFile {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the mapping is only read. Column files are written once
+        // and not modified while they are being queried.
+        let map = unsafe { Mmap::map(&file)? };
+        let header = FileHeader::read(&mut &map[..])?;
+        let footer = Footer::read(&mut Cursor::new(&map[..]))?;
+        footer.validate(&header.schema)?;
+        Ok(MappedColumnFile {
+            #[cfg(target_endian = "big")]
+            decoded: footer
+                .row_groups
+                .iter()
+                .map(|x| x.columns.iter().map(|_| OnceLock::new()).collect())
+                .collect(),
+            map,
+            header,
+            footer,
+        })
+    }
+
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.header.schema
+    }
+
+    pub fn row_groups(&self) -> &[RowGroupMeta] {
+        &self.footer.row_groups
+    }
+
+    /// See `Footer::matching_row_groups`.
+    pub fn matching_row_groups(&self, predicates: &[Predicate]) -> Result<Vec<usize>> {
+        self.footer
+            .matching_row_groups(&self.header.schema, predicates)
+    }
+
+    /// The bytes of column `column` within row group `row_group`.
+    pub fn column_chunk(&self, row_group: usize, column: usize) -> Result<&[u8]> {
+        let chunk = &self.footer.row_groups[row_group].columns[column];
+        usize::try_from(chunk.offset)
+            .ok()
+            .zip(usize::try_from(chunk.length).ok())
+            .and_then(|(offset, length)| self.map.get(offset..offset.checked_add(length)?))
+            .ok_or_else(|| {
+                Error::corrupt(format!(
+                    "column {} of row group {} lies outside the file",
+                    column, row_group
+                ))
+            })
+    }
+
+    pub fn string_column(&self, row_group: usize, column: usize) -> Result<StringColumnRef<'_>> {
+        if self.header.schema.fields[column].logical_type != LogicalType::Char {
+            panic!("Column {} is not a string column", column);
+        }
+        let chunk = self.column_chunk(row_group, column)?;
+        #[cfg(target_endian = "little")]
+        {
+            StringColumnRef::parse(chunk)
+        }
+        #[cfg(target_endian = "big")]
+        {
+            let decoded = self.decoded(row_group, column, || {
+                let mut string_column = StringColumnReader::empty();
+                string_column.read(&mut std::io::BufReader::new(chunk))?;
+                Ok(DecodedChunk::String(string_column))
+            })?;
+            match decoded {
+                DecodedChunk::String(string_column) => Ok(string_column.view()),
+                DecodedChunk::Decimal(_) => unreachable!("Char columns decode to strings"),
+            }
+        }
+    }
+
+    /// Decimal and date columns; dates have scale 0.
+    pub fn decimal_column(&self, row_group: usize, column: usize) -> Result<DecimalColumnRef<'_>> {
+        let scale = match self.header.schema.fields[column].logical_type {
+            LogicalType::Decimal { scale } => scale,
+            LogicalType::Date => 0,
+            _ => panic!("Column {} is not a decimal column", column),
+        };
+        let row_count = self.footer.row_groups[row_group].row_count as usize;
+        let chunk = self.column_chunk(row_group, column)?;
+        #[cfg(target_endian = "little")]
+        {
+            DecimalColumnRef::parse(chunk, scale, row_count)
+        }
+        #[cfg(target_endian = "big")]
+        {
+            let decoded = self.decoded(row_group, column, || {
+                let column = DecimalColumn::read(&mut &chunk[..], scale, row_count)?;
+                Ok(DecodedChunk::Decimal(column))
+            })?;
+            match decoded {
+                DecodedChunk::Decimal(column) => Ok(column.view()),
+                DecodedChunk::String(_) => unreachable!("decimal columns decode to decimals"),
+            }
+        }
+    }
+
+    // The decoded copy of a chunk, decoding it with `decode` on first use
+    #[cfg(target_endian = "big")]
+    fn decoded(
+        &self,
+        row_group: usize,
+        column: usize,
+        decode: impl FnOnce() -> Result<DecodedChunk>,
+    ) -> Result<&DecodedChunk> {
+        let cell = &self.decoded[row_group][column];
+        if let Some(decoded) = cell.get() {
+            return Ok(decoded);
+        }
+        let decoded = decode()?;
+        Ok(cell.get_or_init(|| decoded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::column_file::ColumnFileReader;
+    use crate::test_data::TempColumnFile;
+    use crate::LineItem;
+
+    fn write_file(name: &str) -> TempColumnFile {
+        let lineitems: Vec<LineItem> = (0..2500)
+            .map(|i| LineItem {
+                l_returnflag: ["A", "N", "R"][i % 3].to_string(),
+                l_linestatus: "O".to_string(),
+                l_quantity: (i % 50) as f64,
+                l_extendedprice: 100000.0 + i as f64,
+                l_discount: 0.05,
+                l_tax: (i % 8) as f64 / 100.0,
+                l_shipdate: 9000 + i as i32,
+            })
+            .collect();
+        TempColumnFile::with_row_group_size(name, &lineitems, 1000)
+    }
+
+    #[test]
+    fn test_mapped_columns_match_buffered_reads() {
+        let file = write_file("mapped");
+        let mapped = MappedColumnFile::open(file.path()).unwrap();
+        let mut reader = ColumnFileReader::open(file.path()).unwrap();
+
+        assert_eq!(mapped.row_groups(), reader.row_groups());
+        for row_group in 0..mapped.row_groups().len() {
+            let buffered = reader.read_columns(row_group, &[1, 3, 5, 6]).unwrap();
+            assert_eq!(
+                mapped.string_column(row_group, 1).unwrap(),
+                buffered.string_column(1).view()
+            );
+            assert_eq!(
+                mapped.decimal_column(row_group, 3).unwrap(),
+                buffered.decimal_column(3).view()
+            );
+            assert_eq!(
+                mapped.decimal_column(row_group, 5).unwrap(),
+                buffered.decimal_column(5).view()
+            );
+            assert_eq!(
+                mapped.decimal_column(row_group, 6).unwrap(),
+                buffered.date_column(6).view()
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn test_mapped_columns_borrow_the_mapping() {
+        use crate::f64_column::PackedSlice;
+
+        let file = write_file("mapped_borrow");
+        let mapped = MappedColumnFile::open(file.path()).unwrap();
+        let map = mapped.map.as_ptr_range();
+
+        let runs = mapped.string_column(0, 1).unwrap().run_lengths;
+        // Every discount is 0.05, so the column is a constant
+        let discount_rows = mapped.decimal_column(0, 3).unwrap().run_lengths;
+        let quantity = match mapped.decimal_column(0, 2).unwrap().packed {
+            PackedSlice::U16(values) => values,
+            packed => panic!("Expected u16 offsets, got {:?}", packed),
+        };
+
+        assert!(map.contains(&(runs.as_ptr() as *const u8)));
+        assert!(map.contains(&(quantity.as_ptr() as *const u8)));
+        assert_eq!(discount_rows, &[1000]);
+        assert!(map.contains(&(discount_rows.as_ptr() as *const u8)));
+    }
+}
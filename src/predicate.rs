@@ -0,0 +1,264 @@
+use crate::column_file::ColumnStats;
+use crate::f64_column::from_unscaled;
+use crate::schema::LogicalType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Eq,
+    NotEq,
+}
+
+/// A comparison of a column against a constant, e.g. `l_quantity < 25`.
+///
+/// Values are compared in the column's logical domain: decimals as `f64`,
+/// single-byte string columns by their byte value and dates as days since
+/// 1970-01-01.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Predicate {
+    pub column: String,
+    pub op: CompareOp,
+    pub value: f64,
+}
+
+impl Predicate {
+    pub fn new(column: &str, op: CompareOp, value: f64) -> Self {
+        Predicate {
+            column: column.to_string(),
+            op,
+            value,
+        }
+    }
+
+    pub fn lt(column: &str, value: f64) -> Self {
+        Predicate::new(column, CompareOp::Lt, value)
+    }
+
+    pub fn lt_eq(column: &str, value: f64) -> Self {
+        Predicate::new(column, CompareOp::LtEq, value)
+    }
+
+    pub fn gt(column: &str, value: f64) -> Self {
+        Predicate::new(column, CompareOp::Gt, value)
+    }
+
+    pub fn gt_eq(column: &str, value: f64) -> Self {
+        Predicate::new(column, CompareOp::GtEq, value)
+    }
+
+    pub fn eq(column: &str, value: f64) -> Self {
+        Predicate::new(column, CompareOp::Eq, value)
+    }
+
+    /// The stored values of a column of `logical_type` that satisfy the
+    /// predicate. Varchar columns store dictionary codes, not values, so
+    /// they cannot be filtered this way.
+    pub fn value_range(&self, logical_type: LogicalType) -> ValueRange {
+        let scale = match logical_type {
+            LogicalType::Char | LogicalType::Date => 0,
+            LogicalType::Decimal { scale } => scale,
+            LogicalType::Varchar => panic!("Column {} is a varchar column", self.column),
+        };
+        ValueRange::new(self.op, self.value, scale)
+    }
+
+    /// Returns false only when `stats` prove that no value in the column
+    /// chunk can satisfy the predicate.
+    pub fn may_match(&self, stats: &ColumnStats, logical_type: LogicalType) -> bool {
+        if stats.count == 0 {
+            return false;
+        }
+        // Varchar chunks carry no min/max, only a row count
+        if logical_type == LogicalType::Varchar {
+            return true;
+        }
+        let min = logical_value(stats.min, logical_type);
+        let max = logical_value(stats.max, logical_type);
+        match self.op {
+            CompareOp::Lt => min < self.value,
+            CompareOp::LtEq => min <= self.value,
+            CompareOp::Gt => max > self.value,
+            CompareOp::GtEq => max >= self.value,
+            CompareOp::Eq => min <= self.value && self.value <= max,
+            CompareOp::NotEq => !(min == self.value && max == self.value),
+        }
+    }
+}
+
+/// A predicate translated to stored (unscaled) values: those in
+/// `min..=max`, or outside it when `negated`. `min > max` is an empty range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueRange {
+    pub min: i64,
+    pub max: i64,
+    pub negated: bool,
+}
+
+impl ValueRange {
+    /// The unscaled values `v` of a column with `scale` for which
+    /// `v / 10^scale op value` holds, comparing exactly as `may_match` does.
+    pub fn new(op: CompareOp, value: f64, scale: u8) -> Self {
+        // The logical value grows with the stored one, so the matching
+        // values are a range bounded by the first at or above `value` and
+        // the first strictly above it
+        let at_least = first_stored(|x| from_unscaled(x, scale) >= value);
+        let above = first_stored(|x| from_unscaled(x, scale) > value);
+        let range = |min: i128, max: i128| {
+            if min > max {
+                return ValueRange {
+                    min: i64::MAX,
+                    max: i64::MIN,
+                    negated: false,
+                };
+            }
+            ValueRange {
+                min: min as i64,
+                max: max as i64,
+                negated: false,
+            }
+        };
+        match op {
+            CompareOp::Lt => range(i64::MIN as i128, at_least - 1),
+            CompareOp::LtEq => range(i64::MIN as i128, above - 1),
+            CompareOp::Gt => range(above, i64::MAX as i128),
+            CompareOp::GtEq => range(at_least, i64::MAX as i128),
+            CompareOp::Eq => range(at_least, above - 1),
+            CompareOp::NotEq => ValueRange {
+                negated: true,
+                ..range(at_least, above - 1)
+            },
+        }
+    }
+
+    pub fn contains(&self, stored: i64) -> bool {
+        (self.min <= stored && stored <= self.max) != self.negated
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.negated && self.min > self.max
+    }
+}
+
+// The smallest i64 for which `test` holds, or i64::MAX + 1 if there is none.
+// `test` must be monotonic.
+fn first_stored(test: impl Fn(i64) -> bool) -> i128 {
+    let (mut low, mut high) = (i64::MIN as i128, i64::MAX as i128 + 1);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if test(middle as i64) {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    low
+}
+
+fn logical_value(stored: i64, logical_type: LogicalType) -> f64 {
+    match logical_type {
+        LogicalType::Char | LogicalType::Date => stored as f64,
+        LogicalType::Decimal { scale } => from_unscaled(stored, scale),
+        LogicalType::Varchar => unreachable!("Varchar chunks have no zone map"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DECIMAL: LogicalType = LogicalType::Decimal { scale: 2 };
+
+    fn stats(min: i64, max: i64) -> ColumnStats {
+        ColumnStats {
+            min,
+            max,
+            count: 10,
+        }
+    }
+
+    #[test]
+    fn test_may_match_ranges() {
+        // l_quantity between 10.00 and 20.00
+        let quantity = stats(1000, 2000);
+
+        assert!(Predicate::lt("l_quantity", 25.0).may_match(&quantity, DECIMAL));
+        assert!(!Predicate::lt("l_quantity", 10.0).may_match(&quantity, DECIMAL));
+        assert!(Predicate::lt_eq("l_quantity", 10.0).may_match(&quantity, DECIMAL));
+        assert!(!Predicate::gt("l_quantity", 20.0).may_match(&quantity, DECIMAL));
+        assert!(Predicate::gt_eq("l_quantity", 20.0).may_match(&quantity, DECIMAL));
+        assert!(Predicate::eq("l_quantity", 15.5).may_match(&quantity, DECIMAL));
+        assert!(!Predicate::eq("l_quantity", 20.01).may_match(&quantity, DECIMAL));
+    }
+
+    #[test]
+    fn test_may_match_string_column() {
+        let returnflag = stats(b'A' as i64, b'A' as i64);
+
+        assert!(
+            Predicate::eq("l_returnflag", b'A' as f64).may_match(&returnflag, LogicalType::Char)
+        );
+        assert!(
+            !Predicate::new("l_returnflag", CompareOp::NotEq, b'A' as f64)
+                .may_match(&returnflag, LogicalType::Char)
+        );
+    }
+
+    #[test]
+    fn test_may_match_varchar_column_never_prunes() {
+        let shipmode = ColumnStats {
+            min: 0,
+            max: 0,
+            count: 10,
+        };
+
+        assert!(Predicate::eq("l_shipmode", 1.0).may_match(&shipmode, LogicalType::Varchar));
+    }
+
+    #[test]
+    fn test_value_range_uses_decimal_comparison() {
+        let discount = |op, value| ValueRange::new(op, value, 2);
+
+        // 0.07 * 100 is 7.000000000000001 in f64, but 7 still matches
+        assert_eq!(
+            discount(CompareOp::LtEq, 0.07),
+            ValueRange::new(CompareOp::Lt, 0.075, 2)
+        );
+        assert!(discount(CompareOp::LtEq, 0.07).contains(7));
+        assert!(!discount(CompareOp::LtEq, 0.07).contains(8));
+        assert!(discount(CompareOp::GtEq, 0.05).contains(5));
+        assert!(!discount(CompareOp::Gt, 0.05).contains(5));
+        assert_eq!(discount(CompareOp::Eq, 0.05).min, 5);
+        assert_eq!(discount(CompareOp::Eq, 0.05).max, 5);
+        assert!(discount(CompareOp::Eq, 0.055).is_empty());
+        assert!(!discount(CompareOp::NotEq, 0.05).contains(5));
+        assert!(discount(CompareOp::NotEq, 0.05).contains(i64::MIN));
+    }
+
+    #[test]
+    fn test_value_range_at_the_limits() {
+        assert!(ValueRange::new(CompareOp::Lt, -1e300, 0).is_empty());
+        assert!(ValueRange::new(CompareOp::Gt, 1e300, 0).is_empty());
+        assert!(ValueRange::new(CompareOp::Eq, f64::NAN, 0).is_empty());
+        assert!(ValueRange::new(CompareOp::NotEq, f64::NAN, 0).contains(0));
+        let all = ValueRange::new(CompareOp::LtEq, 1e300, 4);
+        assert!(all.contains(i64::MIN) && all.contains(i64::MAX));
+        assert_eq!(
+            Predicate::lt_eq("l_shipdate", 10500.0).value_range(LogicalType::Date),
+            ValueRange::new(CompareOp::LtEq, 10500.0, 0)
+        );
+    }
+
+    #[test]
+    fn test_empty_chunk_never_matches() {
+        let empty = ColumnStats {
+            min: 0,
+            max: 0,
+            count: 0,
+        };
+
+        assert!(!Predicate::gt_eq("l_tax", 0.0).may_match(&empty, DECIMAL));
+    }
+}
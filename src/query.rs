@@ -0,0 +1,1262 @@
+//! Runs the SQL subset of `sql` on a column file.
+//!
+//! Comparisons of a column with a literal among the top-level ANDs of WHERE
+//! become `Predicate`s, which skip row groups by their statistics and filter
+//! the rest with the selection kernels. The remaining conditions and
+//! computed expressions are evaluated row by row. Grouped queries go
+//! through a `HashAggregate`, feeding plain column arguments to the
+//! aggregate functions a run at a time.
+
+use std::any::Any;
+use std::cmp::Ordering;
+use std::fmt;
+use std::io::{Read, Seek};
+
+use crate::aggregate::{
+    boxed, group_runs, init_states, update_from_column, update_from_values, AggregateValue, Avg,
+    Count, CountDistinct, DynAggregateFunction, Max, Min, Sum,
+};
+use crate::column_file::{required_column_index, ColumnFileReader};
+use crate::date_column::format_date;
+use crate::error::{Error, Result};
+use crate::f64_column::{scale_factor, Decimal, MAX_DECIMAL_SCALE};
+use crate::group_by::{for_each_run, GroupValue, HashAggregate};
+use crate::predicate::{CompareOp, Predicate};
+use crate::row_group::char_byte;
+use crate::scan::{ColumnData, ProjectedRowGroup};
+use crate::schema::{LogicalType, Schema};
+use crate::selection::Selection;
+use crate::sql::{self, AggregateKind, BinaryOp, Expr, Literal, Query};
+use crate::varchar_column::VarcharColumn;
+
+/// A value of a query result
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Decimal(Decimal),
+    Float(f64),
+    /// Days since 1970-01-01
+    Date(i32),
+    Char(u8),
+    Str(String),
+    Bool(bool),
+    /// AVG, MIN and MAX of no rows, and division by zero
+    Null,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Decimal(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Date(days) => write!(f, "{}", format_date(*days)),
+            Value::Char(value) => write!(f, "{}", *value as char),
+            Value::Str(value) => write!(f, "{}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Null => write!(f, "NULL"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryOutput {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl fmt::Display for QueryOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.columns.join(", "))?;
+        for row in &self.rows {
+            let values: Vec<String> = row.iter().map(|x| x.to_string()).collect();
+            writeln!(f, "{}", values.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `sql` on the column file at `path`.
+pub fn query_file(path: &str, sql: &str) -> Result<QueryOutput> {
+    execute(&mut ColumnFileReader::open(path)?, sql)
+}
+
+/// Runs `sql` on the file of `reader`, whatever its FROM clause names.
+pub fn execute<R: Read + Seek>(reader: &mut ColumnFileReader<R>, sql: &str) -> Result<QueryOutput> {
+    let query = sql::parse(sql)?;
+    let plan = Plan::new(&query, reader.schema())?;
+    let mut rows = match plan.grouped {
+        true => plan.run_grouped(reader)?,
+        false => plan.run_rows(reader)?,
+    };
+    rows.sort_by(|a, b| {
+        plan.order_by
+            .iter()
+            .map(|&(column, descending)| order(&a[column], &b[column], descending))
+            .find(|x| x.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+    if let Some(limit) = plan.limit {
+        rows.truncate(limit);
+    }
+    Ok(QueryOutput {
+        columns: query.select.iter().map(|x| x.name()).collect(),
+        rows,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Decimal(u8),
+    Float,
+    Date,
+    Char,
+    Str,
+    Bool,
+}
+
+impl Type {
+    fn of(logical_type: LogicalType) -> Self {
+        match logical_type {
+            LogicalType::Char => Type::Char,
+            LogicalType::Decimal { scale } => Type::Decimal(scale),
+            LogicalType::Date => Type::Date,
+            LogicalType::Varchar => Type::Str,
+        }
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Type::Decimal(_) | Type::Float)
+    }
+
+    fn is_text(self) -> bool {
+        matches!(self, Type::Char | Type::Str)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Decimal(scale) => write!(f, "decimal({})", scale),
+            Type::Float => write!(f, "float"),
+            Type::Date => write!(f, "date"),
+            Type::Char => write!(f, "char"),
+            Type::Str => write!(f, "varchar"),
+            Type::Bool => write!(f, "boolean"),
+        }
+    }
+}
+
+// The type of `left op right`, or `None` if the operands do not fit
+fn binary_type(left: Type, op: BinaryOp, right: Type) -> Option<Type> {
+    use Type::*;
+    match op {
+        BinaryOp::And | BinaryOp::Or => (left == Bool && right == Bool).then_some(Bool),
+        op if op.is_comparison() => {
+            let comparable = (left.is_numeric() && right.is_numeric())
+                || (left.is_text() && right.is_text())
+                || left == right;
+            comparable.then_some(Bool)
+        }
+        BinaryOp::Div => (left.is_numeric() && right.is_numeric()).then_some(Float),
+        _ => match (left, right) {
+            (Decimal(a), Decimal(b)) if op == BinaryOp::Mul => {
+                (a + b <= MAX_DECIMAL_SCALE).then_some(Decimal(a + b))
+            }
+            (Decimal(a), Decimal(b)) => Some(Decimal(a.max(b))),
+            (Date, Decimal(0)) if op != BinaryOp::Mul => Some(Date),
+            (Decimal(0), Date) if op == BinaryOp::Add => Some(Date),
+            (Date, Date) if op == BinaryOp::Sub => Some(Decimal(0)),
+            _ => (left.is_numeric() && right.is_numeric()).then_some(Float),
+        },
+    }
+}
+
+/// An expression with its columns resolved, evaluated either for a row of a
+/// row group or, in grouped queries, for a group.
+#[derive(Debug)]
+enum Scalar {
+    /// A column by schema index, read from a row
+    Column(usize),
+    /// A GROUP BY column by position, read from a group
+    Key(usize),
+    /// A finalized aggregate by position, read from a group
+    Aggregate(usize),
+    Literal(Value),
+    Negate(Box<Scalar>),
+    Not(Box<Scalar>),
+    Binary(Box<Scalar>, BinaryOp, Box<Scalar>),
+}
+
+enum Input<'a> {
+    Row {
+        columns: &'a [Option<Decoded<'a>>],
+        row: usize,
+    },
+    Group {
+        key: &'a [Value],
+        aggregates: &'a [Value],
+    },
+}
+
+impl Scalar {
+    fn eval(&self, input: &Input) -> Result<Value> {
+        Ok(match (self, input) {
+            (Scalar::Column(index), Input::Row { columns, row }) => {
+                match columns[*index].as_ref().expect("Row columns are decoded") {
+                    Decoded::Decimal(values, scale) => {
+                        Value::Decimal(Decimal::new(values[*row] as i128, *scale))
+                    }
+                    Decoded::Date(values) => Value::Date(values[*row] as i32),
+                    Decoded::Char(values) => Value::Char(values[*row]),
+                    Decoded::Varchar(column) => Value::Str(column.get(*row).to_string()),
+                }
+            }
+            (Scalar::Key(index), Input::Group { key, .. }) => key[*index].clone(),
+            (Scalar::Aggregate(index), Input::Group { aggregates, .. }) => {
+                aggregates[*index].clone()
+            }
+            (Scalar::Literal(value), _) => value.clone(),
+            (Scalar::Negate(expr), _) => match expr.eval(input)? {
+                Value::Decimal(x) => Value::Decimal(Decimal::new(-x.unscaled, x.scale)),
+                Value::Float(x) => Value::Float(-x),
+                value => value,
+            },
+            (Scalar::Not(expr), _) => match expr.eval(input)? {
+                Value::Bool(x) => Value::Bool(!x),
+                value => value,
+            },
+            (Scalar::Binary(left, op, right), _) => {
+                eval_binary(left.eval(input)?, *op, right.eval(input)?)?
+            }
+            (scalar, _) => unreachable!("{:?} was planned for another input", scalar),
+        })
+    }
+
+    // True if the row satisfies the condition; NULL does not
+    fn matches(&self, input: &Input) -> Result<bool> {
+        Ok(self.eval(input)? == Value::Bool(true))
+    }
+}
+
+fn eval_binary(left: Value, op: BinaryOp, right: Value) -> Result<Value> {
+    let overflow = || Error::InvalidQuery(format!("decimal overflow in {}", op.symbol()));
+    let date_overflow = || Error::InvalidQuery(format!("date out of range in {}", op.symbol()));
+    Ok(match (left, right) {
+        // Three-valued logic: FALSE AND NULL is FALSE and TRUE OR NULL is TRUE
+        (Value::Bool(false), _) | (_, Value::Bool(false)) if op == BinaryOp::And => {
+            Value::Bool(false)
+        }
+        (Value::Bool(true), _) | (_, Value::Bool(true)) if op == BinaryOp::Or => Value::Bool(true),
+        (Value::Null, _) | (_, Value::Null) => Value::Null,
+        (Value::Bool(a), Value::Bool(b)) if op == BinaryOp::And => Value::Bool(a && b),
+        (Value::Bool(a), Value::Bool(b)) if op == BinaryOp::Or => Value::Bool(a || b),
+        (left, right) if op.is_comparison() => {
+            let ordering = compare(&left, &right).expect("Comparisons are typed when planned");
+            Value::Bool(match op {
+                BinaryOp::Eq => ordering.is_eq(),
+                BinaryOp::NotEq => ordering.is_ne(),
+                BinaryOp::Lt => ordering.is_lt(),
+                BinaryOp::LtEq => ordering.is_le(),
+                BinaryOp::Gt => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            })
+        }
+        (Value::Decimal(a), Value::Decimal(b)) if op != BinaryOp::Div => {
+            let (a, b, scale) = match op {
+                BinaryOp::Mul => (a.unscaled, b.unscaled, a.scale + b.scale),
+                _ => {
+                    let scale = a.scale.max(b.scale);
+                    (
+                        rescale(a, scale).ok_or_else(overflow)?,
+                        rescale(b, scale).ok_or_else(overflow)?,
+                        scale,
+                    )
+                }
+            };
+            let unscaled = match op {
+                BinaryOp::Add => a.checked_add(b),
+                BinaryOp::Sub => a.checked_sub(b),
+                _ => a.checked_mul(b),
+            };
+            Value::Decimal(Decimal::new(unscaled.ok_or_else(overflow)?, scale))
+        }
+        (Value::Date(days), Value::Decimal(x)) => {
+            let x = i32::try_from(x.unscaled).map_err(|_| date_overflow())?;
+            Value::Date(
+                match op {
+                    BinaryOp::Add => days.checked_add(x),
+                    _ => days.checked_sub(x),
+                }
+                .ok_or_else(date_overflow)?,
+            )
+        }
+        (Value::Decimal(x), Value::Date(days)) => Value::Date(
+            i32::try_from(x.unscaled)
+                .ok()
+                .and_then(|x| days.checked_add(x))
+                .ok_or_else(date_overflow)?,
+        ),
+        (Value::Date(a), Value::Date(b)) => Value::Decimal(Decimal::new(a as i128 - b as i128, 0)),
+        (left, right) => {
+            let (a, b) = (
+                number(&left).expect("Arithmetic is typed when planned"),
+                number(&right).expect("Arithmetic is typed when planned"),
+            );
+            match op {
+                BinaryOp::Add => Value::Float(a + b),
+                BinaryOp::Sub => Value::Float(a - b),
+                BinaryOp::Mul => Value::Float(a * b),
+                _ if b == 0.0 => Value::Null,
+                _ => Value::Float(a / b),
+            }
+        }
+    })
+}
+
+fn rescale(value: Decimal, scale: u8) -> Option<i128> {
+    value
+        .unscaled
+        .checked_mul(scale_factor(scale - value.scale) as i128)
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Decimal(x) => Some(x.to_f64()),
+        Value::Float(x) => Some(*x),
+        _ => None,
+    }
+}
+
+// Char values are Latin-1, so the byte is the code point
+fn text<'a>(value: &'a Value, buffer: &'a mut [u8; 4]) -> Option<&'a str> {
+    match value {
+        Value::Char(x) => Some((*x as char).encode_utf8(buffer)),
+        Value::Str(x) => Some(x),
+        _ => None,
+    }
+}
+
+// `None` for values of types that cannot be compared
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Decimal(x), Value::Decimal(y)) => {
+            let scale = x.scale.max(y.scale);
+            match (rescale(*x, scale), rescale(*y, scale)) {
+                (Some(x), Some(y)) => Some(x.cmp(&y)),
+                _ => x.to_f64().partial_cmp(&y.to_f64()),
+            }
+        }
+        (Value::Date(x), Value::Date(y)) => Some(x.cmp(y)),
+        (Value::Bool(x), Value::Bool(y)) => Some(x.cmp(y)),
+        _ => match (number(a), number(b)) {
+            (Some(x), Some(y)) => x.partial_cmp(&y),
+            _ => {
+                let (mut x, mut y) = ([0; 4], [0; 4]);
+                Some(text(a, &mut x)?.cmp(text(b, &mut y)?))
+            }
+        },
+    }
+}
+
+// The ORDER BY order of two values of one column, with NULLs last
+fn order(a: &Value, b: &Value, descending: bool) -> Ordering {
+    match (a, b) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => Ordering::Greater,
+        (_, Value::Null) => Ordering::Less,
+        _ => {
+            let ordering = compare(a, b).unwrap_or(Ordering::Equal);
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        }
+    }
+}
+
+/// A projected column decoded for evaluating expressions row by row
+enum Decoded<'a> {
+    Decimal(Vec<i64>, u8),
+    Date(Vec<i64>),
+    Char(Vec<u8>),
+    Varchar(&'a VarcharColumn),
+}
+
+fn decode<'a>(
+    row_group: &'a ProjectedRowGroup,
+    schema: &Schema,
+    columns: &[usize],
+) -> Result<Vec<Option<Decoded<'a>>>> {
+    let mut decoded: Vec<Option<Decoded>> = (0..schema.len()).map(|_| None).collect();
+    for &index in columns {
+        decoded[index] = Some(match &row_group.columns[index] {
+            Some(ColumnData::String(column)) => {
+                let mut values = Vec::new();
+                for_each_run(column, row_group.row_count as usize, |value, run| {
+                    values.resize(run.end, value)
+                })?;
+                Decoded::Char(values)
+            }
+            Some(ColumnData::Decimal(column)) => match schema.fields[index].logical_type {
+                LogicalType::Decimal { scale } => Decoded::Decimal(column.to_i64s(), scale),
+                _ => unreachable!("Decimal data is read for decimal columns"),
+            },
+            Some(ColumnData::Date(column)) => Decoded::Date(column.to_i64s()),
+            Some(ColumnData::Varchar(column)) => Decoded::Varchar(column),
+            None => panic!("Column {} was not projected", index),
+        });
+    }
+    Ok(decoded)
+}
+
+enum AggregateInput {
+    /// Every row, for COUNT
+    Rows,
+    /// A char, decimal or date column by schema index
+    Column(usize),
+    /// An expression evaluated for every selected row
+    Expr(Scalar),
+}
+
+struct AggregatePlan {
+    expr: Expr,
+    function: Box<dyn DynAggregateFunction>,
+    input: AggregateInput,
+    /// Scale of the input values
+    scale: u8,
+    result_type: Type,
+}
+
+impl AggregatePlan {
+    fn finalize(&self, state: &dyn Any) -> Value {
+        match self.function.finalize(state, self.scale) {
+            AggregateValue::Decimal(x) => match self.result_type {
+                Type::Date => Value::Date(x.unscaled as i32),
+                Type::Char => Value::Char(x.unscaled as u8),
+                _ => Value::Decimal(x),
+            },
+            AggregateValue::Count(x) => Value::Decimal(Decimal::new(x as i128, 0)),
+            AggregateValue::Float(x) => Value::Float(x),
+            AggregateValue::Null => Value::Null,
+        }
+    }
+}
+
+struct Plan {
+    schema: Schema,
+    /// Pushed down to the zone maps and selection kernels
+    predicates: Vec<Predicate>,
+    /// The rest of the WHERE conditions, all of which must hold
+    residual: Vec<Scalar>,
+    grouped: bool,
+    key_columns: Vec<usize>,
+    aggregates: Vec<AggregatePlan>,
+    /// Row expressions, or group expressions in grouped queries
+    select: Vec<Scalar>,
+    /// Columns decoded for row expressions
+    row_columns: Vec<usize>,
+    projection: Vec<usize>,
+    /// Output column and whether it is descending
+    order_by: Vec<(usize, bool)>,
+    limit: Option<usize>,
+}
+
+impl Plan {
+    fn new(query: &Query, schema: &Schema) -> Result<Self> {
+        let grouped =
+            !query.group_by.is_empty() || query.select.iter().any(|x| x.expr.contains_aggregate());
+        let mut plan = Plan {
+            schema: schema.clone(),
+            predicates: Vec::new(),
+            residual: Vec::new(),
+            grouped,
+            key_columns: Vec::new(),
+            aggregates: Vec::new(),
+            select: Vec::new(),
+            row_columns: Vec::new(),
+            projection: Vec::new(),
+            order_by: Vec::new(),
+            limit: query.limit,
+        };
+
+        for conjunct in query.filter.iter().flat_map(|x| x.conjuncts()) {
+            match pushdown(schema, conjunct) {
+                Some(predicate) => plan.predicates.push(predicate),
+                None => {
+                    let (condition, condition_type) = plan.compile(conjunct, false)?;
+                    if condition_type != Type::Bool {
+                        return Err(Error::InvalidQuery(format!(
+                            "WHERE {} is a {}, not a condition",
+                            conjunct, condition_type
+                        )));
+                    }
+                    plan.residual.push(condition);
+                }
+            }
+        }
+        for key in &query.group_by {
+            let Expr::Column(name) = key else {
+                return Err(Error::InvalidQuery(format!(
+                    "GROUP BY {} is not a column",
+                    key
+                )));
+            };
+            plan.key_columns.push(required_column_index(schema, name)?);
+        }
+        for item in &query.select {
+            let (scalar, _) = plan.compile(&item.expr, grouped)?;
+            plan.select.push(scalar);
+        }
+        for order_by in &query.order_by {
+            plan.order_by
+                .push((output_column(query, &order_by.expr)?, order_by.descending));
+        }
+
+        let aggregate_columns = plan.aggregates.iter().filter_map(|x| match x.input {
+            AggregateInput::Column(column) => Some(column),
+            _ => None,
+        });
+        let predicate_columns = plan
+            .predicates
+            .iter()
+            .map(|x| required_column_index(schema, &x.column))
+            .collect::<Result<Vec<_>>>()?;
+        let mut projection: Vec<usize> = plan
+            .key_columns
+            .iter()
+            .chain(&plan.row_columns)
+            .copied()
+            .chain(aggregate_columns)
+            .chain(predicate_columns)
+            .collect();
+        projection.sort_unstable();
+        projection.dedup();
+        plan.projection = projection;
+        Ok(plan)
+    }
+
+    /// Resolves `expr` for evaluation per group when `grouped`, and per row
+    /// otherwise.
+    fn compile(&mut self, expr: &Expr, grouped: bool) -> Result<(Scalar, Type)> {
+        match expr {
+            Expr::Column(name) => {
+                let index = required_column_index(&self.schema, name)?;
+                let column_type = Type::of(self.schema.fields[index].logical_type);
+                if !grouped {
+                    if !self.row_columns.contains(&index) {
+                        self.row_columns.push(index);
+                    }
+                    return Ok((Scalar::Column(index), column_type));
+                }
+                match self.key_columns.iter().position(|x| *x == index) {
+                    Some(key) => Ok((Scalar::Key(key), column_type)),
+                    None => Err(Error::InvalidQuery(format!(
+                        "column {} must be in GROUP BY or inside an aggregate",
+                        name
+                    ))),
+                }
+            }
+            Expr::Literal(literal) => Ok(match literal {
+                Literal::Number(x) => (Scalar::Literal(Value::Decimal(*x)), Type::Decimal(x.scale)),
+                Literal::String(x) => (Scalar::Literal(Value::Str(x.clone())), Type::Str),
+                Literal::Date(x) => (Scalar::Literal(Value::Date(*x)), Type::Date),
+            }),
+            Expr::Negate(inner) => {
+                let (scalar, inner_type) = self.compile(inner, grouped)?;
+                if !inner_type.is_numeric() {
+                    return Err(Error::InvalidQuery(format!(
+                        "cannot negate {} of type {}",
+                        inner, inner_type
+                    )));
+                }
+                Ok((Scalar::Negate(Box::new(scalar)), inner_type))
+            }
+            Expr::Not(inner) => {
+                let (scalar, inner_type) = self.compile(inner, grouped)?;
+                if inner_type != Type::Bool {
+                    return Err(Error::InvalidQuery(format!(
+                        "NOT needs a condition, {} is a {}",
+                        inner, inner_type
+                    )));
+                }
+                Ok((Scalar::Not(Box::new(scalar)), Type::Bool))
+            }
+            Expr::Binary { left, op, right } => {
+                let (left_scalar, left_type) = self.compile(left, grouped)?;
+                let (right_scalar, right_type) = self.compile(right, grouped)?;
+                let result_type = binary_type(left_type, *op, right_type).ok_or_else(|| {
+                    Error::InvalidQuery(format!(
+                        "cannot evaluate {} with {} and {} operands",
+                        expr, left_type, right_type
+                    ))
+                })?;
+                Ok((
+                    Scalar::Binary(Box::new(left_scalar), *op, Box::new(right_scalar)),
+                    result_type,
+                ))
+            }
+            Expr::Aggregate { kind, arg } if grouped => {
+                self.compile_aggregate(expr, *kind, arg.as_deref())
+            }
+            Expr::Aggregate { .. } => Err(Error::InvalidQuery(format!(
+                "{} is only allowed in the select list",
+                expr
+            ))),
+        }
+    }
+
+    fn compile_aggregate(
+        &mut self,
+        expr: &Expr,
+        kind: AggregateKind,
+        arg: Option<&Expr>,
+    ) -> Result<(Scalar, Type)> {
+        if let Some(index) = self.aggregates.iter().position(|x| x.expr == *expr) {
+            return Ok((Scalar::Aggregate(index), self.aggregates[index].result_type));
+        }
+        let (input, arg_type) = match arg {
+            None => (AggregateInput::Rows, None),
+            Some(arg) => {
+                // Plain columns and COUNT arguments need no decoding
+                let row_columns = self.row_columns.len();
+                let (scalar, arg_type) = self.compile(arg, false)?;
+                let input = match scalar {
+                    // Columns have no nulls, so COUNT(x) counts every row
+                    _ if kind == AggregateKind::Count => AggregateInput::Rows,
+                    Scalar::Column(index) if arg_type != Type::Str => AggregateInput::Column(index),
+                    scalar => AggregateInput::Expr(scalar),
+                };
+                if !matches!(input, AggregateInput::Expr(_)) {
+                    self.row_columns.truncate(row_columns);
+                }
+                (input, Some(arg_type))
+            }
+        };
+        let (function, result_type) = match (kind, arg_type) {
+            (AggregateKind::Count, _) => (boxed(Count), Type::Decimal(0)),
+            (AggregateKind::CountDistinct, Some(Type::Decimal(_) | Type::Date | Type::Char)) => {
+                (boxed(CountDistinct), Type::Decimal(0))
+            }
+            (AggregateKind::Sum, Some(Type::Decimal(scale))) => (boxed(Sum), Type::Decimal(scale)),
+            (AggregateKind::Avg, Some(Type::Decimal(_))) => (boxed(Avg), Type::Float),
+            (AggregateKind::Min, Some(t @ (Type::Decimal(_) | Type::Date | Type::Char))) => {
+                (boxed(Min), t)
+            }
+            (AggregateKind::Max, Some(t @ (Type::Decimal(_) | Type::Date | Type::Char))) => {
+                (boxed(Max), t)
+            }
+            (_, arg_type) => {
+                return Err(Error::InvalidQuery(format!(
+                    "cannot compute {} of a {}",
+                    expr,
+                    arg_type.map_or("row".to_string(), |x| x.to_string())
+                )))
+            }
+        };
+        self.aggregates.push(AggregatePlan {
+            expr: expr.clone(),
+            function,
+            input,
+            scale: match arg_type {
+                Some(Type::Decimal(scale)) => scale,
+                _ => 0,
+            },
+            result_type,
+        });
+        Ok((Scalar::Aggregate(self.aggregates.len() - 1), result_type))
+    }
+
+    // Copies the selection into `selected`, dropping the rows that fail a
+    // residual condition
+    fn filter(
+        &self,
+        selection: &Selection,
+        columns: &[Option<Decoded>],
+        selected: &mut Vec<bool>,
+    ) -> Result<()> {
+        selected.clear();
+        selected.extend_from_slice(selection.flags());
+        if self.residual.is_empty() {
+            return Ok(());
+        }
+        for (row, keep) in selected.iter_mut().enumerate() {
+            if *keep {
+                let input = Input::Row { columns, row };
+                for condition in &self.residual {
+                    if !condition.matches(&input)? {
+                        *keep = false;
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn run_rows<R: Read + Seek>(
+        &self,
+        reader: &mut ColumnFileReader<R>,
+    ) -> Result<Vec<Vec<Value>>> {
+        // Without ORDER BY the scan can stop at the LIMIT
+        let enough = self.limit.filter(|_| self.order_by.is_empty());
+        let mut rows = Vec::new();
+        let mut selection = Selection::new();
+        let mut selected = Vec::new();
+        for index in reader.matching_row_groups(&self.predicates)? {
+            if enough.is_some_and(|x| rows.len() >= x) {
+                break;
+            }
+            let row_group = reader.read_columns(index, &self.projection)?;
+            row_group.select(&self.schema, &self.predicates, &mut selection)?;
+            let columns = decode(&row_group, &self.schema, &self.row_columns)?;
+            self.filter(&selection, &columns, &mut selected)?;
+            for row in (0..selected.len()).filter(|x| selected[*x]) {
+                if enough.is_some_and(|x| rows.len() >= x) {
+                    break;
+                }
+                let input = Input::Row {
+                    columns: &columns,
+                    row,
+                };
+                rows.push(
+                    self.select
+                        .iter()
+                        .map(|x| x.eval(&input))
+                        .collect::<Result<_>>()?,
+                );
+            }
+        }
+        Ok(rows)
+    }
+
+    fn run_grouped<R: Read + Seek>(
+        &self,
+        reader: &mut ColumnFileReader<R>,
+    ) -> Result<Vec<Vec<Value>>> {
+        let key_types: Vec<LogicalType> = self
+            .key_columns
+            .iter()
+            .map(|x| self.schema.fields[*x].logical_type)
+            .collect();
+        let functions = || self.aggregates.iter().map(|x| &*x.function);
+        let mut aggregate = HashAggregate::<Vec<Box<dyn Any + Send>>>::new(&key_types);
+        let mut selection = Selection::new();
+        let mut selected = Vec::new();
+        let mut decoded = Vec::new();
+        let mut inputs: Vec<Vec<i64>> = self.aggregates.iter().map(|_| Vec::new()).collect();
+        for index in reader.matching_row_groups(&self.predicates)? {
+            let row_group = reader.read_columns(index, &self.projection)?;
+            row_group.select(&self.schema, &self.predicates, &mut selection)?;
+            let columns = decode(&row_group, &self.schema, &self.row_columns)?;
+            self.filter(&selection, &columns, &mut selected)?;
+            for (aggregate, values) in self.aggregates.iter().zip(&mut inputs) {
+                if let AggregateInput::Expr(scalar) = &aggregate.input {
+                    values.clear();
+                    for (row, keep) in selected.iter().enumerate() {
+                        values.push(match keep {
+                            true => unscaled(scalar.eval(&Input::Row {
+                                columns: &columns,
+                                row,
+                            })?)?,
+                            false => 0,
+                        });
+                    }
+                }
+            }
+
+            let all_selected = selected.iter().all(|x| *x);
+            let mut result = Ok(());
+            aggregate.update(
+                &row_group,
+                &self.key_columns,
+                (!all_selected).then_some(&selected[..]),
+                |group_ids, states| {
+                    init_states(states, functions());
+                    for (index, (aggregate, values)) in
+                        self.aggregates.iter().zip(&inputs).enumerate()
+                    {
+                        let function = &*aggregate.function;
+                        match &aggregate.input {
+                            AggregateInput::Rows => {
+                                for (id, run) in group_runs(group_ids, 0..group_ids.len()) {
+                                    function.update_run(
+                                        &mut *states[id as usize][index],
+                                        0,
+                                        run.len() as u64,
+                                    );
+                                }
+                            }
+                            AggregateInput::Column(column) => {
+                                let column = row_group.columns[*column]
+                                    .as_ref()
+                                    .expect("Aggregate inputs are projected");
+                                let update = update_from_column(
+                                    function,
+                                    index,
+                                    column,
+                                    group_ids,
+                                    states,
+                                    &mut decoded,
+                                );
+                                if result.is_ok() {
+                                    result = update;
+                                }
+                            }
+                            AggregateInput::Expr(_) => {
+                                update_from_values(function, index, values, group_ids, states)
+                            }
+                        }
+                    }
+                },
+            )?;
+            result?;
+        }
+
+        let mut groups = aggregate.into_groups();
+        // Without GROUP BY there is one group even when no rows match
+        if groups.is_empty() && self.key_columns.is_empty() {
+            groups.push((Vec::new(), functions().map(|x| x.init()).collect()));
+        }
+        groups
+            .into_iter()
+            .map(|(key, states)| {
+                let key: Vec<Value> = key
+                    .into_iter()
+                    .zip(&key_types)
+                    .map(|(value, logical_type)| group_value(value, *logical_type))
+                    .collect();
+                let aggregates: Vec<Value> = self
+                    .aggregates
+                    .iter()
+                    .zip(&states)
+                    .map(|(aggregate, state)| aggregate.finalize(&**state))
+                    .collect();
+                let input = Input::Group {
+                    key: &key,
+                    aggregates: &aggregates,
+                };
+                self.select.iter().map(|x| x.eval(&input)).collect()
+            })
+            .collect()
+    }
+}
+
+// The predicate for a comparison of a column with a literal of its type
+fn pushdown(schema: &Schema, conjunct: &Expr) -> Option<Predicate> {
+    let Expr::Binary { left, op, right } = conjunct else {
+        return None;
+    };
+    let (column, op, literal) = match (&**left, &**right) {
+        (Expr::Column(column), Expr::Literal(literal)) => (column, *op, literal),
+        (Expr::Literal(literal), Expr::Column(column)) => (column, op.flip(), literal),
+        _ => return None,
+    };
+    let op = match op {
+        BinaryOp::Eq => CompareOp::Eq,
+        BinaryOp::NotEq => CompareOp::NotEq,
+        BinaryOp::Lt => CompareOp::Lt,
+        BinaryOp::LtEq => CompareOp::LtEq,
+        BinaryOp::Gt => CompareOp::Gt,
+        BinaryOp::GtEq => CompareOp::GtEq,
+        _ => return None,
+    };
+    let index = schema.index_of(column)?;
+    let value = match (schema.fields[index].logical_type, literal) {
+        (LogicalType::Decimal { .. }, Literal::Number(x)) => x.to_f64(),
+        (LogicalType::Date, Literal::Date(x)) => *x as f64,
+        (LogicalType::Char, Literal::String(x)) => char_byte(x)? as f64,
+        _ => return None,
+    };
+    Some(Predicate::new(column, op, value))
+}
+
+// The select list position named by an ORDER BY expression: a 1-based
+// position, an alias or a selected expression
+fn output_column(query: &Query, expr: &Expr) -> Result<usize> {
+    if let Expr::Literal(Literal::Number(position)) = expr {
+        return match position.unscaled {
+            x if position.scale == 0 && 1 <= x && x <= query.select.len() as i128 => {
+                Ok(x as usize - 1)
+            }
+            _ => Err(Error::InvalidQuery(format!(
+                "ORDER BY position {} is not in the select list",
+                position
+            ))),
+        };
+    }
+    if let Expr::Column(name) = expr {
+        if let Some(index) = query
+            .select
+            .iter()
+            .position(|x| x.alias.as_ref() == Some(name))
+        {
+            return Ok(index);
+        }
+    }
+    query
+        .select
+        .iter()
+        .position(|x| x.expr == *expr)
+        .ok_or_else(|| Error::InvalidQuery(format!("ORDER BY {} is not in the select list", expr)))
+}
+
+// The unscaled value of an aggregate argument
+fn unscaled(value: Value) -> Result<i64> {
+    match value {
+        Value::Decimal(x) => i64::try_from(x.unscaled)
+            .map_err(|_| Error::InvalidQuery(format!("{} is too large to aggregate", x))),
+        Value::Date(x) => Ok(x as i64),
+        Value::Char(x) => Ok(x as i64),
+        value => Err(Error::InvalidQuery(format!("cannot aggregate {}", value))),
+    }
+}
+
+fn group_value(value: GroupValue, logical_type: LogicalType) -> Value {
+    match (value, logical_type) {
+        (GroupValue::Byte(x), _) => Value::Char(x),
+        (GroupValue::Int(x), LogicalType::Date) => Value::Date(x as i32),
+        (GroupValue::Int(x), LogicalType::Decimal { scale }) => {
+            Value::Decimal(Decimal::new(x as i128, scale))
+        }
+        (GroupValue::Int(x), _) => Value::Decimal(Decimal::new(x as i128, 0)),
+        (GroupValue::Str(x), _) => Value::Str(x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::column_file::ColumnFileWriter;
+    use crate::row_group::ColumnValues;
+    use crate::schema::Field;
+    use crate::test_data::{lineitems, TempColumnFile};
+    use crate::{query_1_column, query_6_column, QuerySixParams};
+
+    fn decimal(unscaled: i128, scale: u8) -> Value {
+        Value::Decimal(Decimal::new(unscaled, scale))
+    }
+
+    #[test]
+    fn test_sql_matches_query_1_and_6() {
+        let file = TempColumnFile::new("sql", &lineitems());
+        let path = file.path();
+        let cutoff = 9800;
+
+        let query_1 = query_file(
+            path,
+            &format!(
+                "SELECT l_returnflag, l_linestatus,
+                    sum(l_quantity), sum(l_extendedprice),
+                    sum(l_extendedprice * (1 - l_discount)),
+                    sum(l_extendedprice * (1 - l_discount) * (1 + l_tax)),
+                    avg(l_quantity), avg(l_extendedprice), avg(l_discount),
+                    count(*) AS count_order
+                FROM lineitem
+                WHERE l_shipdate <= DATE '{}'
+                GROUP BY l_returnflag, l_linestatus
+                ORDER BY l_returnflag, l_linestatus",
+                format_date(cutoff)
+            ),
+        )
+        .unwrap();
+        let query_6 = query_file(
+            path,
+            "SELECT sum(l_extendedprice * l_discount) AS revenue
+            FROM lineitem
+            WHERE l_shipdate >= DATE '1994-01-01'
+                AND l_shipdate < DATE '1994-01-01' + INTERVAL '1' YEAR
+                AND l_discount BETWEEN 0.06 - 0.01 AND 0.06 + 0.01
+                AND l_quantity < 24",
+        )
+        .unwrap();
+        let expected_1 = query_1_column(path, cutoff).unwrap();
+        let expected_6 = query_6_column(path, &QuerySixParams::default()).unwrap();
+
+        assert_eq!(query_1.columns[2], "sum(l_quantity)");
+        assert_eq!(query_1.columns[9], "count_order");
+        let expected_rows: Vec<Vec<Value>> = expected_1
+            .iter()
+            .map(|x| {
+                vec![
+                    Value::Char(x.l_returnflag.as_bytes()[0]),
+                    Value::Char(x.l_linestatus.as_bytes()[0]),
+                    Value::Decimal(x.sum_qty),
+                    Value::Decimal(x.sum_base_price),
+                    Value::Decimal(x.sum_disc_price),
+                    Value::Decimal(x.sum_charge),
+                    Value::Float(x.avg_qty),
+                    Value::Float(x.avg_price),
+                    Value::Float(x.avg_disc),
+                    decimal(x.count_order as i128, 0),
+                ]
+            })
+            .collect();
+        assert_eq!(query_1.rows, expected_rows);
+        assert_eq!(query_6.rows, vec![vec![Value::Decimal(expected_6)]]);
+    }
+
+    // Rows of (status, price in cents, date, priority)
+    fn orders() -> Vec<(u8, i64, i32, String)> {
+        (0..500)
+            .map(|i| {
+                (
+                    b"FOP"[i % 3],
+                    (i * 7919 % 100000) as i64 - 20000,
+                    9000 + (i % 40) as i32,
+                    format!("{}-PRIORITY", i % 5),
+                )
+            })
+            .collect()
+    }
+
+    fn orders_reader(rows: &[(u8, i64, i32, String)]) -> ColumnFileReader<Cursor<Vec<u8>>> {
+        let schema = Schema::new(vec![
+            Field::new("o_orderstatus", LogicalType::Char),
+            Field::new("o_totalprice", LogicalType::Decimal { scale: 2 }),
+            Field::new("o_orderdate", LogicalType::Date),
+            Field::new("o_orderpriority", LogicalType::Varchar),
+        ]);
+        let mut writer = ColumnFileWriter::with_schema(Vec::new(), schema);
+        for chunk in rows.chunks(64) {
+            writer
+                .write_row_group(&[
+                    ColumnValues::Strings(
+                        chunk.iter().map(|x| (x.0 as char).to_string()).collect(),
+                    ),
+                    ColumnValues::Decimals(chunk.iter().map(|x| x.1 as f64 / 100.0).collect()),
+                    ColumnValues::Dates(chunk.iter().map(|x| x.2).collect()),
+                    ColumnValues::Strings(chunk.iter().map(|x| x.3.clone()).collect()),
+                ])
+                .unwrap();
+        }
+        ColumnFileReader::new(Cursor::new(
+            writer.finish().unwrap().into_inner().into_inner().unwrap(),
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sql_rows_match_brute_force() {
+        let rows = orders();
+        let mut reader = orders_reader(&rows);
+        let first_date = 9000 + 10;
+
+        let output = execute(
+            &mut reader,
+            &format!(
+                "SELECT o_orderpriority, o_totalprice * 2 AS doubled, o_orderdate + 1, o_totalprice / 100
+                FROM orders
+                WHERE (o_orderstatus = 'F' OR o_orderpriority = '1-PRIORITY' AND NOT o_totalprice > 0)
+                    AND o_orderdate >= DATE '{}'
+                ORDER BY doubled DESC, 1
+                LIMIT 25",
+                format_date(first_date)
+            ),
+        )
+        .unwrap();
+
+        let mut expected: Vec<_> = rows
+            .iter()
+            .filter(|x| x.0 == b'F' || (x.3 == "1-PRIORITY" && x.1 <= 0))
+            .filter(|x| x.2 >= first_date)
+            .collect();
+        expected.sort_by(|a, b| b.1.cmp(&a.1).then(a.3.cmp(&b.3)));
+        let expected: Vec<Vec<Value>> = expected[..25]
+            .iter()
+            .map(|x| {
+                vec![
+                    Value::Str(x.3.clone()),
+                    decimal(x.1 as i128 * 2, 2),
+                    Value::Date(x.2 + 1),
+                    Value::Float(x.1 as f64 / 100.0 / 100.0),
+                ]
+            })
+            .collect();
+        assert_eq!(
+            output.columns,
+            [
+                "o_orderpriority",
+                "doubled",
+                "o_orderdate + 1",
+                "o_totalprice / 100"
+            ]
+        );
+        assert_eq!(output.rows, expected);
+    }
+
+    #[test]
+    fn test_sql_group_by_varchar_and_date() {
+        let rows = orders();
+        let mut reader = orders_reader(&rows);
+
+        let output = execute(
+            &mut reader,
+            "SELECT o_orderpriority, min(o_orderdate), max(o_orderstatus),
+                sum(o_totalprice) / count(*) AS mean, count(DISTINCT o_orderdate)
+            FROM orders
+            WHERE o_totalprice < 500.00
+            GROUP BY o_orderpriority
+            ORDER BY mean DESC",
+        )
+        .unwrap();
+
+        let mut expected: Vec<Vec<Value>> = (0..5)
+            .map(|priority| {
+                let group: Vec<_> = rows
+                    .iter()
+                    .filter(|x| x.3 == format!("{}-PRIORITY", priority) && x.1 < 50000)
+                    .collect();
+                let sum: i64 = group.iter().map(|x| x.1).sum();
+                let dates: std::collections::HashSet<i32> = group.iter().map(|x| x.2).collect();
+                vec![
+                    Value::Str(format!("{}-PRIORITY", priority)),
+                    Value::Date(group.iter().map(|x| x.2).min().unwrap()),
+                    Value::Char(group.iter().map(|x| x.0).max().unwrap()),
+                    Value::Float(sum as f64 / 100.0 / group.len() as f64),
+                    decimal(dates.len() as i128, 0),
+                ]
+            })
+            .collect();
+        expected.sort_by(|a, b| order(&a[3], &b[3], true));
+        assert_eq!(output.rows, expected);
+    }
+
+    #[test]
+    fn test_sql_compares_latin_1_chars() {
+        let mut rows = orders();
+        for row in rows.iter_mut().step_by(7) {
+            row.0 = 0xE9;
+        }
+        let mut reader = orders_reader(&rows);
+        let count = |reader: &mut ColumnFileReader<_>, condition: &str| {
+            execute(
+                reader,
+                &format!("SELECT count(*) FROM orders WHERE {}", condition),
+            )
+            .unwrap()
+            .rows
+        };
+        let accented = rows.iter().filter(|x| x.0 == 0xE9).count() as i128;
+
+        // Pushed down to the selection kernels
+        assert_eq!(
+            count(&mut reader, "o_orderstatus = 'é'"),
+            vec![vec![decimal(accented, 0)]]
+        );
+        // Evaluated row by row
+        assert_eq!(
+            count(&mut reader, "o_orderstatus = 'é' OR o_totalprice < -1000"),
+            vec![vec![decimal(accented, 0)]]
+        );
+        assert_eq!(
+            count(&mut reader, "o_orderstatus > 'P' OR o_totalprice < -1000"),
+            vec![vec![decimal(accented, 0)]]
+        );
+        assert_eq!(
+            execute(&mut reader, "SELECT max(o_orderstatus) FROM orders")
+                .unwrap()
+                .rows,
+            vec![vec![Value::Char(0xE9)]]
+        );
+    }
+
+    #[test]
+    fn test_sql_and_or_with_null() {
+        let rows = orders();
+        let mut reader = orders_reader(&rows);
+        let count = |reader: &mut ColumnFileReader<_>, condition: &str| {
+            execute(
+                reader,
+                &format!("SELECT count(*) FROM orders WHERE {}", condition),
+            )
+            .unwrap()
+            .rows
+        };
+        let finished = rows.iter().filter(|x| x.0 == b'F').count() as i128;
+
+        // Dividing by zero gives NULL
+        assert_eq!(
+            count(&mut reader, "o_totalprice / 0 > 1 OR o_orderstatus = 'F'"),
+            vec![vec![decimal(finished, 0)]]
+        );
+        assert_eq!(
+            count(
+                &mut reader,
+                "NOT (o_totalprice / 0 > 1 AND o_orderstatus = 'F')"
+            ),
+            vec![vec![decimal(rows.len() as i128 - finished, 0)]]
+        );
+        assert_eq!(
+            count(
+                &mut reader,
+                "o_totalprice / 0 > 1 OR o_orderstatus <> o_orderstatus"
+            ),
+            vec![vec![decimal(0, 0)]]
+        );
+    }
+
+    #[test]
+    fn test_sql_date_arithmetic_out_of_range() {
+        let mut reader = orders_reader(&orders());
+
+        for sql in [
+            "SELECT o_orderdate + 4294967296 FROM orders",
+            "SELECT o_orderdate + 2147483647 FROM orders",
+            "SELECT 2147483647 + o_orderdate FROM orders",
+            "SELECT o_orderdate - 2147483647 - 2147483647 FROM orders",
+        ] {
+            assert!(
+                matches!(execute(&mut reader, sql), Err(Error::InvalidQuery(_))),
+                "{} should be out of range",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn test_sql_aggregates_without_rows() {
+        let mut reader = orders_reader(&orders());
+
+        let output = execute(
+            &mut reader,
+            "SELECT count(*), sum(o_totalprice), max(o_orderdate) FROM orders WHERE o_orderdate < DATE '1990-01-01'",
+        )
+        .unwrap();
+
+        assert_eq!(
+            output.rows,
+            vec![vec![decimal(0, 0), decimal(0, 2), Value::Null]]
+        );
+    }
+
+    #[test]
+    fn test_sql_rejects_invalid_queries() {
+        let mut reader = orders_reader(&orders());
+
+        for sql in [
+            "SELECT o_comment FROM orders",
+            "SELECT o_orderstatus, count(*) FROM orders",
+            "SELECT o_orderstatus FROM orders GROUP BY o_totalprice + 1",
+            "SELECT o_totalprice + o_orderpriority FROM orders",
+            "SELECT o_totalprice FROM orders WHERE o_orderdate < 10",
+            "SELECT o_totalprice FROM orders WHERE o_totalprice",
+            "SELECT o_totalprice FROM orders WHERE sum(o_totalprice) > 0",
+            "SELECT sum(o_orderpriority) FROM orders",
+            "SELECT avg(o_orderdate) FROM orders",
+            "SELECT sum(sum(o_totalprice)) FROM orders",
+            "SELECT o_totalprice FROM orders ORDER BY o_orderdate",
+            "SELECT o_totalprice FROM orders ORDER BY 2",
+        ] {
+            assert!(
+                matches!(
+                    execute(&mut reader, sql),
+                    Err(Error::InvalidQuery(_)) | Err(Error::UnknownColumn(_))
+                ),
+                "{} should be rejected",
+                sql
+            );
+        }
+    }
+}
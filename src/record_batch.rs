@@ -0,0 +1,499 @@
+//! Conversion between row groups and Arrow record batches. Char columns
+//! become dictionary arrays with one entry per distinct byte, decimals
+//! `Decimal128` with the column's scale, dates `Date32` and varchar columns
+//! `Utf8`. Importing accepts any string array for char and varchar columns
+//! and `Float64` for decimals, as long as every value fits the column.
+
+use std::sync::Arc;
+
+use datafusion::arrow::array::{
+    Array, ArrayRef, AsArray, BooleanArray, Date32Array, Decimal128Array, DictionaryArray,
+    Int32Array, StringArray,
+};
+use datafusion::arrow::compute::{cast, filter_record_batch};
+use datafusion::arrow::datatypes::{
+    DataType, Date32Type, Decimal128Type, Field as ArrowField, Float64Type, Int32Type,
+    Schema as ArrowSchema, SchemaRef, DECIMAL128_MAX_PRECISION,
+};
+use datafusion::arrow::record_batch::{RecordBatch, RecordBatchOptions};
+
+use crate::error::{Error, Result};
+use crate::f64_column::{to_unscaled, MAX_DECIMAL_SCALE, MAX_EXACT_UNSCALED};
+use crate::group_by::for_each_run;
+use crate::row_group::{char_byte, ColumnValues};
+use crate::scan::{ColumnData, ProjectedRowGroup};
+use crate::schema::{Field, LogicalType, Schema, MAX_COLUMNS, MAX_NAME_LENGTH};
+
+// Any unscaled i64 fits, so decimals never fail Arrow's precision checks
+const DECIMAL_PRECISION: u8 = DECIMAL128_MAX_PRECISION;
+
+pub fn arrow_data_type(logical_type: LogicalType) -> DataType {
+    match logical_type {
+        LogicalType::Char => {
+            DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8))
+        }
+        LogicalType::Decimal { scale } => DataType::Decimal128(DECIMAL_PRECISION, scale as i8),
+        LogicalType::Date => DataType::Date32,
+        LogicalType::Varchar => DataType::Utf8,
+    }
+}
+
+pub fn arrow_field(field: &Field) -> ArrowField {
+    // Column files have no nulls
+    ArrowField::new(&field.name, arrow_data_type(field.logical_type), false)
+}
+
+pub fn arrow_schema(schema: &Schema) -> SchemaRef {
+    Arc::new(ArrowSchema::new(
+        schema.fields.iter().map(arrow_field).collect::<Vec<_>>(),
+    ))
+}
+
+/// The `projection` columns of `row_group` in projection order, keeping only
+/// the rows flagged in `selected` if given. The projected columns must have
+/// been read.
+pub fn record_batch(
+    row_group: &ProjectedRowGroup,
+    schema: &Schema,
+    projection: &[usize],
+    selected: Option<&[bool]>,
+) -> Result<RecordBatch> {
+    let fields: Vec<_> = projection
+        .iter()
+        .map(|&x| arrow_field(&schema.fields[x]))
+        .collect();
+    let columns = projection
+        .iter()
+        .map(|&x| arrow_array(row_group, x, schema.fields[x].logical_type))
+        .collect::<Result<Vec<_>>>()?;
+    // The row count is needed when nothing is projected, e.g. for COUNT(*)
+    let options = RecordBatchOptions::new().with_row_count(Some(row_group.row_count as usize));
+    let batch =
+        RecordBatch::try_new_with_options(Arc::new(ArrowSchema::new(fields)), columns, &options)?;
+    Ok(match selected {
+        Some(selected) => filter_record_batch(&batch, &BooleanArray::from(selected.to_vec()))?,
+        None => batch,
+    })
+}
+
+fn arrow_array(
+    row_group: &ProjectedRowGroup,
+    index: usize,
+    logical_type: LogicalType,
+) -> Result<ArrayRef> {
+    let row_count = row_group.row_count as usize;
+    Ok(match &row_group.columns[index] {
+        Some(ColumnData::String(column)) => {
+            // Bytes map to the chars U+0000 to U+00FF, keyed in order of
+            // first appearance
+            let mut slots = [None; 256];
+            let mut values = Vec::new();
+            let mut keys = Vec::with_capacity(row_count);
+            for_each_run(column, row_count, |value, run| {
+                let key = *slots[value as usize].get_or_insert_with(|| {
+                    values.push((value as char).to_string());
+                    values.len() as i32 - 1
+                });
+                keys.resize(run.end, key);
+            })?;
+            Arc::new(DictionaryArray::<Int32Type>::try_new(
+                Int32Array::from(keys),
+                Arc::new(StringArray::from(values)),
+            )?)
+        }
+        Some(ColumnData::Decimal(column)) => {
+            let LogicalType::Decimal { scale } = logical_type else {
+                panic!("Column {} is not a decimal column", index)
+            };
+            let values: Vec<i128> = column.to_i64s().into_iter().map(i128::from).collect();
+            Arc::new(
+                Decimal128Array::from(values)
+                    .with_precision_and_scale(DECIMAL_PRECISION, scale as i8)?,
+            )
+        }
+        Some(ColumnData::Date(column)) => Arc::new(Date32Array::from(
+            column
+                .to_i64s()
+                .into_iter()
+                .map(|x| x as i32)
+                .collect::<Vec<_>>(),
+        )),
+        Some(ColumnData::Varchar(column)) => Arc::new(StringArray::from_iter_values(
+            (0..row_count).map(|x| column.get(x)),
+        )),
+        None => panic!("Column {} was not projected", index),
+    })
+}
+
+/// The schema to store Arrow data of `schema` with; the inverse of
+/// `arrow_schema`. Dictionary-encoded strings become char columns, so their
+/// values must be single chars from U+0000 to U+00FF.
+pub fn schema_from_arrow(schema: &ArrowSchema) -> Result<Schema> {
+    if let Some(field) = schema.fields().get(MAX_COLUMNS) {
+        return Err(Error::IncompatibleArrowColumn {
+            column: field.name().clone(),
+            reason: format!("a schema has at most {} columns", MAX_COLUMNS),
+        });
+    }
+    let fields = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            if field.name().len() > MAX_NAME_LENGTH {
+                return Err(Error::IncompatibleArrowColumn {
+                    column: field.name().clone(),
+                    reason: format!("names are at most {} bytes", MAX_NAME_LENGTH),
+                });
+            }
+            let logical_type = match field.data_type() {
+                DataType::Dictionary(_, values) if is_string(values) => LogicalType::Char,
+                DataType::Utf8 | DataType::LargeUtf8 => LogicalType::Varchar,
+                DataType::Decimal128(_, scale) if (0..=MAX_DECIMAL_SCALE as i8).contains(scale) => {
+                    LogicalType::Decimal {
+                        scale: *scale as u8,
+                    }
+                }
+                DataType::Date32 => LogicalType::Date,
+                data_type => {
+                    return Err(Error::IncompatibleArrowColumn {
+                        column: field.name().clone(),
+                        reason: format!("no column type holds {}", data_type),
+                    })
+                }
+            };
+            Ok(Field::new(field.name(), logical_type))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Schema::new(fields))
+}
+
+/// The columns of `batch` as a row group of `schema`. The batch must have
+/// the schema's columns in the same order.
+pub fn column_values(batch: &RecordBatch, schema: &Schema) -> Result<Vec<ColumnValues>> {
+    let arrow_schema = batch.schema();
+    for i in 0..arrow_schema.fields().len().max(schema.len()) {
+        let (column, reason) = match (arrow_schema.fields().get(i), schema.fields.get(i)) {
+            (Some(x), Some(field)) if *x.name() == field.name => continue,
+            (Some(x), Some(field)) => (
+                x.name().clone(),
+                format!("expected column {} in its place", field.name),
+            ),
+            (Some(x), None) => (x.name().clone(), "not in the schema".to_string()),
+            (None, Some(field)) => (field.name.clone(), "missing from the batch".to_string()),
+            (None, None) => unreachable!(),
+        };
+        return Err(Error::IncompatibleArrowColumn { column, reason });
+    }
+    schema
+        .fields
+        .iter()
+        .zip(batch.columns())
+        .map(|(field, array)| values_from_arrow(field, array))
+        .collect()
+}
+
+fn is_string(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Utf8 | DataType::LargeUtf8)
+}
+
+fn values_from_arrow(field: &Field, array: &ArrayRef) -> Result<ColumnValues> {
+    let incompatible = |reason: String| Error::IncompatibleArrowColumn {
+        column: field.name.clone(),
+        reason,
+    };
+    if array.null_count() > 0 {
+        return Err(incompatible(format!(
+            "{} of {} values are null",
+            array.null_count(),
+            array.len()
+        )));
+    }
+    Ok(match (field.logical_type, array.data_type()) {
+        (LogicalType::Char | LogicalType::Varchar, data_type)
+            if is_string(data_type)
+                || matches!(data_type, DataType::Dictionary(_, values) if is_string(values)) =>
+        {
+            let strings = cast(array, &DataType::Utf8)?;
+            let strings: Vec<String> = strings
+                .as_string::<i32>()
+                .iter()
+                .map(|x| x.expect("Nulls were rejected").to_string())
+                .collect();
+            if field.logical_type == LogicalType::Char {
+                if let Some(value) = strings.iter().find(|x| char_byte(x).is_none()) {
+                    return Err(incompatible(format!(
+                        "{:?} is not a single byte char (U+0000 to U+00FF); use LogicalType::Varchar",
+                        value
+                    )));
+                }
+            }
+            ColumnValues::Strings(strings)
+        }
+        (LogicalType::Decimal { scale }, DataType::Float64 | DataType::Decimal128(..)) => {
+            let values: Vec<f64> = match array.data_type() {
+                DataType::Decimal128(_, from_scale) if *from_scale >= 0 => {
+                    let array = array.as_primitive::<Decimal128Type>();
+                    if let Some(value) = array
+                        .values()
+                        .iter()
+                        .find(|x| x.unsigned_abs() > MAX_EXACT_UNSCALED as u128)
+                    {
+                        return Err(incompatible(format!(
+                            "{} is too large to be held exactly",
+                            value
+                        )));
+                    }
+                    let factor = 10f64.powi(*from_scale as i32);
+                    array.values().iter().map(|x| *x as f64 / factor).collect()
+                }
+                DataType::Decimal128(..) => {
+                    return Err(incompatible(
+                        "negative scales are not supported".to_string(),
+                    ))
+                }
+                _ => array.as_primitive::<Float64Type>().values().to_vec(),
+            };
+            // The writer stores decimals exactly or not at all
+            for value in &values {
+                to_unscaled(*value, scale).map_err(|e| incompatible(e.to_string()))?;
+            }
+            ColumnValues::Decimals(values)
+        }
+        (LogicalType::Date, DataType::Date32) => {
+            ColumnValues::Dates(array.as_primitive::<Date32Type>().values().to_vec())
+        }
+        (logical_type, data_type) => {
+            return Err(incompatible(format!(
+                "{} cannot be stored as {:?}",
+                data_type, logical_type
+            )))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use datafusion::arrow::array::{Float64Array, Int64Array};
+
+    use super::*;
+    use crate::column_file::{ColumnFileReader, ColumnFileWriter};
+
+    #[test]
+    fn test_record_batch_from_row_group() {
+        let schema = Schema::new(vec![
+            Field::new("flag", LogicalType::Char),
+            Field::new("price", LogicalType::Decimal { scale: 2 }),
+            Field::new("day", LogicalType::Date),
+            Field::new("comment", LogicalType::Varchar),
+        ]);
+        let mut writer = ColumnFileWriter::with_schema(Vec::new(), schema.clone());
+        writer
+            .write_row_group(&[
+                ColumnValues::Strings(vec!["A".into(), "A".into(), "R".into(), "A".into()]),
+                ColumnValues::Decimals(vec![1.5, 20.25, -3.0, 0.01]),
+                ColumnValues::Dates(vec![0, 1, 9000, -1]),
+                ColumnValues::Strings(vec!["a".into(), "bb".into(), "".into(), "a".into()]),
+            ])
+            .unwrap();
+        let bytes = writer.finish().unwrap().into_inner().into_inner().unwrap();
+
+        let mut reader = ColumnFileReader::new(Cursor::new(bytes)).unwrap();
+        let row_group = reader.read_columns(0, &[0, 1, 2, 3]).unwrap();
+        let batch = record_batch(&row_group, &schema, &[3, 1, 0, 2], None).unwrap();
+        assert_eq!(batch.schema().field(0).name(), "comment");
+        assert_eq!(
+            batch.schema().field(1).data_type(),
+            &DataType::Decimal128(38, 2)
+        );
+
+        let prices = batch.column(1).as_primitive::<Decimal128Type>();
+        assert_eq!(prices.values().to_vec(), vec![150, 2025, -300, 1]);
+        let flags = batch.column(2).as_dictionary::<Int32Type>();
+        assert_eq!(flags.keys().values().to_vec(), vec![0, 0, 1, 0]);
+        assert_eq!(flags.values().as_string::<i32>().value(1), "R");
+
+        let selected = [false, true, true, false];
+        let batch = record_batch(&row_group, &schema, &[2, 3], Some(&selected)).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(
+            batch
+                .column(0)
+                .as_primitive::<Date32Type>()
+                .values()
+                .to_vec(),
+            vec![1, 9000]
+        );
+        assert_eq!(batch.column(1).as_string::<i32>().value(0), "bb");
+
+        // Nothing projected still counts the selected rows
+        let batch = record_batch(&row_group, &schema, &[], Some(&selected)).unwrap();
+        assert_eq!((batch.num_columns(), batch.num_rows()), (0, 2));
+    }
+
+    #[test]
+    fn test_write_and_read_record_batches() {
+        let flags: DictionaryArray<Int32Type> = ["N", "N", "A", "R", "N"].into_iter().collect();
+        let prices = Decimal128Array::from(vec![100, 2550, -1, 0, 99999])
+            .with_precision_and_scale(12, 2)
+            .unwrap();
+        let taxes = Float64Array::from(vec![0.0, 0.08, 0.02, 0.5, 0.08]);
+        let days = Date32Array::from(vec![8000, 8001, 8001, 9000, 10000]);
+        let comments = StringArray::from(vec!["", "quick", "brown fox", "quick", "ü"]);
+        let batch = RecordBatch::try_from_iter([
+            ("flag", Arc::new(flags) as ArrayRef),
+            ("price", Arc::new(prices) as ArrayRef),
+            ("day", Arc::new(days) as ArrayRef),
+            ("comment", Arc::new(comments) as ArrayRef),
+        ])
+        .unwrap();
+        let mut schema = schema_from_arrow(&batch.schema()).unwrap();
+        assert_eq!(
+            schema
+                .fields
+                .iter()
+                .map(|x| x.logical_type)
+                .collect::<Vec<_>>(),
+            vec![
+                LogicalType::Char,
+                LogicalType::Decimal { scale: 2 },
+                LogicalType::Date,
+                LogicalType::Varchar
+            ]
+        );
+
+        // A float column can fill a decimal column
+        schema
+            .fields
+            .insert(2, Field::new("tax", LogicalType::Decimal { scale: 2 }));
+        let mut columns: Vec<_> = batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|x| x.name().clone())
+            .zip(batch.columns().iter().cloned())
+            .collect();
+        columns.insert(2, ("tax".to_string(), Arc::new(taxes)));
+        let batch = RecordBatch::try_from_iter(columns).unwrap();
+
+        let mut writer =
+            ColumnFileWriter::with_schema(Vec::new(), schema.clone()).with_row_group_size(3);
+        writer.write_record_batch(&batch).unwrap();
+        let bytes = writer.finish().unwrap().into_inner().into_inner().unwrap();
+
+        let mut reader = ColumnFileReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.row_group_count(), 2);
+        let mut values = vec![Vec::new(); schema.len()];
+        for index in 0..reader.row_group_count() {
+            let batch = reader.read_record_batch(index, &[0, 1, 2, 3, 4]).unwrap();
+            let columns = column_values(&batch, &schema).unwrap();
+            assert_eq!(columns, reader.read_row_group(index).unwrap());
+            for (values, column) in values.iter_mut().zip(columns) {
+                values.push(column);
+            }
+        }
+        assert_eq!(
+            values[1],
+            vec![
+                ColumnValues::Decimals(vec![1.0, 25.5, -0.01]),
+                ColumnValues::Decimals(vec![0.0, 999.99])
+            ]
+        );
+        assert_eq!(
+            values[4][1],
+            ColumnValues::Strings(vec!["quick".into(), "ü".into()])
+        );
+        assert_eq!(
+            values[0][0],
+            ColumnValues::Strings(vec!["N".into(), "N".into(), "A".into()])
+        );
+    }
+
+    #[test]
+    fn test_char_columns_round_trip_latin_1() {
+        let flags: DictionaryArray<Int32Type> = ["é", "A", "ÿ", "é"].into_iter().collect();
+        let batch = RecordBatch::try_from_iter([("flag", Arc::new(flags) as ArrayRef)]).unwrap();
+        let schema = schema_from_arrow(&batch.schema()).unwrap();
+
+        let mut writer = ColumnFileWriter::with_schema(Vec::new(), schema.clone());
+        writer.write_record_batch(&batch).unwrap();
+        let bytes = writer.finish().unwrap().into_inner().into_inner().unwrap();
+
+        let mut reader = ColumnFileReader::new(Cursor::new(bytes)).unwrap();
+        let exported = reader.read_record_batch(0, &[0]).unwrap();
+        let flags = exported.column(0).as_dictionary::<Int32Type>();
+        assert_eq!(flags.keys().values().to_vec(), vec![0, 1, 2, 0]);
+        assert_eq!(flags.values().as_string::<i32>().value(2), "ÿ");
+        assert_eq!(
+            column_values(&exported, &schema).unwrap(),
+            reader.read_row_group(0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_schema_from_arrow_rejects_what_the_header_cannot_hold() {
+        let long_name = "x".repeat(256);
+        let schema = ArrowSchema::new(vec![ArrowField::new(&long_name, DataType::Date32, false)]);
+        assert!(matches!(
+            schema_from_arrow(&schema),
+            Err(Error::IncompatibleArrowColumn { column, .. }) if column == long_name
+        ));
+
+        let fields: Vec<_> = (0..256)
+            .map(|i| ArrowField::new(format!("c{}", i), DataType::Date32, false))
+            .collect();
+        assert!(matches!(
+            schema_from_arrow(&ArrowSchema::new(fields)),
+            Err(Error::IncompatibleArrowColumn { column, .. }) if column == "c255"
+        ));
+    }
+
+    #[test]
+    fn test_incompatible_arrow_columns() {
+        let schema = Schema::new(vec![
+            Field::new("flag", LogicalType::Char),
+            Field::new("price", LogicalType::Decimal { scale: 2 }),
+        ]);
+        let batch = |flags: Vec<Option<&str>>, prices: ArrayRef| {
+            RecordBatch::try_from_iter([
+                ("flag", Arc::new(StringArray::from(flags)) as ArrayRef),
+                ("price", prices),
+            ])
+            .unwrap()
+        };
+        let prices = Arc::new(Float64Array::from(vec![1.0, 2.0])) as ArrayRef;
+        let incompatible = |batch: RecordBatch| match column_values(&batch, &schema) {
+            Err(Error::IncompatibleArrowColumn { column, .. }) => column,
+            result => panic!("Expected an incompatible column, got {:?}", result),
+        };
+
+        assert!(column_values(&batch(vec![Some("A"), Some("B")], prices.clone()), &schema).is_ok());
+        assert_eq!(
+            incompatible(batch(vec![Some("A"), None], prices.clone())),
+            "flag"
+        );
+        assert_eq!(
+            incompatible(batch(vec![Some("A"), Some("FO")], prices.clone())),
+            "flag"
+        );
+        assert_eq!(
+            incompatible(batch(vec![Some("A"), Some("Ā")], prices.clone())),
+            "flag"
+        );
+        let flags = vec![Some("A"), Some("B")];
+        let inexact = Arc::new(Float64Array::from(vec![1.0, 2.125]));
+        assert_eq!(incompatible(batch(flags.clone(), inexact)), "price");
+        let integers = Arc::new(Int64Array::from(vec![1, 2]));
+        assert_eq!(incompatible(batch(flags.clone(), integers)), "price");
+        let huge = Decimal128Array::from(vec![1, 1 << 60]).with_precision_and_scale(38, 2);
+        assert_eq!(
+            incompatible(batch(flags.clone(), Arc::new(huge.unwrap()))),
+            "price"
+        );
+
+        let swapped =
+            RecordBatch::try_from_iter([("price", prices.clone()), ("flag", prices)]).unwrap();
+        assert_eq!(incompatible(swapped), "price");
+    }
+}
@@ -0,0 +1,310 @@
+use std::io::{BufReader, Read, Write};
+
+use crate::column_file::{ColumnChunkMeta, ColumnStats, RowGroupMeta};
+use crate::error::{Error, Result};
+use crate::f64_column::DecimalColumn;
+use crate::header::FileHeader;
+use crate::schema::{Field, LogicalType, Schema};
+use crate::string_column::StringColumnReader;
+use crate::varchar_column::VarcharColumn;
+use crate::{TrackedWriter, MAX_ROW_GROUP_SIZE};
+
+/// The values of one column of a row group, in row order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnValues {
+    Strings(Vec<String>),
+    Decimals(Vec<f64>),
+    /// Days since 1970-01-01
+    Dates(Vec<i32>),
+}
+
+impl ColumnValues {
+    pub fn len(&self) -> usize {
+        match self {
+            ColumnValues::Strings(values) => values.len(),
+            ColumnValues::Decimals(values) => values.len(),
+            ColumnValues::Dates(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn matches(&self, logical_type: LogicalType) -> bool {
+        matches!(
+            (self, logical_type),
+            (
+                ColumnValues::Strings(_),
+                LogicalType::Char | LogicalType::Varchar
+            ) | (ColumnValues::Decimals(_), LogicalType::Decimal { .. })
+                | (ColumnValues::Dates(_), LogicalType::Date)
+        )
+    }
+}
+
+/// The byte a char column stores for `value`: its code point, if it is a
+/// single char from U+0000 to U+00FF.
+pub(crate) fn char_byte(value: &str) -> Option<u8> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => u8::try_from(c).ok(),
+        _ => None,
+    }
+}
+
+fn write_column_chunk<W: Write>(
+    writer: &mut TrackedWriter<W>,
+    stats: ColumnStats,
+    write: impl FnOnce(&mut TrackedWriter<W>) -> Result<()>,
+) -> Result<ColumnChunkMeta> {
+    let offset = writer.bytes_written() as u64;
+    write(writer)?;
+    Ok(ColumnChunkMeta {
+        offset,
+        length: writer.bytes_written() as u64 - offset,
+        stats,
+    })
+}
+
+fn write_column<W: Write>(
+    field: &Field,
+    values: &ColumnValues,
+    writer: &mut TrackedWriter<W>,
+) -> Result<ColumnChunkMeta> {
+    match (values, field.logical_type) {
+        (ColumnValues::Strings(values), LogicalType::Char) => {
+            let bytes = values
+                .iter()
+                .map(|value| {
+                    char_byte(value).ok_or_else(|| {
+                        Error::InvalidValue(format!(
+                            "column {}: {:?} is not a single byte char (U+0000 to U+00FF); use LogicalType::Varchar",
+                            field.name, value
+                        ))
+                    })
+                })
+                .collect::<Result<Vec<u8>>>()?;
+            let stats = ColumnStats::from_values(bytes.iter().map(|x| *x as i64));
+            write_column_chunk(writer, stats, |w| StringColumnReader::from_bytes(&bytes).write(w))
+        }
+        (ColumnValues::Decimals(values), LogicalType::Decimal { scale }) => {
+            let column = DecimalColumn::from_f64s(values.iter().copied(), scale)
+                .map_err(|e| Error::InvalidValue(format!("column {}: {}", field.name, e)))?;
+            let stats = ColumnStats::from_values(column.to_i64s().into_iter());
+            write_column_chunk(writer, stats, |w| column.write(w))
+        }
+        (ColumnValues::Dates(values), LogicalType::Date) => {
+            let days: Vec<i64> = values.iter().map(|x| *x as i64).collect();
+            let column = DecimalColumn::new(0, &days);
+            let stats = ColumnStats::from_values(days.into_iter());
+            write_column_chunk(writer, stats, |w| column.write(w))
+        }
+        (ColumnValues::Strings(values), LogicalType::Varchar) => {
+            let column = VarcharColumn::from_strings(values);
+            let stats = ColumnStats {
+                min: 0,
+                max: 0,
+                count: values.len() as u32,
+            };
+            write_column_chunk(writer, stats, |w| column.write(w))
+        }
+        _ => panic!("Column {} has the wrong type", field.name),
+    }
+}
+
+/// Writes one row group of `schema`-shaped columns. The first row group
+/// written to a file is preceded by the file header.
+pub fn write_row_group_columns<W: Write>(
+    schema: &Schema,
+    columns: &[ColumnValues],
+    writer: &mut TrackedWriter<W>,
+) -> Result<RowGroupMeta> {
+    assert_eq!(
+        columns.len(),
+        schema.len(),
+        "Row group has {} columns but the schema has {}",
+        columns.len(),
+        schema.len()
+    );
+    let row_count = columns.first().map_or(0, |x| x.len());
+    assert!(
+        row_count <= MAX_ROW_GROUP_SIZE,
+        "Row group has {} rows, at most {} are supported",
+        row_count,
+        MAX_ROW_GROUP_SIZE
+    );
+    for (field, values) in schema.fields.iter().zip(columns) {
+        assert!(
+            values.matches(field.logical_type),
+            "Column {} has the wrong type",
+            field.name
+        );
+        assert_eq!(
+            values.len(),
+            row_count,
+            "Column {} has {} rows, expected {}",
+            field.name,
+            values.len(),
+            row_count
+        );
+    }
+
+    if writer.bytes_written() == 0 {
+        FileHeader::new(schema.clone()).write(writer)?;
+    }
+    let offset = writer.bytes_written() as u64;
+    crate::io::write_u32(writer, row_count as u32)?;
+    let columns = schema
+        .fields
+        .iter()
+        .zip(columns)
+        .map(|(field, values)| write_column(field, values, writer))
+        .collect::<Result<_>>()?;
+    Ok(RowGroupMeta {
+        offset,
+        row_count: row_count as u32,
+        columns,
+    })
+}
+
+/// Reads the row count that starts every row group.
+pub fn read_row_count<R: Read>(reader: &mut R) -> Result<u32> {
+    let row_count = crate::io::read_u32(reader)?;
+    if row_count as usize > MAX_ROW_GROUP_SIZE {
+        return Err(Error::corrupt(format!(
+            "row group has {} rows, at most {} are supported",
+            row_count, MAX_ROW_GROUP_SIZE
+        )));
+    }
+    Ok(row_count)
+}
+
+/// Reads the row group at the reader's position, decoding every column of
+/// `schema` into its values.
+pub fn read_row_group_columns<R: Read>(
+    reader: &mut BufReader<R>,
+    schema: &Schema,
+) -> Result<Vec<ColumnValues>> {
+    let row_count = read_row_count(reader)?;
+    schema
+        .fields
+        .iter()
+        .map(|field| {
+            Ok(match field.logical_type {
+                LogicalType::Char => {
+                    let column = StringColumnReader::new(reader)?;
+                    let mut values = Vec::with_capacity(row_count as usize);
+                    for (value, count) in column.compressed_iterator() {
+                        let value = (value as char).to_string();
+                        values.extend(std::iter::repeat_n(value, count as usize));
+                    }
+                    ColumnValues::Strings(values)
+                }
+                LogicalType::Decimal { scale } => ColumnValues::Decimals(
+                    DecimalColumn::read(reader, scale, row_count as usize)?.to_f64s(),
+                ),
+                LogicalType::Date => ColumnValues::Dates(
+                    DecimalColumn::read(reader, 0, row_count as usize)?
+                        .to_i64s()
+                        .into_iter()
+                        .map(|x| x as i32)
+                        .collect(),
+                ),
+                LogicalType::Varchar => ColumnValues::Strings(
+                    VarcharColumn::read(reader, row_count as usize)?.to_strings(),
+                ),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::column_file::{ColumnFileReader, ColumnFileWriter};
+
+    fn orders_schema() -> Schema {
+        Schema::new(vec![
+            Field::new("o_totalprice", LogicalType::Decimal { scale: 2 }),
+            Field::new("o_orderstatus", LogicalType::Char),
+            Field::new("o_orderdate", LogicalType::Date),
+            Field::new("o_comment", LogicalType::Varchar),
+        ])
+    }
+
+    fn orders(prices: &[f64], statuses: &[&str]) -> Vec<ColumnValues> {
+        vec![
+            ColumnValues::Decimals(prices.to_vec()),
+            ColumnValues::Strings(statuses.iter().map(|x| x.to_string()).collect()),
+            ColumnValues::Dates((0..prices.len() as i32).map(|x| 8036 + x * 400).collect()),
+            ColumnValues::Strings(
+                (0..prices.len())
+                    .map(|x| format!("{} ärgerlich pending déposits", x % 2))
+                    .collect(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_write_read_custom_schema() {
+        let first = orders(&[1.5, 2.25, 104949.5], &["F", "F", "O"]);
+        let second = orders(&[4.75], &["P"]);
+        let mut writer = ColumnFileWriter::with_schema(Vec::new(), orders_schema());
+        writer.write_row_group(&first).unwrap();
+        writer.write_row_group(&second).unwrap();
+        let bytes = writer.finish().unwrap().into_inner().into_inner().unwrap();
+
+        let mut reader = ColumnFileReader::new(Cursor::new(bytes)).unwrap();
+
+        assert_eq!(reader.schema(), &orders_schema());
+        assert_eq!(reader.read_row_group(0).unwrap(), first);
+        assert_eq!(reader.read_row_group(1).unwrap(), second);
+    }
+
+    #[test]
+    fn test_write_rejects_inexact_decimals() {
+        let mut writer = TrackedWriter::new(Vec::new());
+
+        let result = write_row_group_columns(
+            &orders_schema(),
+            &orders(&[1.0, 2.345], &["F", "F"]),
+            &mut writer,
+        );
+
+        assert!(matches!(
+            result,
+            Err(Error::InvalidValue(message))
+                if message.contains("o_totalprice: 2.345 cannot be stored exactly")
+        ));
+    }
+
+    #[test]
+    fn test_write_rejects_multi_byte_char() {
+        let mut writer = TrackedWriter::new(Vec::new());
+
+        let result = write_row_group_columns(
+            &orders_schema(),
+            &orders(&[1.0, 2.0], &["F", "OK"]),
+            &mut writer,
+        );
+
+        assert!(matches!(
+            result,
+            Err(Error::InvalidValue(message))
+                if message.contains("o_orderstatus: \"OK\" is not a single byte")
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "wrong type")]
+    fn test_write_rejects_mismatched_columns() {
+        let mut columns = orders(&[1.0], &["F"]);
+        columns.reverse();
+        let mut writer = TrackedWriter::new(Vec::new());
+
+        let _ = write_row_group_columns(&orders_schema(), &columns, &mut writer);
+    }
+}
@@ -0,0 +1,252 @@
+use std::io::{BufReader, Read, Seek};
+
+use crate::column_file::required_column_index;
+use crate::error::{Error, Result};
+use crate::f64_column::DecimalColumn;
+use crate::predicate::Predicate;
+use crate::schema::{LogicalType, PhysicalType, Schema};
+use crate::selection::Selection;
+use crate::string_column::StringColumnReader;
+use crate::varchar_column::VarcharColumn;
+
+pub enum ColumnData {
+    String(StringColumnReader),
+    Decimal(DecimalColumn),
+    /// Days since 1970-01-01, encoded as a decimal column with scale 0
+    Date(DecimalColumn),
+    Varchar(VarcharColumn),
+}
+
+/// A row group with only the projected columns decoded. Columns are indexed
+/// by their position in the file header; unprojected columns are `None`.
+pub struct ProjectedRowGroup {
+    pub row_count: u32,
+    pub columns: Vec<Option<ColumnData>>,
+}
+
+impl ProjectedRowGroup {
+    pub fn string_column(&self, index: usize) -> &StringColumnReader {
+        match &self.columns[index] {
+            Some(ColumnData::String(column)) => column,
+            Some(_) => panic!("Column {} is not a string column", index),
+            None => panic!("Column {} was not projected", index),
+        }
+    }
+
+    pub fn decimal_column(&self, index: usize) -> &DecimalColumn {
+        match &self.columns[index] {
+            Some(ColumnData::Decimal(column)) => column,
+            Some(_) => panic!("Column {} is not a decimal column", index),
+            None => panic!("Column {} was not projected", index),
+        }
+    }
+
+    pub fn date_column(&self, index: usize) -> &DecimalColumn {
+        match &self.columns[index] {
+            Some(ColumnData::Date(column)) => column,
+            Some(_) => panic!("Column {} is not a date column", index),
+            None => panic!("Column {} was not projected", index),
+        }
+    }
+
+    pub fn varchar_column(&self, index: usize) -> &VarcharColumn {
+        match &self.columns[index] {
+            Some(ColumnData::Varchar(column)) => column,
+            Some(_) => panic!("Column {} is not a varchar column", index),
+            None => panic!("Column {} was not projected", index),
+        }
+    }
+
+    /// Resets `selection` to the rows that satisfy every predicate. The
+    /// predicate columns must be projected.
+    pub fn select(
+        &self,
+        schema: &Schema,
+        predicates: &[Predicate],
+        selection: &mut Selection,
+    ) -> Result<()> {
+        selection.reset(self.row_count as usize);
+        for predicate in predicates {
+            let index = required_column_index(schema, &predicate.column)?;
+            let logical_type = schema.fields[index].logical_type;
+            match &self.columns[index] {
+                Some(ColumnData::String(column)) => {
+                    selection.filter_string(column.view(), predicate.value_range(logical_type))?
+                }
+                Some(ColumnData::Decimal(column)) | Some(ColumnData::Date(column)) => {
+                    selection.filter_decimal(column.view(), predicate.value_range(logical_type))
+                }
+                Some(ColumnData::Varchar(_)) => {
+                    return Err(Error::InvalidQuery(format!(
+                        "cannot compare varchar column {} with a number",
+                        predicate.column
+                    )))
+                }
+                None => panic!("Column {} was not projected", index),
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn read_column<R: Read>(
+    reader: &mut BufReader<R>,
+    logical_type: LogicalType,
+    row_count: u32,
+) -> Result<ColumnData> {
+    Ok(match logical_type {
+        LogicalType::Char => ColumnData::String(StringColumnReader::new(reader)?),
+        LogicalType::Decimal { scale } => {
+            ColumnData::Decimal(DecimalColumn::read(reader, scale, row_count as usize)?)
+        }
+        LogicalType::Date => ColumnData::Date(DecimalColumn::read(reader, 0, row_count as usize)?),
+        LogicalType::Varchar => {
+            ColumnData::Varchar(VarcharColumn::read(reader, row_count as usize)?)
+        }
+    })
+}
+
+/// Moves past a column chunk without decoding it. String columns are
+/// skipped using their entry count, decimal columns by their fixed size and
+/// varchar columns by their body length.
+pub fn skip_column<R: Read + Seek>(
+    reader: &mut BufReader<R>,
+    physical_type: PhysicalType,
+    row_count: u32,
+) -> Result<()> {
+    match physical_type {
+        PhysicalType::RleU8String => StringColumnReader::skip(reader),
+        PhysicalType::FrameOfReference => DecimalColumn::skip(reader, row_count as usize),
+        PhysicalType::Dictionary => VarcharColumn::skip(reader),
+    }
+}
+
+/// Reads the row group at the reader's position, decoding only the columns
+/// listed in `projection` and seeking past the rest. Works on any row group
+/// stream, including legacy files without a footer.
+pub fn read_projected_row_group<R: Read + Seek>(
+    reader: &mut BufReader<R>,
+    schema: &Schema,
+    projection: &[usize],
+) -> Result<ProjectedRowGroup> {
+    let row_count = crate::row_group::read_row_count(reader)?;
+    let mut columns = Vec::with_capacity(schema.len());
+    for (index, field) in schema.fields.iter().enumerate() {
+        if projection.contains(&index) {
+            columns.push(Some(read_column(reader, field.logical_type, row_count)?));
+        } else {
+            skip_column(reader, field.logical_type.physical_type(), row_count)?;
+            columns.push(None);
+        }
+    }
+    Ok(ProjectedRowGroup { row_count, columns })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    use super::*;
+    use crate::column_file::ColumnFileWriter;
+    use crate::header::lineitem_header;
+    use crate::predicate::Predicate;
+    use crate::LineItem;
+
+    fn lineitems(returnflag: &str, quantity: f64, count: usize) -> Vec<LineItem> {
+        vec![
+            LineItem {
+                l_returnflag: returnflag.to_string(),
+                l_linestatus: "O".to_string(),
+                l_quantity: quantity,
+                l_extendedprice: 9.99,
+                l_discount: 0.04,
+                l_tax: 0.02,
+                l_shipdate: 10000,
+            };
+            count
+        ]
+    }
+
+    fn write_file() -> Vec<u8> {
+        let mut writer = ColumnFileWriter::new(Vec::new());
+        writer.write_batch(&mut lineitems("A", 1.0, 10)).unwrap();
+        writer.write_batch(&mut lineitems("N", 2.0, 20)).unwrap();
+        writer.finish().unwrap().into_inner().into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_read_projected_row_group_skips_columns() {
+        let header = lineitem_header();
+        let mut header_bytes = Vec::new();
+        header.write(&mut header_bytes).unwrap();
+        let mut reader = BufReader::new(Cursor::new(write_file()));
+        reader
+            .seek(SeekFrom::Start(header_bytes.len() as u64))
+            .unwrap();
+
+        let first = read_projected_row_group(&mut reader, &header.schema, &[1, 5]).unwrap();
+        let second = read_projected_row_group(&mut reader, &header.schema, &[1, 2]).unwrap();
+
+        assert_eq!(first.row_count, 10);
+        assert!(first.columns[0].is_none() && first.columns[2].is_none());
+        assert_eq!(first.string_column(1).count_strings(), 10);
+        assert!(first.decimal_column(5).to_i64s().iter().all(|x| *x == 999));
+        assert_eq!(second.row_count, 20);
+        assert!(second.decimal_column(2).to_i64s().iter().all(|x| *x == 200));
+    }
+
+    #[test]
+    fn test_scan_projected() {
+        let mut reader =
+            crate::column_file::ColumnFileReader::new(Cursor::new(write_file())).unwrap();
+        let mut sum_qty = 0;
+
+        reader
+            .scan_projected(
+                &["l_quantity"],
+                &[Predicate::gt("l_quantity", 1.0)],
+                |row_group| {
+                    assert!(row_group.columns[0].is_none());
+                    let quantity = row_group.decimal_column(2);
+                    sum_qty += quantity.sum(0, quantity.len());
+                },
+            )
+            .unwrap();
+
+        assert_eq!(sum_qty, 20 * 200);
+    }
+
+    #[test]
+    fn test_select_filters_rows_within_row_groups() {
+        let mut writer = ColumnFileWriter::new(Vec::new());
+        let mut batch = lineitems("A", 1.0, 10);
+        batch.extend(lineitems("N", 30.0, 5));
+        batch.extend(lineitems("R", 2.0, 20));
+        writer.write_batch(&mut batch).unwrap();
+        let bytes = writer.finish().unwrap().into_inner().into_inner().unwrap();
+        let mut reader = crate::column_file::ColumnFileReader::new(Cursor::new(bytes)).unwrap();
+        let schema = reader.schema().clone();
+        let predicates = [
+            Predicate::lt("l_quantity", 24.0),
+            Predicate::new(
+                "l_returnflag",
+                crate::predicate::CompareOp::NotEq,
+                b'A' as f64,
+            ),
+        ];
+        let mut selection = Selection::new();
+        let mut counts = Vec::new();
+
+        reader
+            .scan_projected(&["l_returnflag", "l_quantity"], &predicates, |row_group| {
+                row_group
+                    .select(&schema, &predicates, &mut selection)
+                    .unwrap();
+                let quantity = row_group.decimal_column(2).view();
+                counts.push((selection.count(), quantity.masked_sum(0, selection.flags())));
+            })
+            .unwrap();
+
+        assert_eq!(counts, vec![(20, 20 * 200)]);
+    }
+}
@@ -0,0 +1,113 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhysicalType {
+    RleU8String,
+    FrameOfReference,
+    /// Dictionary-encoded strings, or plain offsets and bytes when most
+    /// values are distinct
+    Dictionary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogicalType {
+    /// Single-byte string, stored run-length encoded
+    Char,
+    /// Fixed-point number with `scale` digits after the decimal point
+    Decimal { scale: u8 },
+    /// Calendar date, stored as days since 1970-01-01
+    Date,
+    /// Variable-length UTF-8 string
+    Varchar,
+}
+
+impl LogicalType {
+    pub fn physical_type(self) -> PhysicalType {
+        match self {
+            LogicalType::Char => PhysicalType::RleU8String,
+            LogicalType::Decimal { .. } | LogicalType::Date => PhysicalType::FrameOfReference,
+            LogicalType::Varchar => PhysicalType::Dictionary,
+        }
+    }
+
+    pub(crate) fn tag(self) -> u8 {
+        match self {
+            LogicalType::Char => 1,
+            LogicalType::Decimal { .. } => 3,
+            LogicalType::Date => 4,
+            LogicalType::Varchar => 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub logical_type: LogicalType,
+}
+
+impl Field {
+    pub fn new(name: &str, logical_type: LogicalType) -> Self {
+        Field {
+            name: name.to_string(),
+            logical_type,
+        }
+    }
+}
+
+/// Most columns a schema can have. The file header records the column count
+/// in a byte.
+pub const MAX_COLUMNS: usize = u8::MAX as usize;
+/// Longest column name in bytes. The file header records name lengths in a
+/// byte.
+pub const MAX_NAME_LENGTH: usize = u8::MAX as usize;
+
+/// The ordered columns of a table. Row groups store their column chunks in
+/// schema order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schema {
+    pub fields: Vec<Field>,
+}
+
+impl Schema {
+    /// Panics if there are more than `MAX_COLUMNS` fields or a name is
+    /// longer than `MAX_NAME_LENGTH`, as the header could not record them.
+    pub fn new(fields: Vec<Field>) -> Self {
+        assert!(
+            fields.len() <= MAX_COLUMNS,
+            "A schema has at most {} columns, got {}",
+            MAX_COLUMNS,
+            fields.len()
+        );
+        if let Some(field) = fields.iter().find(|x| x.name.len() > MAX_NAME_LENGTH) {
+            panic!(
+                "Column name {:?} is longer than {} bytes",
+                field.name, MAX_NAME_LENGTH
+            );
+        }
+        Schema { fields }
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|x| x.name == name)
+    }
+}
+
+/// The TPC-H lineitem subset used by Query 1, in on-disk order.
+pub fn lineitem_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("l_linestatus", LogicalType::Char),
+        Field::new("l_returnflag", LogicalType::Char),
+        Field::new("l_quantity", LogicalType::Decimal { scale: 2 }),
+        Field::new("l_discount", LogicalType::Decimal { scale: 2 }),
+        Field::new("l_tax", LogicalType::Decimal { scale: 2 }),
+        Field::new("l_extendedprice", LogicalType::Decimal { scale: 2 }),
+        Field::new("l_shipdate", LogicalType::Date),
+    ])
+}
@@ -0,0 +1,266 @@
+use crate::error::{Error, Result};
+use crate::f64_column::{DecimalColumnRef, PackedSlice};
+use crate::kernels;
+use crate::predicate::ValueRange;
+use crate::string_column::StringColumnRef;
+
+/// The rows of a row group that pass a filter, one flag per row.
+///
+/// A selection starts with every row selected and each `filter_*` call
+/// clears the rows that fail one predicate, so applying several filters
+/// ANDs them. The flags are the masks taken by the masked sums in `kernels`
+/// and `DecimalColumnRef::masked_sum`. The buffer is reused across row
+/// groups.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+    flags: Vec<bool>,
+}
+
+impl Selection {
+    pub fn new() -> Self {
+        Selection::default()
+    }
+
+    /// Selects all `row_count` rows.
+    pub fn reset(&mut self, row_count: usize) {
+        self.flags.clear();
+        self.flags.resize(row_count, true);
+    }
+
+    pub fn flags(&self) -> &[bool] {
+        &self.flags
+    }
+
+    pub fn len(&self) -> usize {
+        self.flags.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flags.is_empty()
+    }
+
+    /// Number of selected rows
+    pub fn count(&self) -> u64 {
+        kernels::count_selected(&self.flags)
+    }
+
+    pub fn all_selected(&self) -> bool {
+        self.count() == self.flags.len() as u64
+    }
+
+    pub fn none_selected(&self) -> bool {
+        self.count() == 0
+    }
+
+    /// Keeps the rows whose value in `column` is in `range`.
+    pub fn filter_decimal(&mut self, column: DecimalColumnRef, range: ValueRange) {
+        assert_eq!(column.len(), self.flags.len());
+        if !column.run_lengths.is_empty() {
+            // One comparison per run
+            for (value, rows) in column.runs() {
+                if !range.contains(value) {
+                    self.flags[rows].fill(false);
+                }
+            }
+            return;
+        }
+        if range.negated {
+            for (row, selected) in self.flags.iter_mut().enumerate() {
+                *selected &= range.contains(column.value(row));
+            }
+            return;
+        }
+        // Compare the packed offsets instead of the values: the writer
+        // picks the reference so that adding offsets never overflows
+        let min = (range.min as i128 - column.reference as i128).max(0);
+        let max = range.max as i128 - column.reference as i128;
+        match column.packed {
+            PackedSlice::U8(values) => match offset_range(min, max, u8::MAX.into()) {
+                Some((min, max)) => kernels::and_in_range_u8(values, min, max, &mut self.flags),
+                None => self.flags.fill(false),
+            },
+            PackedSlice::U16(values) => match offset_range(min, max, u16::MAX.into()) {
+                Some((min, max)) => kernels::and_in_range_u16(values, min, max, &mut self.flags),
+                None => self.flags.fill(false),
+            },
+            PackedSlice::U32(values) => self.filter_scalar(values, min, max),
+            PackedSlice::U64(values) => self.filter_scalar(values, min, max),
+        }
+    }
+
+    /// Keeps the rows whose value in `values` is in `range`, for u16 columns
+    /// such as the ones in headerless files (see `U16column`).
+    pub fn filter_u16(&mut self, values: &[u16], range: ValueRange) {
+        assert_eq!(values.len(), self.flags.len());
+        if range.negated {
+            for (value, selected) in values.iter().zip(&mut self.flags) {
+                *selected &= range.contains(*value as i64);
+            }
+            return;
+        }
+        match offset_range(range.min.max(0).into(), range.max.into(), u16::MAX.into()) {
+            Some((min, max)) => kernels::and_in_range_u16(values, min, max, &mut self.flags),
+            None => self.flags.fill(false),
+        }
+    }
+
+    /// Keeps the rows whose byte in the string column is in `range`.
+    pub fn filter_string(&mut self, column: StringColumnRef, range: ValueRange) -> Result<()> {
+        let mut start = 0;
+        for (value, run_length) in column.compressed_iterator() {
+            let end = start + run_length as usize;
+            if end > self.flags.len() {
+                return Err(Error::corrupt(
+                    "string column runs cover more rows than the row group",
+                ));
+            }
+            if !range.contains(value as i64) {
+                self.flags[start..end].fill(false);
+            }
+            start = end;
+        }
+        if start != self.flags.len() {
+            return Err(Error::corrupt("string column ended early"));
+        }
+        Ok(())
+    }
+
+    fn filter_scalar<T: Copy + Into<u64>>(&mut self, values: &[T], min: i128, max: i128) {
+        for (value, selected) in values.iter().zip(&mut self.flags) {
+            let value: u64 = (*value).into();
+            *selected &= min <= value as i128 && value as i128 <= max;
+        }
+    }
+}
+
+// `min..=max` narrowed to the offsets up to `type_max`, or `None` if no
+// offset is in range. `min` must not be negative.
+fn offset_range<T: TryFrom<i128>>(min: i128, max: i128, type_max: i128) -> Option<(T, T)> {
+    let max = max.min(type_max);
+    if min > max {
+        return None;
+    }
+    Some((T::try_from(min).ok()?, T::try_from(max).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::f64_column::{DecimalColumn, DecimalEncoding};
+    use crate::predicate::{CompareOp, Predicate};
+    use crate::schema::LogicalType;
+    use crate::string_column::StringColumnReader;
+
+    const DECIMAL: LogicalType = LogicalType::Decimal { scale: 2 };
+
+    fn selected_rows(selection: &Selection) -> Vec<usize> {
+        (0..selection.len())
+            .filter(|row| selection.flags()[*row])
+            .collect()
+    }
+
+    #[test]
+    fn test_filters_are_anded() {
+        // Q6: l_discount between 0.05 and 0.07 and l_quantity < 24
+        let discount = DecimalColumn::new(2, &[4, 5, 6, 7, 8, 6]);
+        let quantity = DecimalColumn::new(2, &[100, 2300, 2400, 500, 100, 2399]);
+        let mut selection = Selection::new();
+
+        selection.reset(6);
+        selection.filter_decimal(
+            discount.view(),
+            Predicate::gt_eq("l_discount", 0.05).value_range(DECIMAL),
+        );
+        selection.filter_decimal(
+            discount.view(),
+            Predicate::lt_eq("l_discount", 0.07).value_range(DECIMAL),
+        );
+        selection.filter_decimal(
+            quantity.view(),
+            Predicate::lt("l_quantity", 24.0).value_range(DECIMAL),
+        );
+
+        assert_eq!(selected_rows(&selection), vec![1, 3, 5]);
+        assert_eq!(selection.count(), 3);
+        assert_eq!(
+            quantity.view().masked_sum(0, selection.flags()),
+            2300 + 500 + 2399
+        );
+    }
+
+    #[test]
+    fn test_filter_decimal_matches_row_by_row_comparison() {
+        // Each width of packed offsets, with a negative reference, and each
+        // with a run of zeros when run-length encoded
+        let columns = [
+            vec![-300, -250, 0, -50],
+            vec![-300, 60_000, 5, -7],
+            vec![-300, 5_000_000, 5, 70],
+            vec![i64::MIN / 2, i64::MAX / 2, 5, 0],
+        ];
+        let ranges = [
+            ValueRange::new(CompareOp::Lt, 0.0, 2),
+            ValueRange::new(CompareOp::GtEq, -2.5, 2),
+            ValueRange::new(CompareOp::Eq, 0.05, 2),
+            ValueRange::new(CompareOp::NotEq, 0.05, 2),
+            ValueRange::new(CompareOp::Gt, 1e9, 2),
+        ];
+        let encodings = [
+            DecimalEncoding::FrameOfReference,
+            DecimalEncoding::RunLength,
+        ];
+        for values in &columns {
+            let values = [values.clone(), vec![0; 3]].concat();
+            for encoding in encodings {
+                let column = DecimalColumn::with_encoding(2, &values, encoding);
+                for range in ranges {
+                    let mut selection = Selection::new();
+                    selection.reset(values.len());
+
+                    selection.filter_decimal(column.view(), range);
+
+                    let expected: Vec<bool> = values.iter().map(|x| range.contains(*x)).collect();
+                    assert_eq!(selection.flags(), expected, "{:?} {:?}", column, range);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_filter_u16() {
+        let mut selection = Selection::new();
+        selection.reset(5);
+
+        selection.filter_u16(
+            &[0, 5, 7, 65535, 6],
+            ValueRange::new(CompareOp::GtEq, 0.06, 2),
+        );
+        assert_eq!(selected_rows(&selection), vec![2, 3, 4]);
+        selection.filter_u16(
+            &[0, 5, 7, 65535, 6],
+            ValueRange::new(CompareOp::Lt, -1.0, 2),
+        );
+        assert!(selection.none_selected());
+    }
+
+    #[test]
+    fn test_filter_string() {
+        let column = StringColumnReader::new_from_strings(vec!["A", "A", "N", "R", "R", "R"]).unwrap();
+        let mut selection = Selection::new();
+        selection.reset(6);
+
+        selection
+            .filter_string(
+                column.view(),
+                ValueRange::new(CompareOp::NotEq, b'N' as f64, 0),
+            )
+            .unwrap();
+
+        assert_eq!(selected_rows(&selection), vec![0, 1, 3, 4, 5]);
+        selection.reset(5);
+        assert!(matches!(
+            selection.filter_string(column.view(), ValueRange::new(CompareOp::Eq, 0.0, 0)),
+            Err(Error::Corrupt(_))
+        ));
+    }
+}
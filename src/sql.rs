@@ -0,0 +1,875 @@
+//! The SQL subset accepted by `query::execute`:
+//!
+//! ```text
+//! SELECT expr [[AS] alias], ...
+//! FROM table
+//! [WHERE condition]
+//! [GROUP BY column, ...]
+//! [ORDER BY expr [ASC | DESC], ...]
+//! [LIMIT n]
+//! ```
+//!
+//! Expressions are columns, numbers, `'strings'`, `DATE 'YYYY-MM-DD'`
+//! literals, `+ - * /`, comparisons, `BETWEEN`, `AND`, `OR`, `NOT` and the
+//! aggregates SUM, COUNT, AVG, MIN, MAX and COUNT(DISTINCT). A date literal
+//! may be shifted by `INTERVAL 'n' DAY | MONTH | YEAR`. Keywords and column
+//! names are case-insensitive.
+
+use std::fmt;
+
+use crate::date_column::{add_months, format_date, parse_date};
+use crate::error::{Error, Result};
+use crate::f64_column::{Decimal, MAX_DECIMAL_SCALE};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Number(Decimal),
+    String(String),
+    /// Days since 1970-01-01
+    Date(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Eq
+                | BinaryOp::NotEq
+                | BinaryOp::Lt
+                | BinaryOp::LtEq
+                | BinaryOp::Gt
+                | BinaryOp::GtEq
+        )
+    }
+
+    /// The comparison with its operands swapped, so `1 < x` becomes `x > 1`
+    pub fn flip(self) -> Self {
+        match self {
+            BinaryOp::Lt => BinaryOp::Gt,
+            BinaryOp::LtEq => BinaryOp::GtEq,
+            BinaryOp::Gt => BinaryOp::Lt,
+            BinaryOp::GtEq => BinaryOp::LtEq,
+            op => op,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Eq => "=",
+            BinaryOp::NotEq => "<>",
+            BinaryOp::Lt => "<",
+            BinaryOp::LtEq => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::GtEq => ">=",
+            BinaryOp::And => "AND",
+            BinaryOp::Or => "OR",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateKind {
+    Sum,
+    Count,
+    Avg,
+    Min,
+    Max,
+    /// `COUNT(DISTINCT x)`, counted exactly by `CountDistinct`
+    CountDistinct,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Column(String),
+    Literal(Literal),
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Binary {
+        left: Box<Expr>,
+        op: BinaryOp,
+        right: Box<Expr>,
+    },
+    /// `arg` is `None` for `COUNT(*)`
+    Aggregate {
+        kind: AggregateKind,
+        arg: Option<Box<Expr>>,
+    },
+}
+
+impl Expr {
+    pub fn binary(left: Expr, op: BinaryOp, right: Expr) -> Self {
+        Expr::Binary {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+        }
+    }
+
+    pub fn contains_aggregate(&self) -> bool {
+        match self {
+            Expr::Column(_) | Expr::Literal(_) => false,
+            Expr::Negate(expr) | Expr::Not(expr) => expr.contains_aggregate(),
+            Expr::Binary { left, right, .. } => {
+                left.contains_aggregate() || right.contains_aggregate()
+            }
+            Expr::Aggregate { .. } => true,
+        }
+    }
+
+    /// The operands of the top-level ANDs, so `a AND (b AND c)` gives
+    /// `[a, b, c]`
+    pub fn conjuncts(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Binary {
+                left,
+                op: BinaryOp::And,
+                right,
+            } => {
+                let mut conjuncts = left.conjuncts();
+                conjuncts.extend(right.conjuncts());
+                conjuncts
+            }
+            expr => vec![expr],
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Nested binary operations are parenthesized rather than relying on
+        // precedence
+        let operand = |f: &mut fmt::Formatter, expr: &Expr| match expr {
+            Expr::Binary { .. } => write!(f, "({})", expr),
+            _ => write!(f, "{}", expr),
+        };
+        match self {
+            Expr::Column(name) => write!(f, "{}", name),
+            Expr::Literal(Literal::Number(value)) => write!(f, "{}", value),
+            Expr::Literal(Literal::String(value)) => write!(f, "'{}'", value.replace('\'', "''")),
+            Expr::Literal(Literal::Date(days)) => write!(f, "DATE '{}'", format_date(*days)),
+            Expr::Negate(expr) => {
+                write!(f, "-")?;
+                operand(f, expr)
+            }
+            Expr::Not(expr) => {
+                write!(f, "NOT ")?;
+                operand(f, expr)
+            }
+            Expr::Binary { left, op, right } => {
+                operand(f, left)?;
+                write!(f, " {} ", op.symbol())?;
+                operand(f, right)
+            }
+            Expr::Aggregate { kind, arg } => {
+                let name = match kind {
+                    AggregateKind::Sum => "sum",
+                    AggregateKind::Count | AggregateKind::CountDistinct => "count",
+                    AggregateKind::Avg => "avg",
+                    AggregateKind::Min => "min",
+                    AggregateKind::Max => "max",
+                };
+                match arg {
+                    None => write!(f, "{}(*)", name),
+                    Some(arg) if *kind == AggregateKind::CountDistinct => {
+                        write!(f, "{}(DISTINCT {})", name, arg)
+                    }
+                    Some(arg) => write!(f, "{}({})", name, arg),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectItem {
+    pub expr: Expr,
+    pub alias: Option<String>,
+}
+
+impl SelectItem {
+    /// The output column name: the alias, or the expression as written
+    pub fn name(&self) -> String {
+        match &self.alias {
+            Some(alias) => alias.clone(),
+            None => self.expr.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy {
+    pub expr: Expr,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub select: Vec<SelectItem>,
+    /// The table name is not checked: the file being queried is the table
+    pub from: String,
+    pub filter: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<usize>,
+}
+
+/// Parses one query, optionally followed by a semicolon.
+pub fn parse(sql: &str) -> Result<Query> {
+    let mut parser = Parser {
+        tokens: tokenize(sql)?,
+        position: 0,
+        depth: 0,
+    };
+    let query = parser.query()?;
+    parser.eat_symbol(";");
+    match parser.peek() {
+        None => Ok(query),
+        Some(token) => Err(syntax_error(format!(
+            "unexpected {} after the query",
+            token
+        ))),
+    }
+}
+
+fn syntax_error(message: String) -> Error {
+    Error::InvalidQuery(format!("syntax error: {}", message))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// A keyword or identifier, lowercased
+    Word(String),
+    Number(String),
+    String(String),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Number(number) => write!(f, "{}", number),
+            Token::String(string) => write!(f, "'{}'", string),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+// Longest first, so `<=` is not read as `<` and `=`
+const SYMBOLS: [&str; 15] = [
+    "<=", ">=", "<>", "!=", "<", ">", "=", "+", "-", "*", "/", "(", ")", ",", ";",
+];
+
+// Words that end an expression, so they cannot be implicit aliases
+const RESERVED: [&str; 16] = [
+    "select", "from", "where", "group", "by", "order", "limit", "and", "or", "not", "as",
+    "between", "asc", "desc", "date", "interval",
+];
+
+fn tokenize(sql: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = sql;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if rest.starts_with("--") {
+            rest = rest.find('\n').map_or("", |end| &rest[end..]);
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest
+                .find(|x: char| !(x.is_ascii_alphanumeric() || x == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..end].to_ascii_lowercase()));
+            rest = &rest[end..];
+        } else if c.is_ascii_digit()
+            || (c == '.' && rest[1..].starts_with(|x: char| x.is_ascii_digit()))
+        {
+            let end = rest
+                .find(|x: char| !(x.is_ascii_digit() || x == '.'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if c == '\'' {
+            // Quotes inside strings are doubled
+            let mut value = String::new();
+            let mut chars = rest.char_indices().skip(1);
+            let end = loop {
+                match chars.next() {
+                    Some((i, '\'')) if rest[i + 1..].starts_with('\'') => {
+                        value.push('\'');
+                        chars.next();
+                    }
+                    Some((i, '\'')) => break i + 1,
+                    Some((_, x)) => value.push(x),
+                    None => return Err(syntax_error("unterminated string".to_string())),
+                }
+            };
+            tokens.push(Token::String(value));
+            rest = &rest[end..];
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|x| rest.starts_with(**x))
+                .ok_or_else(|| syntax_error(format!("unexpected character {:?}", c)))?;
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_number(text: &str) -> Result<Decimal> {
+    let invalid = || syntax_error(format!("invalid number {}", text));
+    let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+    if fraction.contains('.') || fraction.len() > MAX_DECIMAL_SCALE as usize {
+        return Err(invalid());
+    }
+    let unscaled: i128 = format!("{}{}", whole, fraction)
+        .parse()
+        .map_err(|_| invalid())?;
+    Ok(Decimal::new(unscaled, fraction.len() as u8))
+}
+
+// How deeply parentheses, NOT and unary minus may nest before parsing
+// gives up rather than overflowing the stack
+const MAX_DEPTH: usize = 64;
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    /// Recursive descents the parser is currently inside
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| syntax_error("unexpected end of query".to_string()))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word == keyword)
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_keyword(keyword);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Symbol(x)) if *x == symbol);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.eat_keyword(keyword) {
+            return Ok(());
+        }
+        Err(self.expected(&keyword.to_ascii_uppercase()))
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<()> {
+        if self.eat_symbol(symbol) {
+            return Ok(());
+        }
+        Err(self.expected(symbol))
+    }
+
+    // Runs one recursive step of the grammar, counting it against MAX_DEPTH
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Parser) -> Result<T>) -> Result<T> {
+        if self.depth == MAX_DEPTH {
+            return Err(Error::InvalidQuery(format!(
+                "expression nested more than {} levels deep",
+                MAX_DEPTH
+            )));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn expected(&self, what: &str) -> Error {
+        match self.peek() {
+            Some(token) => syntax_error(format!("expected {}, found {}", what, token)),
+            None => syntax_error(format!("expected {}, found the end of the query", what)),
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Word(word)) if !RESERVED.contains(&word.as_str()) => {
+                let word = word.clone();
+                self.position += 1;
+                Ok(word)
+            }
+            _ => Err(self.expected("a name")),
+        }
+    }
+
+    fn query(&mut self) -> Result<Query> {
+        self.expect_keyword("select")?;
+        let select = self.list(Parser::select_item)?;
+        self.expect_keyword("from")?;
+        let from = self.identifier()?;
+        let filter = if self.eat_keyword("where") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let mut group_by = Vec::new();
+        if self.eat_keyword("group") {
+            self.expect_keyword("by")?;
+            group_by = self.list(Parser::expr)?;
+        }
+        let mut order_by = Vec::new();
+        if self.eat_keyword("order") {
+            self.expect_keyword("by")?;
+            order_by = self.list(|parser| {
+                let expr = parser.expr()?;
+                let descending = parser.eat_keyword("desc");
+                if !descending {
+                    parser.eat_keyword("asc");
+                }
+                Ok(OrderBy { expr, descending })
+            })?;
+        }
+        let limit = if self.eat_keyword("limit") {
+            match self.next()? {
+                Token::Number(number) => Some(
+                    number
+                        .parse()
+                        .map_err(|_| syntax_error(format!("invalid LIMIT {}", number)))?,
+                ),
+                token => {
+                    return Err(syntax_error(format!(
+                        "expected a row count, found {}",
+                        token
+                    )))
+                }
+            }
+        } else {
+            None
+        };
+        Ok(Query {
+            select,
+            from,
+            filter,
+            group_by,
+            order_by,
+            limit,
+        })
+    }
+
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Parser) -> Result<T>) -> Result<Vec<T>> {
+        let mut items = vec![item(self)?];
+        while self.eat_symbol(",") {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    fn select_item(&mut self) -> Result<SelectItem> {
+        let expr = self.expr()?;
+        let alias = if self.eat_keyword("as") {
+            Some(self.identifier()?)
+        } else {
+            self.identifier().ok()
+        };
+        Ok(SelectItem { expr, alias })
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.eat_keyword("or") {
+            expr = Expr::binary(expr, BinaryOp::Or, self.and()?);
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.not()?;
+        while self.eat_keyword("and") {
+            expr = Expr::binary(expr, BinaryOp::And, self.not()?);
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.eat_keyword("not") {
+            return Ok(Expr::Not(Box::new(self.nested(Parser::not)?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let left = self.additive()?;
+        let negated = self.eat_keyword("not");
+        if self.eat_keyword("between") {
+            // `x BETWEEN a AND b` is `x >= a AND x <= b`
+            let low = self.additive()?;
+            self.expect_keyword("and")?;
+            let high = self.additive()?;
+            let between = Expr::binary(
+                Expr::binary(left.clone(), BinaryOp::GtEq, low),
+                BinaryOp::And,
+                Expr::binary(left, BinaryOp::LtEq, high),
+            );
+            return Ok(match negated {
+                true => Expr::Not(Box::new(between)),
+                false => between,
+            });
+        }
+        if negated {
+            return Err(self.expected("BETWEEN"));
+        }
+        let op = match self.peek() {
+            Some(Token::Symbol("=")) => BinaryOp::Eq,
+            Some(Token::Symbol("<>")) | Some(Token::Symbol("!=")) => BinaryOp::NotEq,
+            Some(Token::Symbol("<")) => BinaryOp::Lt,
+            Some(Token::Symbol("<=")) => BinaryOp::LtEq,
+            Some(Token::Symbol(">")) => BinaryOp::Gt,
+            Some(Token::Symbol(">=")) => BinaryOp::GtEq,
+            _ => return Ok(left),
+        };
+        self.position += 1;
+        Ok(Expr::binary(left, op, self.additive()?))
+    }
+
+    fn additive(&mut self) -> Result<Expr> {
+        let mut expr = self.multiplicative()?;
+        loop {
+            let op = if self.eat_symbol("+") {
+                BinaryOp::Add
+            } else if self.eat_symbol("-") {
+                BinaryOp::Sub
+            } else {
+                return Ok(expr);
+            };
+            expr = if self.eat_keyword("interval") {
+                self.shift_date(expr, op)?
+            } else {
+                Expr::binary(expr, op, self.multiplicative()?)
+            };
+        }
+    }
+
+    // Folds `DATE '...' +- INTERVAL ...` into a date literal
+    fn shift_date(&mut self, date: Expr, op: BinaryOp) -> Result<Expr> {
+        let Expr::Literal(Literal::Date(days)) = date else {
+            return Err(Error::InvalidQuery(
+                "INTERVAL can only be added to or subtracted from a DATE literal".to_string(),
+            ));
+        };
+        // Both INTERVAL '3' MONTH and INTERVAL '3 months'
+        let text = match self.next()? {
+            Token::String(text) => text,
+            token => {
+                return Err(syntax_error(format!(
+                    "expected an interval, found {}",
+                    token
+                )))
+            }
+        };
+        let mut parts = text.split_whitespace();
+        let amount = parts.next().unwrap_or("");
+        let unit = match parts.next() {
+            Some(unit) => unit.to_ascii_lowercase(),
+            None => match self.next()? {
+                Token::Word(unit) => unit,
+                token => {
+                    return Err(syntax_error(format!(
+                        "expected an interval unit, found {}",
+                        token
+                    )))
+                }
+            },
+        };
+        let amount_text = amount;
+        let amount: i32 = amount
+            .parse()
+            .map_err(|_| syntax_error(format!("invalid interval '{}'", text)))?;
+        let amount = if op == BinaryOp::Sub {
+            amount.checked_neg()
+        } else {
+            Some(amount)
+        };
+        let shifted = match unit.trim_end_matches('s') {
+            "day" => amount.and_then(|x| days.checked_add(x)),
+            "month" => amount.and_then(|x| add_months(days, x)),
+            "year" => amount
+                .and_then(|x| x.checked_mul(12))
+                .and_then(|x| add_months(days, x)),
+            _ => return Err(syntax_error(format!("unsupported interval unit {}", unit))),
+        };
+        let days = shifted.ok_or_else(|| {
+            Error::InvalidQuery(format!(
+                "DATE '{}' {} {} {} is out of range",
+                format_date(days),
+                op.symbol(),
+                amount_text,
+                unit
+            ))
+        })?;
+        Ok(Expr::Literal(Literal::Date(days)))
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        loop {
+            let op = if self.eat_symbol("*") {
+                BinaryOp::Mul
+            } else if self.eat_symbol("/") {
+                BinaryOp::Div
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::binary(expr, op, self.unary()?);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat_symbol("-") {
+            return Ok(match self.nested(Parser::unary)? {
+                Expr::Literal(Literal::Number(value)) => {
+                    Expr::Literal(Literal::Number(Decimal::new(-value.unscaled, value.scale)))
+                }
+                expr => Expr::Negate(Box::new(expr)),
+            });
+        }
+        self.eat_symbol("+");
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.next()? {
+            Token::Number(number) => Ok(Expr::Literal(Literal::Number(parse_number(&number)?))),
+            Token::String(value) => Ok(Expr::Literal(Literal::String(value))),
+            Token::Symbol("(") => {
+                let expr = self.nested(Parser::expr)?;
+                self.expect_symbol(")")?;
+                Ok(expr)
+            }
+            Token::Word(word) if word == "date" => match self.next()? {
+                Token::String(value) => parse_date(&value)
+                    .map(|days| Expr::Literal(Literal::Date(days)))
+                    .ok_or_else(|| syntax_error(format!("invalid date '{}'", value))),
+                token => Err(syntax_error(format!("expected a date, found {}", token))),
+            },
+            Token::Word(word) if self.eat_symbol("(") => self.aggregate(&word),
+            Token::Word(word) if !RESERVED.contains(&word.as_str()) => Ok(Expr::Column(word)),
+            token => Err(syntax_error(format!(
+                "expected an expression, found {}",
+                token
+            ))),
+        }
+    }
+
+    // The rest of an aggregate call after its opening parenthesis
+    fn aggregate(&mut self, name: &str) -> Result<Expr> {
+        let mut kind = match name {
+            "sum" => AggregateKind::Sum,
+            "count" => AggregateKind::Count,
+            "avg" => AggregateKind::Avg,
+            "min" => AggregateKind::Min,
+            "max" => AggregateKind::Max,
+            _ => return Err(Error::InvalidQuery(format!("unknown function {}", name))),
+        };
+        let arg = if kind == AggregateKind::Count && self.eat_symbol("*") {
+            None
+        } else {
+            if kind == AggregateKind::Count && self.eat_keyword("distinct") {
+                kind = AggregateKind::CountDistinct;
+            }
+            Some(Box::new(self.nested(Parser::expr)?))
+        };
+        self.expect_symbol(")")?;
+        Ok(Expr::Aggregate { kind, arg })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str) -> Expr {
+        Expr::Column(name.to_string())
+    }
+
+    fn number(unscaled: i128, scale: u8) -> Expr {
+        Expr::Literal(Literal::Number(Decimal::new(unscaled, scale)))
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query = parse(
+            "select l_returnflag, SUM(l_extendedprice * (1 - l_discount)) AS revenue, count(*) n
+             FROM lineitem
+             WHERE l_shipdate <= DATE '1998-12-01' - INTERVAL '90' DAY -- the Query 1 cutoff
+             GROUP BY l_returnflag
+             ORDER BY revenue DESC, 1
+             LIMIT 10;",
+        )
+        .unwrap();
+
+        assert_eq!(
+            query.select,
+            vec![
+                SelectItem {
+                    expr: column("l_returnflag"),
+                    alias: None,
+                },
+                SelectItem {
+                    expr: Expr::Aggregate {
+                        kind: AggregateKind::Sum,
+                        arg: Some(Box::new(Expr::binary(
+                            column("l_extendedprice"),
+                            BinaryOp::Mul,
+                            Expr::binary(number(1, 0), BinaryOp::Sub, column("l_discount")),
+                        ))),
+                    },
+                    alias: Some("revenue".to_string()),
+                },
+                SelectItem {
+                    expr: Expr::Aggregate {
+                        kind: AggregateKind::Count,
+                        arg: None,
+                    },
+                    alias: Some("n".to_string()),
+                },
+            ]
+        );
+        assert_eq!(query.from, "lineitem");
+        assert_eq!(
+            query.filter,
+            Some(Expr::binary(
+                column("l_shipdate"),
+                BinaryOp::LtEq,
+                Expr::Literal(Literal::Date(parse_date("1998-09-02").unwrap())),
+            ))
+        );
+        assert_eq!(query.group_by, vec![column("l_returnflag")]);
+        assert_eq!(
+            query.order_by,
+            vec![
+                OrderBy {
+                    expr: column("revenue"),
+                    descending: true,
+                },
+                OrderBy {
+                    expr: number(1, 0),
+                    descending: false,
+                },
+            ]
+        );
+        assert_eq!(query.limit, Some(10));
+        assert_eq!(
+            query.select[1].expr.to_string(),
+            "sum(l_extendedprice * (1 - l_discount))"
+        );
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        let filter = |condition: &str| {
+            parse(&format!("SELECT a FROM t WHERE {}", condition))
+                .unwrap()
+                .filter
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(filter("a + b * -2.50 > 1"), "(a + (b * -2.50)) > 1");
+        assert_eq!(
+            filter("a = 1 OR b = 2 AND NOT c = 3"),
+            "(a = 1) OR ((b = 2) AND NOT (c = 3))"
+        );
+        assert_eq!(filter("a BETWEEN 1 AND 2"), "(a >= 1) AND (a <= 2)");
+        assert_eq!(filter("a - b - c <> 'it''s'"), "((a - b) - c) <> 'it''s'");
+        assert_eq!(
+            filter("d < DATE '1994-01-31' + INTERVAL '1 month'"),
+            "d < DATE '1994-02-28'"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for sql in [
+            "SELECT FROM t",
+            "SELECT a FROM t WHERE",
+            "SELECT a FROM t LIMIT x",
+            "SELECT a b c FROM t",
+            "SELECT (a FROM t",
+            "SELECT a FROM t WHERE b = 'open",
+            "SELECT a FROM t WHERE a < b + INTERVAL '1' DAY",
+            "SELECT median(a) FROM t",
+            "SELECT a FROM t WHERE d = DATE '1998-02-30'",
+            "SELECT a FROM t WHERE d = DATE '99999999-01-01'",
+            "SELECT a FROM t WHERE d < DATE '2000-01-01' + INTERVAL '2147483000' DAY",
+            "SELECT a FROM t WHERE d < DATE '1998-01-01' - INTERVAL '300000000' YEAR",
+            "SELECT a FROM t; SELECT b FROM t",
+        ] {
+            assert!(
+                matches!(parse(sql), Err(Error::InvalidQuery(_))),
+                "{} should not parse",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_limits_nesting() {
+        let nested = |open: &str, close: &str, depth: usize| {
+            format!(
+                "SELECT {}a{} FROM t",
+                open.repeat(depth),
+                close.repeat(depth)
+            )
+        };
+        for (open, close) in [("(", ")"), ("NOT ", ""), ("- ", ""), ("sum(", ")")] {
+            assert!(parse(&nested(open, close, MAX_DEPTH)).is_ok());
+            let deep = parse(&nested(open, close, 100_000));
+            assert!(
+                matches!(deep, Err(Error::InvalidQuery(ref message)) if message.contains("nested")),
+                "{}a{} should be rejected, got {:?}",
+                open,
+                close,
+                deep
+            );
+        }
+    }
+}
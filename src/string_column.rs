@@ -1,122 +1,254 @@
-use std::io::Read;
+use std::io::{Read, Seek};
 use std::io::Write;
 
+use crate::error::{Error, Result};
 use crate::io;
 use crate::io::read_u64;
-use crate::{TrackedWriter, MAX_ROW_GROUP_SIZE};
+use crate::row_group::char_byte;
+use crate::{TrackedWriter, DEFAULT_ROW_GROUP_SIZE, MAX_ROW_GROUP_SIZE};
 
+/// Run-length encoded single-byte strings.
+///
+/// On disk a column is the entry count (u64), one byte per entry holding
+/// the value, padding that aligns the run lengths to 4 bytes within the file
+/// and then one little-endian u32 per entry holding its run length.
 pub struct StringColumnReader {
-    data: Vec<(u8, u32)>,
+    values: Vec<u8>,
+    run_lengths: Vec<u32>,
     column_entries: u64,
-    item_index: u16,
-    repeat_index: i16,
+    item_index: u32,
+    repeat_index: u32,
+}
+
+
+// Headerless legacy files stored each entry as the in-memory layout of a
+// `(u8, u32)`: the value, three bytes of padding and the run length.
+const LEGACY_ENTRY_SIZE: usize = 8;
+
+fn read_column_entries<R: Read>(reader: &mut R) -> Result<u64> {
+    let column_entries = read_u64(reader)?;
+    // Every entry covers at least one row
+    if column_entries > MAX_ROW_GROUP_SIZE as u64 {
+        return Err(Error::corrupt(format!(
+            "string column has {} entries",
+            column_entries
+        )));
+    }
+    Ok(column_entries)
 }
 
 fn read_u8_string_column_to_vec<R: Read>(
     reader: &mut std::io::BufReader<R>,
-    data : &mut Vec<(u8, u32)>
-) -> u64 {
-    let column_entries = read_u64(reader);
-
-    data.clear();
-    // data.reserve(column_entries as usize); // I think not required as we are setting the length in the initilization
-    unsafe {
-        data.set_len(column_entries as usize);
-        // Using data as a raw byte buffer
-        let byte_slice = std::slice::from_raw_parts_mut(
-            data.as_mut_ptr() as *mut u8,
-            std::mem::size_of::<(u8, u32)>() * column_entries as usize
-        );
-        reader.read_exact(byte_slice).expect("Failed to read column data");
+    values: &mut Vec<u8>,
+    run_lengths: &mut Vec<u32>,
+) -> Result<u64> {
+    let column_entries = read_column_entries(reader)?;
+
+    values.clear();
+    values.resize(column_entries as usize, 0);
+    run_lengths.clear();
+    run_lengths.resize(column_entries as usize, 0);
+    let result = reader
+        .read_exact(values)
+        .map_err(Error::from)
+        .and_then(|_| io::read_padding(reader))
+        .and_then(|_| Ok(reader.read_exact(bytemuck::cast_slice_mut(run_lengths))?));
+    if let Err(e) = result {
+        values.clear();
+        run_lengths.clear();
+        return Err(e);
+    }
+    for run_length in run_lengths.iter_mut() {
+        *run_length = u32::from_le(*run_length);
     }
+    Ok(column_entries)
+}
+
+fn read_legacy_string_column_to_vec<R: Read>(
+    reader: &mut std::io::BufReader<R>,
+    values: &mut Vec<u8>,
+    run_lengths: &mut Vec<u32>,
+) -> Result<u64> {
+    let column_entries = read_column_entries(reader)?;
 
-    // No further processing needed as the bytes should now represent the (u8, u32) pairs
-    column_entries
+    values.clear();
+    run_lengths.clear();
+    let mut record = [0u8; LEGACY_ENTRY_SIZE];
+    for _ in 0..column_entries {
+        if let Err(e) = reader.read_exact(&mut record) {
+            values.clear();
+            run_lengths.clear();
+            return Err(e.into());
+        }
+        values.push(record[0]);
+        run_lengths.push(u32::from_le_bytes(record[4..8].try_into().unwrap()));
+    }
+    Ok(column_entries)
 }
 
 fn write_u8_string_column_from_vec<W: Write>(
     writer: &mut TrackedWriter<W>,
-    data: &[(u8, u32)]
-) {
-    // Write the number of entries
-    io::write_u64(writer, data.len() as u64);
-    
-    // Write the raw bytes of the vector
-    unsafe {
-        let byte_slice = std::slice::from_raw_parts(
-            data.as_ptr() as *const u8,
-            std::mem::size_of::<(u8, u32)>() * data.len()
-        );
-        writer.write_all(byte_slice).expect("Failed to write column data");
+    values: &[u8],
+    run_lengths: &[u32],
+) -> Result<()> {
+    io::write_u64(writer, values.len() as u64)?;
+    writer.write_all(values)?;
+    io::write_padding(writer, 4)?;
+    for run_length in run_lengths {
+        io::write_u32(writer, *run_length)?;
     }
+    Ok(())
 }
 
-fn write_vec_to_data_array(input : &Vec<&str>, data: &mut Vec<(u8, u32)>) -> u64 {
-    //io::write_u64(writer, column_length);
-    // Clone the iterator to avoid consuming it when we count
-    data.clear();
-    let mut column_length : u64 = 0;
-    let mut iter = input.iter().peekable();
-    while let Some(value) = iter.next() {
-        let mut count = 1;
-        while iter.peek() == Some(&value) {
-            iter.next();
-            count += 1;
-        }
-        data.push((value.as_bytes()[0], count));
-        column_length += 1;
-    }
-    column_length
-}
 impl StringColumnReader {
-    pub fn new_from_strings(strings : Vec<&str>) -> Self {
-        let mut data = Vec::with_capacity(MAX_ROW_GROUP_SIZE*5);
-        let column_entries = write_vec_to_data_array(&strings, &mut data);
-        StringColumnReader {
-            data,
-            column_entries,
-            item_index: 0,
-            repeat_index: 0,
+    /// Run-length encodes the strings, each of which must be a single char
+    /// from U+0000 to U+00FF.
+    pub fn new_from_strings(strings: Vec<&str>) -> Result<Self> {
+        let bytes = strings
+            .iter()
+            .map(|value| {
+                char_byte(value).ok_or_else(|| {
+                    Error::InvalidValue(format!(
+                        "{:?} is not a single char from U+0000 to U+00FF",
+                        value
+                    ))
+                })
+            })
+            .collect::<Result<Vec<u8>>>()?;
+        Ok(StringColumnReader::from_bytes(&bytes))
+    }
+
+    /// Run-length encodes one byte per row.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut column = StringColumnReader::empty();
+        for &byte in bytes {
+            match (column.values.last(), column.run_lengths.last_mut()) {
+                (Some(last), Some(count)) if *last == byte => *count += 1,
+                _ => {
+                    column.values.push(byte);
+                    column.run_lengths.push(1);
+                }
+            }
         }
+        column.column_entries = column.values.len() as u64;
+        column
     }
 
     pub fn empty() -> Self {
-        let data = Vec::with_capacity(MAX_ROW_GROUP_SIZE*5);
         StringColumnReader {
-            data,
+            values: Vec::with_capacity(DEFAULT_ROW_GROUP_SIZE),
+            run_lengths: Vec::with_capacity(DEFAULT_ROW_GROUP_SIZE),
             column_entries: 0,
             item_index: 0,
             repeat_index: 0,
         }
     }
 
-    pub fn new<R: Read>(reader: &mut std::io::BufReader<R>) -> Self {
-        let mut data = Vec::with_capacity(MAX_ROW_GROUP_SIZE*5);
-        let column_entries = read_u8_string_column_to_vec(reader, &mut data);
-        StringColumnReader {
-            data,
-            column_entries,
-            item_index: 0,
-            repeat_index: 0,
+    pub fn new<R: Read>(reader: &mut std::io::BufReader<R>) -> Result<Self> {
+        let mut column = StringColumnReader::empty();
+        column.read(reader)?;
+        Ok(column)
+    }
+
+    /// Reads a string column from a headerless legacy file.
+    pub fn new_legacy<R: Read>(reader: &mut std::io::BufReader<R>) -> Result<Self> {
+        let mut column = StringColumnReader::empty();
+        column.read_legacy(reader)?;
+        Ok(column)
+    }
+
+    /// Moves the reader past a string column without decoding it.
+    pub fn skip<R: Read + Seek>(reader: &mut std::io::BufReader<R>) -> Result<()> {
+        let column_entries = read_column_entries(reader)?;
+        reader.seek_relative(column_entries as i64)?;
+        io::read_padding(reader)?;
+        reader.seek_relative(4 * column_entries as i64)?;
+        Ok(())
+    }
+
+    pub fn write(&self, writer: &mut TrackedWriter<impl Write>) -> Result<()> {
+        write_u8_string_column_from_vec(writer, &self.values, &self.run_lengths)
+    }
+
+    pub fn view(&self) -> StringColumnRef<'_> {
+        StringColumnRef {
+            values: &self.values,
+            run_lengths: &self.run_lengths,
         }
     }
 
-    pub fn write(&self, writer: &mut TrackedWriter<impl Write>) {
-        write_u8_string_column_from_vec(writer, &self.data);
+    /// The `(value, run length)` entries of the column
+    pub fn compressed_iterator(&self) -> impl Iterator<Item = (u8, u32)> + '_ {
+        self.view().compressed_iterator()
     }
 
-    pub fn compressed_iterator(&self) -> impl Iterator<Item = &(u8, u32)> {
-        self.data.iter()
+    pub fn read(&mut self, reader: &mut std::io::BufReader<impl Read>) -> Result<()> {
+        self.column_entries =
+            read_u8_string_column_to_vec(reader, &mut self.values, &mut self.run_lengths)?;
+        self.item_index = 0;
+        self.repeat_index = 0;
+        Ok(())
     }
 
-    pub fn read(&mut self, reader: &mut std::io::BufReader<impl Read>) {
-        self.column_entries = read_u8_string_column_to_vec(reader, &mut self.data);
+    pub fn read_legacy(&mut self, reader: &mut std::io::BufReader<impl Read>) -> Result<()> {
+        self.column_entries =
+            read_legacy_string_column_to_vec(reader, &mut self.values, &mut self.run_lengths)?;
         self.item_index = 0;
         self.repeat_index = 0;
+        Ok(())
+    }
+
+    pub fn count_strings(&self) -> u64 {
+        self.view().count_strings()
+    }
+}
+
+/// String column entries borrowed from a `StringColumnReader` or parsed in
+/// place from a mapped column chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StringColumnRef<'a> {
+    pub values: &'a [u8],
+    pub run_lengths: &'a [u32],
+}
+
+impl<'a> StringColumnRef<'a> {
+    /// Views a column chunk without copying its entries. `chunk` must start
+    /// at an offset of the file that is a multiple of 4, and the run lengths
+    /// can only be viewed in place on a little-endian target.
+    #[cfg(target_endian = "little")]
+    pub fn parse(chunk: &'a [u8]) -> Result<Self> {
+        let mut rest = chunk;
+        let column_entries = read_column_entries(&mut rest)? as usize;
+        if rest.len() < column_entries {
+            return Err(Error::corrupt("string column values are truncated"));
+        }
+        let (values, mut rest) = rest.split_at(column_entries);
+        io::read_padding(&mut rest)?;
+        let run_lengths = rest
+            .get(..4 * column_entries)
+            .ok_or_else(|| Error::corrupt("string column run lengths are truncated"))?;
+        Ok(StringColumnRef {
+            values,
+            run_lengths: crate::f64_column::cast_aligned(run_lengths)?,
+        })
+    }
+
+    /// The `(value, run length)` entries of the column
+    pub fn compressed_iterator(&self) -> impl Iterator<Item = (u8, u32)> + 'a {
+        self.values
+            .iter()
+            .copied()
+            .zip(self.run_lengths.iter().copied())
     }
 
     pub fn count_strings(&self) -> u64 {
-        self.data.iter().map(|x| x.1 as u64).sum()
+        self.run_lengths.iter().map(|x| *x as u64).sum()
+    }
+}
+
+impl Default for StringColumnReader {
+    fn default() -> Self {
+        StringColumnReader::empty()
     }
 }
 
@@ -128,21 +260,20 @@ impl Iterator for StringColumnReader {
             "item_index: {}, repeat_index: {}, item_count: {}",
             self.item_index, self.repeat_index, self.column_entries
         );
-        if self.item_index < self.column_entries as u16 {
-            let (value, count) = self.data[self.item_index as usize];
-            if self.repeat_index < count as i16 {
+        if self.item_index < self.column_entries as u32 {
+            let value = self.values[self.item_index as usize];
+            let count = self.run_lengths[self.item_index as usize];
+            if self.repeat_index < count {
                 self.repeat_index += 1;
-                return Some(
-                    std::string::String::from_utf8(vec![value])
-                        .expect("Failed to convert to string"),
-                );
+                // The byte is a Latin-1 code point
+                Some((value as char).to_string())
             } else {
                 self.item_index += 1;
                 self.repeat_index = 0;
-                return self.next();
+                self.next()
             }
         } else {
-            return None;
+            None
         }
     }
 }
@@ -155,23 +286,94 @@ mod tests {
 
     #[test]
     fn test_read_write_string_column() {
-        let input = vec!["a", "a", "b", "b", "b", "c"]
+        let input = ["a", "a", "b", "b", "é", "c"]
             .iter()
             .map(|s| s.to_string())
             .collect::<Vec<_>>();
 
         let input_refs: Vec<&str> = input.iter().map(|s| s.as_str()).collect();
-        let writer_string_reader = StringColumnReader::new_from_strings(input_refs);
+        let writer_string_reader = StringColumnReader::new_from_strings(input_refs).unwrap();
         let mut writer = TrackedWriter::new(Vec::new());
         // Write column
-        writer_string_reader.write(&mut writer);
+        writer_string_reader.write(&mut writer).unwrap();
         let written = writer.into_inner().into_inner().unwrap();
 
         // Read column back
         let mut reader = BufReader::new(Cursor::new(written));
-        let col_reader = StringColumnReader::new(&mut reader);
+        let col_reader = StringColumnReader::new(&mut reader).unwrap();
         let output: Vec<String> = col_reader.collect();
 
         assert_eq!(input, output);
     }
+
+    #[test]
+    fn test_iterate_latin_1_bytes() {
+        let column = StringColumnReader::from_bytes(&[b'A', 0xE9, 0xE9]);
+
+        assert_eq!(column.collect::<Vec<_>>(), ["A", "é", "é"]);
+    }
+
+    #[test]
+    fn test_new_from_strings_rejects_values_that_are_not_one_char() {
+        for value in ["AB", "", "€"] {
+            assert!(
+                matches!(
+                    StringColumnReader::new_from_strings(vec!["A", value]),
+                    Err(Error::InvalidValue(_))
+                ),
+                "{:?} should be rejected",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_truncated_string_column() {
+        let writer_string_reader = StringColumnReader::new_from_strings(vec!["a", "b"]).unwrap();
+        let mut writer = TrackedWriter::new(Vec::new());
+        writer_string_reader.write(&mut writer).unwrap();
+        let mut written = writer.into_inner().into_inner().unwrap();
+        written.pop();
+
+        let mut reader = BufReader::new(Cursor::new(written));
+
+        assert!(matches!(
+            StringColumnReader::new(&mut reader),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_on_disk_layout() {
+        let column = StringColumnReader::new_from_strings(vec!["A", "A", "A", "N"]).unwrap();
+        let mut writer = TrackedWriter::new(Vec::new());
+        column.write(&mut writer).unwrap();
+        let written = writer.into_inner().into_inner().unwrap();
+
+        let mut expected = 2u64.to_le_bytes().to_vec();
+        expected.extend_from_slice(b"AN");
+        // One byte of padding puts the run lengths at offset 12
+        expected.extend_from_slice(&[1, 0]);
+        expected.extend_from_slice(&3u32.to_le_bytes());
+        expected.extend_from_slice(&1u32.to_le_bytes());
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn test_read_legacy_string_column() {
+        let mut written = 2u64.to_le_bytes().to_vec();
+        // Padding bytes were never initialised, so they may hold anything
+        written.extend_from_slice(&[b'R', 0xAA, 0xBB, 0xCC]);
+        written.extend_from_slice(&3u32.to_le_bytes());
+        written.extend_from_slice(&[b'F', 0, 0, 0]);
+        written.extend_from_slice(&70000u32.to_le_bytes());
+
+        let mut reader = BufReader::new(Cursor::new(written));
+        let column = StringColumnReader::new_legacy(&mut reader).unwrap();
+
+        assert_eq!(
+            column.compressed_iterator().collect::<Vec<_>>(),
+            vec![(b'R', 3), (b'F', 70000)]
+        );
+    }
 }
@@ -0,0 +1,467 @@
+//! A DataFusion `TableProvider` over a column file, so that
+//! `ctx.register_table("lineitem", Arc::new(AbdbTable::open(path)?))` runs
+//! arbitrary SQL on the abdb encodings. Projections are read column chunk by
+//! column chunk, and filters comparing a column with a literal prune row
+//! groups by their statistics and rows by the selection kernels.
+
+use std::any::Any;
+use std::fmt;
+use std::fs::File;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use datafusion::arrow::datatypes::{DataType, SchemaRef};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::common::{ScalarValue, Statistics};
+use datafusion::datasource::TableProvider;
+use datafusion::error::Result as DataFusionResult;
+use datafusion::execution::context::SessionState;
+use datafusion::execution::TaskContext;
+use datafusion::logical_expr::{
+    Between, BinaryExpr, Cast, Expr, Operator, TableProviderFilterPushDown, TableType, TryCast,
+};
+use datafusion::physical_expr::PhysicalSortExpr;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::{
+    DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, SendableRecordBatchStream,
+};
+
+use crate::column_file::ColumnFileReader;
+use crate::error::Result;
+use crate::predicate::{CompareOp, Predicate};
+use crate::record_batch::{arrow_schema, record_batch};
+use crate::row_group::char_byte;
+use crate::schema::{LogicalType, Schema};
+use crate::selection::Selection;
+
+#[derive(Debug)]
+pub struct AbdbTable {
+    path: String,
+    schema: Schema,
+    arrow_schema: SchemaRef,
+}
+
+impl AbdbTable {
+    /// Reads the header and footer of the column file at `path`; scans open
+    /// the file again for each partition.
+    pub fn open(path: &str) -> Result<Self> {
+        let reader = ColumnFileReader::open(path)?;
+        let schema = reader.schema().clone();
+        Ok(AbdbTable {
+            path: path.to_string(),
+            arrow_schema: arrow_schema(&schema),
+            schema,
+        })
+    }
+}
+
+#[async_trait]
+impl TableProvider for AbdbTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.arrow_schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    // Pushed filters are also evaluated by DataFusion, since they are
+    // compared as f64 and may only cover part of a conjunction
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> DataFusionResult<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(|x| match predicates(x, &self.schema) {
+                Some(_) => TableProviderFilterPushDown::Inexact,
+                None => TableProviderFilterPushDown::Unsupported,
+            })
+            .collect())
+    }
+
+    async fn scan(
+        &self,
+        state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let projection = match projection {
+            Some(projection) => projection.clone(),
+            None => (0..self.schema.len()).collect(),
+        };
+        let predicates: Vec<Predicate> = filters
+            .iter()
+            .filter_map(|x| predicates(x, &self.schema))
+            .flatten()
+            .collect();
+        let row_groups = ColumnFileReader::open(&self.path)?.matching_row_groups(&predicates)?;
+
+        // Contiguous row groups per partition, so each reads its part of the
+        // file front to back
+        let partition_count = state
+            .config()
+            .target_partitions()
+            .clamp(1, row_groups.len().max(1));
+        let partition_size = row_groups.len().div_ceil(partition_count).max(1);
+        let mut partitions: Vec<Vec<usize>> = row_groups
+            .chunks(partition_size)
+            .map(|x| x.to_vec())
+            .collect();
+        if partitions.is_empty() {
+            partitions.push(Vec::new());
+        }
+
+        Ok(Arc::new(AbdbExec {
+            path: self.path.clone(),
+            schema: self.schema.clone(),
+            projected_schema: Arc::new(self.arrow_schema.project(&projection)?),
+            projection,
+            predicates,
+            partitions,
+            limit,
+        }))
+    }
+}
+
+/// Scans the row groups of a column file, one partition per run of row
+/// groups.
+#[derive(Debug)]
+pub struct AbdbExec {
+    path: String,
+    schema: Schema,
+    projected_schema: SchemaRef,
+    projection: Vec<usize>,
+    predicates: Vec<Predicate>,
+    partitions: Vec<Vec<usize>>,
+    limit: Option<usize>,
+}
+
+impl DisplayAs for AbdbExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "AbdbExec: path={}, partitions={}, projection={:?}, predicates={:?}",
+            self.path,
+            self.partitions.len(),
+            self.projection,
+            self.predicates
+        )?;
+        if let Some(limit) = self.limit {
+            write!(f, ", limit={}", limit)?;
+        }
+        Ok(())
+    }
+}
+
+impl ExecutionPlan for AbdbExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.projected_schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.partitions.len())
+    }
+
+    fn output_ordering(&self) -> Option<&[PhysicalSortExpr]> {
+        None
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        Vec::new()
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        _children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        Ok(self)
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        _context: Arc<TaskContext>,
+    ) -> DataFusionResult<SendableRecordBatchStream> {
+        // The predicate columns are read even when not projected
+        let mut columns = self.projection.clone();
+        for predicate in &self.predicates {
+            let index = self
+                .schema
+                .index_of(&predicate.column)
+                .expect("Pushed-down predicates name table columns");
+            if !columns.contains(&index) {
+                columns.push(index);
+            }
+        }
+        let batches = PartitionReader {
+            reader: ColumnFileReader::open(&self.path)?,
+            schema: self.schema.clone(),
+            row_groups: self.partitions[partition].clone().into_iter(),
+            columns,
+            projection: self.projection.clone(),
+            predicates: self.predicates.clone(),
+            selection: Selection::new(),
+            remaining: self.limit.unwrap_or(usize::MAX),
+        };
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.projected_schema.clone(),
+            futures::stream::iter(batches),
+        )))
+    }
+
+    fn statistics(&self) -> DataFusionResult<Statistics> {
+        Ok(Statistics::new_unknown(&self.schema()))
+    }
+}
+
+/// One record batch per row group of a partition, stopping after `limit`
+/// rows.
+struct PartitionReader {
+    reader: ColumnFileReader<File>,
+    schema: Schema,
+    row_groups: std::vec::IntoIter<usize>,
+    columns: Vec<usize>,
+    projection: Vec<usize>,
+    predicates: Vec<Predicate>,
+    selection: Selection,
+    remaining: usize,
+}
+
+impl PartitionReader {
+    fn read(&mut self, index: usize) -> Result<RecordBatch> {
+        let row_group = self.reader.read_columns(index, &self.columns)?;
+        row_group.select(&self.schema, &self.predicates, &mut self.selection)?;
+        let selected = (!self.selection.all_selected()).then(|| self.selection.flags());
+        record_batch(&row_group, &self.schema, &self.projection, selected)
+    }
+}
+
+impl Iterator for PartitionReader {
+    type Item = DataFusionResult<RecordBatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let index = self.row_groups.next()?;
+        Some(match self.read(index) {
+            Ok(batch) => {
+                let batch = batch.slice(0, batch.num_rows().min(self.remaining));
+                self.remaining -= batch.num_rows();
+                Ok(batch)
+            }
+            Err(e) => {
+                self.remaining = 0;
+                Err(e.into())
+            }
+        })
+    }
+}
+
+/// The abdb predicates implied by `filter`, if it compares columns with
+/// literals. Only some conjuncts of an AND may be converted.
+fn predicates(filter: &Expr, schema: &Schema) -> Option<Vec<Predicate>> {
+    match filter {
+        Expr::BinaryExpr(BinaryExpr {
+            left,
+            op: Operator::And,
+            right,
+        }) => {
+            let predicates: Vec<_> = [left, right]
+                .into_iter()
+                .filter_map(|x| predicates(x, schema))
+                .flatten()
+                .collect();
+            (!predicates.is_empty()).then_some(predicates)
+        }
+        Expr::BinaryExpr(BinaryExpr { left, op, right }) => {
+            let (column, op, literal) = match (column(left, schema), column(right, schema)) {
+                (Some(column), None) => (column, *op, right),
+                (None, Some(column)) => (column, op.swap()?, left),
+                _ => return None,
+            };
+            Some(vec![comparison(column, op, literal, schema)?])
+        }
+        Expr::Between(Between {
+            expr,
+            negated: false,
+            low,
+            high,
+        }) => {
+            let column = column(expr, schema)?;
+            Some(vec![
+                comparison(column, Operator::GtEq, low, schema)?,
+                comparison(column, Operator::LtEq, high, schema)?,
+            ])
+        }
+        _ => None,
+    }
+}
+
+/// The index of the column `expr` reads, looking through casts that keep
+/// every value and the order between them.
+fn column(expr: &Expr, schema: &Schema) -> Option<usize> {
+    match expr {
+        Expr::Column(column) => schema.index_of(&column.name),
+        Expr::Cast(Cast { expr, data_type }) | Expr::TryCast(TryCast { expr, data_type }) => {
+            let index = column(expr, schema)?;
+            let exact = match (schema.fields[index].logical_type, data_type) {
+                (LogicalType::Decimal { .. }, DataType::Float64) => true,
+                (LogicalType::Decimal { scale }, DataType::Decimal128(_, to_scale)) => {
+                    *to_scale >= scale as i8
+                }
+                (LogicalType::Char, DataType::Utf8 | DataType::LargeUtf8) => true,
+                (LogicalType::Date, DataType::Date32) => true,
+                _ => false,
+            };
+            exact.then_some(index)
+        }
+        _ => None,
+    }
+}
+
+fn comparison(column: usize, op: Operator, literal: &Expr, schema: &Schema) -> Option<Predicate> {
+    let Expr::Literal(value) = literal else {
+        return None;
+    };
+    let op = match op {
+        Operator::Eq => CompareOp::Eq,
+        Operator::NotEq => CompareOp::NotEq,
+        Operator::Lt => CompareOp::Lt,
+        Operator::LtEq => CompareOp::LtEq,
+        Operator::Gt => CompareOp::Gt,
+        Operator::GtEq => CompareOp::GtEq,
+        _ => return None,
+    };
+    let field = &schema.fields[column];
+    let value = predicate_value(value, field.logical_type)?;
+    Some(Predicate::new(&field.name, op, value))
+}
+
+/// `value` in the domain `Predicate` compares columns of `logical_type` in
+fn predicate_value(value: &ScalarValue, logical_type: LogicalType) -> Option<f64> {
+    match (logical_type, value) {
+        (_, ScalarValue::Dictionary(_, value)) => predicate_value(value, logical_type),
+        (LogicalType::Decimal { .. }, ScalarValue::Float64(Some(x))) => Some(*x),
+        (LogicalType::Decimal { .. }, ScalarValue::Int64(Some(x))) => Some(*x as f64),
+        (LogicalType::Decimal { .. }, ScalarValue::Int32(Some(x))) => Some(*x as f64),
+        (LogicalType::Decimal { .. }, ScalarValue::Decimal128(Some(x), _, scale)) => {
+            Some(*x as f64 / 10f64.powi(*scale as i32))
+        }
+        (LogicalType::Date, ScalarValue::Date32(Some(x))) => Some(*x as f64),
+        (LogicalType::Char, ScalarValue::Utf8(Some(x)) | ScalarValue::LargeUtf8(Some(x))) => {
+            char_byte(x).map(|x| x as f64)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::arrow::array::AsArray;
+    use datafusion::arrow::datatypes::{Decimal128Type, Int64Type};
+    use datafusion::prelude::{cast, col, lit, SessionContext};
+
+    use super::*;
+    use crate::schema::lineitem_schema;
+    use crate::test_data::{lineitems, TempColumnFile};
+    use crate::{query_6_column, QuerySixParams};
+
+    #[test]
+    fn test_filters_become_predicates() {
+        let schema = lineitem_schema();
+
+        let filter = col("l_quantity")
+            .lt(lit(24.0))
+            .and(lit("A").eq(col("l_returnflag")));
+        assert_eq!(
+            predicates(&filter, &schema),
+            Some(vec![
+                Predicate::lt("l_quantity", 24.0),
+                Predicate::eq("l_returnflag", b'A' as f64)
+            ])
+        );
+
+        let filter = cast(col("l_discount"), DataType::Float64).between(lit(0.05), lit(0.07));
+        assert_eq!(
+            predicates(&filter, &schema),
+            Some(vec![
+                Predicate::gt_eq("l_discount", 0.05),
+                Predicate::lt_eq("l_discount", 0.07)
+            ])
+        );
+
+        // Only the convertible half of a conjunction is pushed down
+        let filter = col("l_tax")
+            .lt(col("l_discount"))
+            .and(col("l_shipdate").gt(lit(ScalarValue::Date32(Some(10000)))));
+        assert_eq!(
+            predicates(&filter, &schema),
+            Some(vec![Predicate::gt("l_shipdate", 10000.0)])
+        );
+
+        // Truncating casts and multi-byte strings cannot be compared exactly
+        assert_eq!(
+            predicates(&cast(col("l_tax"), DataType::Int64).gt(lit(0i64)), &schema),
+            None
+        );
+        assert_eq!(
+            predicates(&col("l_linestatus").eq(lit("FO")), &schema),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sql_through_datafusion_matches_column_scan() {
+        let lineitems = lineitems();
+        let file = TempColumnFile::new("datafusion", &lineitems);
+        let path = file.path();
+
+        let ctx = SessionContext::new();
+        ctx.register_table("lineitem", Arc::new(AbdbTable::open(path).unwrap()))
+            .unwrap();
+        let revenue = ctx
+            .sql(
+                "SELECT sum(l_extendedprice * l_discount) FROM lineitem
+                WHERE l_shipdate >= DATE '1994-01-01'
+                    AND l_shipdate < DATE '1995-01-01'
+                    AND l_discount BETWEEN 0.05 AND 0.07
+                    AND l_quantity < 24",
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let count = ctx
+            .sql("SELECT count(*) FROM lineitem WHERE l_returnflag = 'R' AND l_tax > 0.04")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let expected = query_6_column(path, &QuerySixParams::default()).unwrap();
+
+        let revenue = revenue[0].column(0).as_primitive::<Decimal128Type>();
+        assert_eq!(
+            revenue.value(0) * 10i128.pow(expected.scale as u32),
+            expected.unscaled * 10i128.pow(revenue.scale() as u32)
+        );
+        let count = count[0].column(0).as_primitive::<Int64Type>().value(0);
+        let expected = lineitems
+            .iter()
+            .filter(|x| x.l_returnflag == "R" && x.l_tax > 0.04)
+            .count();
+        assert_eq!(count, expected as i64);
+    }
+}
@@ -0,0 +1,57 @@
+//! Line items and column files shared by the unit tests.
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::column_file::ColumnFileWriter;
+use crate::LineItem;
+
+/// 2000 line items covering every flag and status, prices with cents and
+/// ship dates from 1993 to 1998.
+pub fn lineitems() -> Vec<LineItem> {
+    (0..2000)
+        .map(|i| LineItem {
+            l_returnflag: ["A", "N", "R"][i % 3].to_string(),
+            l_linestatus: ["F", "O"][i % 2].to_string(),
+            l_quantity: (i % 50) as f64,
+            l_extendedprice: (90000 + (i * 7919) % 100000) as f64 / 100.0,
+            l_discount: (i % 11) as f64 / 100.0,
+            l_tax: (i % 9) as f64 / 100.0,
+            l_shipdate: 8500 + i as i32,
+        })
+        .collect()
+}
+
+/// A column file in the temp directory, removed when dropped so a failing
+/// test does not leave it behind.
+pub struct TempColumnFile {
+    path: PathBuf,
+}
+
+impl TempColumnFile {
+    /// Writes `lineitems` in row groups of 100 rows, naming the file after
+    /// `name` and the process so concurrent test runs do not collide.
+    pub fn new(name: &str, lineitems: &[LineItem]) -> Self {
+        Self::with_row_group_size(name, lineitems, 100)
+    }
+
+    /// Like [`TempColumnFile::new`] with `row_group_size` rows per group.
+    pub fn with_row_group_size(name: &str, lineitems: &[LineItem], row_group_size: usize) -> Self {
+        let path = std::env::temp_dir().join(format!("abdb_{}_{}.bin", name, std::process::id()));
+        let file = std::fs::File::create(&path).unwrap();
+        let mut writer = ColumnFileWriter::new(file).with_row_group_size(row_group_size);
+        writer.write_batch(&mut lineitems.to_vec()).unwrap();
+        writer.finish().unwrap().flush().unwrap();
+        TempColumnFile { path }
+    }
+
+    pub fn path(&self) -> &str {
+        self.path.to_str().unwrap()
+    }
+}
+
+impl Drop for TempColumnFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -1,5 +1,9 @@
 #[cfg(test)]
 use super::*;
+#[cfg(test)]
+use std::io::BufRead;
+#[cfg(test)]
+use crate::test_data::{lineitems, TempColumnFile};
 
 #[test]
 fn test_compress_f64() {
@@ -42,12 +46,13 @@ fn test_write_and_read_row_group() {
         l_extendedprice: 2.0,
         l_discount: 3.0,
         l_tax: 4.0,
+        l_shipdate: 10000,
     });
     let buffer = Vec::new();
     let mut writer = TrackedWriter::new(std::io::BufWriter::new(buffer));
 
-    write_row_group(&lineitems[0..9], &mut writer);
-    write_row_group(&lineitems[9..20], &mut writer);
+    write_row_group(&lineitems[0..9], &mut writer).unwrap();
+    write_row_group(&lineitems[9..20], &mut writer).unwrap();
 
     let binding = writer
         .into_inner()
@@ -59,9 +64,10 @@ fn test_write_and_read_row_group() {
         let buffer: &[u8] = &binding;
         std::io::BufReader::new(buffer)
     };
+    header::FileHeader::read(&mut reader).unwrap();
 
-    let read_lineitems1 = read_row_group(&mut reader);
-    let read_lineitems2 = read_row_group(&mut reader);
+    let read_lineitems1 = read_row_group(&mut reader).unwrap();
+    let read_lineitems2 = read_row_group(&mut reader).unwrap();
     let read_lineitems = read_lineitems1
         .iter()
         .chain(read_lineitems2.iter())
@@ -80,12 +86,13 @@ fn test_update_state_from_row_group() {
         l_extendedprice: 2.0,
         l_discount: 3.0,
         l_tax: 4.0,
+        l_shipdate: 10000,
     });
     let buffer = Vec::new();
     let mut writer = TrackedWriter::new(std::io::BufWriter::new(buffer));
 
-    write_row_group(&lineitems[0..1000], &mut writer);
-    write_row_group(&lineitems[1000..2000], &mut writer);
+    write_row_group(&lineitems[0..1000], &mut writer).unwrap();
+    write_row_group(&lineitems[1000..2000], &mut writer).unwrap();
 
     let binding = writer
         .into_inner()
@@ -97,6 +104,7 @@ fn test_update_state_from_row_group() {
         let buffer: &[u8] = &binding;
         std::io::BufReader::new(buffer)
     };
+    header::FileHeader::read(&mut reader).unwrap();
 
     let mut state: Vec<Option<QueryOneStateColumn>> = vec![None; 256 * 256];
 
@@ -105,7 +113,7 @@ fn test_update_state_from_row_group() {
             println!("End of file");
             break;
         }
-        update_state_from_row_group(&mut reader, &mut state);
+        update_state_from_row_group(&mut reader, 10000, &mut state).unwrap();
     }
     assert_eq!(
         state[get_state_index(&b'A', &b'B')],
@@ -114,9 +122,169 @@ fn test_update_state_from_row_group() {
             sum_qty: 200000,
             sum_base_price: 400000,
             sum_discount: 600000,
-            sum_tax: 800000
+            sum_tax: 800000,
+            sum_disc_price: -80_000_000,
+            sum_charge: -40_000_000_000
+        })
+    );
+}
+
+#[test]
+fn test_scan_context_reuses_buffers_across_row_groups() {
+    let lineitem = |returnflag: &str, quantity: f64, shipdate: i32| LineItem {
+        l_returnflag: returnflag.to_string(),
+        l_linestatus: "F".to_string(),
+        l_quantity: quantity,
+        l_extendedprice: 1000.0,
+        l_discount: 0.1,
+        l_tax: 0.05,
+        l_shipdate: shipdate,
+    };
+    // The second row group is shorter, packs narrower and is partly filtered
+    let mut writer = TrackedWriter::new(Vec::new());
+    write_row_group(&vec![lineitem("A", 900.0, 10000); 300], &mut writer).unwrap();
+    write_row_group(
+        &[lineitem("N", 1.0, 10000), lineitem("N", 2.0, 20000)],
+        &mut writer,
+    )
+    .unwrap();
+    let binding = writer.into_inner().into_inner().unwrap();
+    let mut reader = std::io::BufReader::new(binding.as_slice());
+    header::FileHeader::read(&mut reader).unwrap();
+
+    let mut context = ScanContext::new();
+    let mut state: Vec<Option<QueryOneStateColumn>> = vec![None; 256 * 256];
+    context
+        .update_state_from_row_group(&mut reader, 10000, &mut state)
+        .unwrap();
+    context
+        .update_state_from_row_group(&mut reader, 10000, &mut state)
+        .unwrap();
+
+    let a_state = state[get_state_index(&b'A', &b'F')].as_ref().unwrap();
+    assert_eq!((a_state.count, a_state.sum_qty), (300, 300 * 90000));
+    let n_state = state[get_state_index(&b'N', &b'F')].as_ref().unwrap();
+    assert_eq!((n_state.count, n_state.sum_qty), (1, 100));
+    assert_eq!(n_state.sum_disc_price, 100000 * 90);
+}
+
+#[test]
+fn test_read_f64_column_returns_only_valid_rows() {
+    let mut writer = TrackedWriter::new(Vec::new());
+    f64_column::write_f64_column([1.5, 2.25, 3.0].into_iter(), &mut writer).unwrap();
+    let binding = writer.into_inner().into_inner().unwrap();
+    let mut reader = std::io::BufReader::new(binding.as_slice());
+
+    assert_eq!(read_f64_column(&mut reader, 3).unwrap(), vec![1.5, 2.25, 3.0]);
+}
+
+#[test]
+fn test_update_state_from_row_group_filters_shipdate() {
+    let lineitems: Vec<LineItem> = (0..10)
+        .map(|i| LineItem {
+            l_returnflag: if i < 5 { "A" } else { "N" }.to_string(),
+            l_linestatus: "F".to_string(),
+            l_quantity: i as f64,
+            l_extendedprice: 10.0,
+            l_discount: 0.1,
+            l_tax: 0.05,
+            l_shipdate: 10000 + i,
+        })
+        .collect();
+    let mut writer = TrackedWriter::new(Vec::new());
+    write_row_group(&lineitems, &mut writer).unwrap();
+    let binding = writer.into_inner().into_inner().unwrap();
+    let mut reader = std::io::BufReader::new(binding.as_slice());
+    header::FileHeader::read(&mut reader).unwrap();
+
+    let mut state: Vec<Option<QueryOneStateColumn>> = vec![None; 256 * 256];
+    update_state_from_row_group(&mut reader, 10002, &mut state).unwrap();
+
+    assert_eq!(
+        state[get_state_index(&b'A', &b'F')],
+        Some(QueryOneStateColumn {
+            count: 3,
+            sum_qty: 300,
+            sum_base_price: 3000,
+            sum_discount: 30,
+            sum_tax: 15,
+            sum_disc_price: 270_000,
+            sum_charge: 28_350_000
         })
     );
+    assert_eq!(state[get_state_index(&b'N', &b'F')], None);
+}
+
+#[test]
+fn test_query_1_column_applies_shipdate_cutoff() {
+    let lineitem = |returnflag: &str, l_shipdate: i32| LineItem {
+        l_returnflag: returnflag.to_string(),
+        l_linestatus: "F".to_string(),
+        l_quantity: 2.0,
+        l_extendedprice: 100.0,
+        l_discount: 0.0,
+        l_tax: 0.0,
+        l_shipdate,
+    };
+    let path = std::env::temp_dir().join(format!("abdb_shipdate_{}.bin", std::process::id()));
+    let file = std::fs::File::create(&path).unwrap();
+    let mut writer = column_file::ColumnFileWriter::new(file);
+    writer.write_batch(&mut [lineitem("A", 8036), lineitem("R", 8037)]).unwrap();
+    writer.write_batch(&mut [lineitem("A", 10471), lineitem("N", 10472)]).unwrap();
+    writer.finish().unwrap().flush().unwrap();
+    let path = path.to_str().unwrap();
+
+    let early = query_1_column(path, 8036).unwrap();
+    let all = query_1_column(path, date_column::parse_date("1998-12-01").unwrap()).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(early.len(), 1);
+    assert_eq!(early[0].l_returnflag, "A");
+    assert_eq!(early[0].count_order, 1);
+    let counts: Vec<(&str, u64)> = all
+        .iter()
+        .map(|x| (x.l_returnflag.as_str(), x.count_order))
+        .collect();
+    assert_eq!(counts, vec![("A", 2), ("N", 1), ("R", 1)]);
+}
+
+#[test]
+fn test_query_1_column_parallel_matches_sequential() {
+    let file = TempColumnFile::new("parallel", &lineitems());
+    let path = file.path();
+
+    let sequential = query_1_column(path, 10500).unwrap();
+    let parallel: Vec<_> = [1, 3, 8, 64]
+        .iter()
+        .map(|threads| query_1_column_parallel(path, 10500, *threads).unwrap())
+        .collect();
+    let mapped: Vec<_> = [1, 3]
+        .iter()
+        .map(|threads| query_1_column_mapped(path, 10500, *threads).unwrap())
+        .collect();
+    let no_threads = query_1_column_parallel(path, 10500, 0);
+
+    assert_eq!(sequential.len(), 6);
+    for result in parallel.into_iter().chain(mapped) {
+        assert_eq!(result, sequential);
+    }
+    assert!(matches!(no_threads, Err(Error::InvalidQuery(_))));
+}
+
+#[test]
+fn test_query_1_column_reports_errors() {
+    let path = std::env::temp_dir().join(format!("abdb_errors_{}.bin", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    assert!(matches!(query_1_column(path, 0), Err(Error::Io(_))));
+
+    std::fs::write(path, b"not a column file").unwrap();
+    let foreign = query_1_column(path, 0);
+    let legacy = query_1_column_with_mode(path, header::FileMode::Legacy, 0);
+    std::fs::remove_file(path).unwrap();
+
+    assert!(matches!(foreign, Err(Error::BadMagic(_))));
+    assert!(matches!(legacy, Err(Error::InvalidQuery(_))));
 }
 
 #[test]
@@ -125,3 +293,93 @@ fn test_get_state_index() {
     assert_eq!(get_state_index(&b'B', &b'O'), 66 * 256 + 79);
     assert_eq!(get_state_index(&b'C', &b'N'), 67 * 256 + 78);
 }
+
+#[test]
+fn test_update_state_from_legacy_row_group() {
+    let mut writer = TrackedWriter::new(Vec::new());
+    writer.write_all(&3u16.to_le_bytes()).unwrap();
+    // Legacy string columns hold one padded 8-byte record per run
+    for value in [b'F', b'R'] {
+        writer.write_all(&1u64.to_le_bytes()).unwrap();
+        writer.write_all(&[value, 0, 0, 0]).unwrap();
+        writer.write_all(&3u32.to_le_bytes()).unwrap();
+    }
+    for value in [1.5, 0.05, 0.02, 600.0] {
+        f64_column::write_f64_column(std::iter::repeat_n(value, 3), &mut writer).unwrap();
+    }
+    let binding = writer.into_inner().into_inner().unwrap();
+    let mut reader = std::io::BufReader::new(binding.as_slice());
+
+    let mut state: Vec<Option<QueryOneStateColumn>> = vec![None; 256 * 256];
+    update_state_from_legacy_row_group(&mut reader, &mut state).unwrap();
+
+    assert_eq!(
+        state[get_state_index(&b'R', &b'F')],
+        Some(QueryOneStateColumn {
+            count: 3,
+            sum_qty: 450,
+            sum_base_price: 180000,
+            sum_discount: 15,
+            sum_tax: 6,
+            sum_disc_price: 17_100_000,
+            sum_charge: 1_744_200_000
+        })
+    );
+}
+
+#[test]
+fn test_query_1_result_is_ordered_with_exact_sums() {
+    let mut state: Vec<Option<QueryOneStateColumn>> = vec![None; 256 * 256];
+    state[get_state_index(&b'R', &b'F')] = Some(QueryOneStateColumn {
+        count: 3,
+        sum_qty: 450,
+        sum_base_price: 180000,
+        sum_discount: 15,
+        sum_tax: 6,
+        sum_disc_price: 17_100_000,
+        sum_charge: 1_744_200_000,
+    });
+    state[get_state_index(&b'A', &b'F')] = Some(QueryOneStateColumn {
+        count: 1,
+        ..Default::default()
+    });
+
+    let rows = query_1_result(&state);
+
+    assert_eq!(rows.len(), 2);
+    assert_eq!((rows[0].l_returnflag.as_str(), rows[0].l_linestatus.as_str()), ("A", "F"));
+    let row = &rows[1];
+    assert_eq!((row.l_returnflag.as_str(), row.l_linestatus.as_str()), ("R", "F"));
+    assert_eq!(row.sum_qty.to_string(), "4.50");
+    assert_eq!(row.sum_base_price.to_string(), "1800.00");
+    assert_eq!(row.sum_disc_price.to_string(), "1710.0000");
+    assert_eq!(row.sum_charge.to_string(), "1744.200000");
+    assert_eq!(row.avg_qty, 1.5);
+    assert_eq!(row.avg_price, 600.0);
+    assert_eq!(row.avg_disc, 0.05);
+    assert_eq!(row.count_order, 3);
+}
+
+#[test]
+fn test_query_6_column_matches_duckdb() {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        "CREATE TABLE lineitem AS SELECT
+            CASE i % 3 WHEN 0 THEN 'A' WHEN 1 THEN 'N' ELSE 'R' END AS l_returnflag,
+            CASE i % 2 WHEN 0 THEN 'F' ELSE 'O' END AS l_linestatus,
+            CAST(i % 50 AS DECIMAL(15,2)) AS l_quantity,
+            CAST((90000 + i * 7919 % 100000) / 100 AS DECIMAL(15,2)) AS l_extendedprice,
+            CAST(i % 11 / 100 AS DECIMAL(15,2)) AS l_discount,
+            CAST(i % 9 / 100 AS DECIMAL(15,2)) AS l_tax,
+            DATE '1993-04-01' + CAST(i % 1000 AS INTEGER) AS l_shipdate
+        FROM range(0, 5000) t(i)",
+    )
+    .unwrap();
+    let mut result = QueryResult::new(&conn, None).unwrap();
+    let lineitems: Vec<LineItem> = result.iter_records().unwrap().map(|x| x.unwrap()).collect();
+    let file = TempColumnFile::with_row_group_size("query_6_duckdb", &lineitems, 1000);
+
+    let revenue = query_6_column(file.path(), &QuerySixParams::default()).unwrap();
+
+    assert_eq!(revenue.to_string(), query_6_duckdb(&conn).unwrap());
+}
@@ -0,0 +1,369 @@
+use std::io::{Read, Seek, Write};
+
+use crate::error::{Error, Result};
+use crate::io;
+
+// Chunk encodings
+const DICTIONARY: u8 = 0;
+const PLAIN: u8 = 1;
+
+// Ways of storing dictionary codes
+const CODE_RUNS: u8 = 0;
+const CODE_PACKED: u8 = 1;
+
+/// A column of arbitrary UTF-8 strings.
+///
+/// Chunks with few distinct values store a dictionary plus one code per
+/// row; the codes are run-length encoded or bit-packed, whichever is
+/// smaller. Chunks where more than half of the values are distinct store
+/// the strings as plain offsets and bytes.
+///
+/// On disk a chunk is the encoding (u8) and the body length (u64), followed
+/// by the body. A string list is `n + 1` u32 offsets and then the bytes they
+/// index. A dictionary body is the entry count (u32), the entries as a string
+/// list, the code bit width (u8) and the code layout (u8), then either the
+/// run count (u32), the run lengths (u32 each) and the bit-packed run codes,
+/// or one bit-packed code per row. A plain body is the values as a string
+/// list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VarcharColumn {
+    Dictionary {
+        dictionary: Vec<String>,
+        codes: Vec<u32>,
+    },
+    Plain(Vec<String>),
+}
+
+impl VarcharColumn {
+    /// Picks the dictionary encoding when it pays off.
+    pub fn from_strings(values: &[String]) -> Self {
+        let mut dictionary: Vec<String> = Vec::new();
+        let mut lookup = ahash::AHashMap::new();
+        let mut codes = Vec::with_capacity(values.len());
+        for value in values {
+            let code = *lookup.entry(value.as_str()).or_insert_with(|| {
+                dictionary.push(value.clone());
+                dictionary.len() as u32 - 1
+            });
+            codes.push(code);
+        }
+        if dictionary.len() * 2 > values.len() {
+            VarcharColumn::Plain(values.to_vec())
+        } else {
+            VarcharColumn::Dictionary { dictionary, codes }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            VarcharColumn::Dictionary { codes, .. } => codes.len(),
+            VarcharColumn::Plain(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> &str {
+        match self {
+            VarcharColumn::Dictionary { dictionary, codes } => &dictionary[codes[index] as usize],
+            VarcharColumn::Plain(values) => &values[index],
+        }
+    }
+
+    pub fn to_strings(&self) -> Vec<String> {
+        (0..self.len()).map(|i| self.get(i).to_string()).collect()
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut body = Vec::new();
+        let encoding = match self {
+            VarcharColumn::Dictionary { dictionary, codes } => {
+                io::write_u32(&mut body, dictionary.len() as u32)?;
+                write_string_list(&mut body, dictionary)?;
+                write_codes(&mut body, codes, dictionary.len())?;
+                DICTIONARY
+            }
+            VarcharColumn::Plain(values) => {
+                write_string_list(&mut body, values)?;
+                PLAIN
+            }
+        };
+        io::write_u8(writer, encoding)?;
+        io::write_u64(writer, body.len() as u64)?;
+        writer.write_all(&body)?;
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R, row_count: usize) -> Result<Self> {
+        let encoding = io::read_u8(reader)?;
+        let body_length = io::read_u64(reader)?;
+        let mut body = Vec::new();
+        reader.take(body_length).read_to_end(&mut body)?;
+        if body.len() as u64 != body_length {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let mut body = body.as_slice();
+        let column = match encoding {
+            DICTIONARY => {
+                let dictionary_length = io::read_u32(&mut body)? as usize;
+                let dictionary = read_string_list(&mut body, dictionary_length)?;
+                let codes = read_codes(&mut body, row_count)?;
+                if codes.iter().any(|x| *x as usize >= dictionary.len()) {
+                    return Err(Error::corrupt("dictionary code out of range"));
+                }
+                VarcharColumn::Dictionary { dictionary, codes }
+            }
+            PLAIN => VarcharColumn::Plain(read_string_list(&mut body, row_count)?),
+            _ => {
+                return Err(Error::corrupt(format!(
+                    "unknown string encoding {}",
+                    encoding
+                )))
+            }
+        };
+        if !body.is_empty() {
+            return Err(Error::corrupt("string column chunk has trailing bytes"));
+        }
+        Ok(column)
+    }
+
+    /// Moves the reader past a chunk without decoding it.
+    pub fn skip<R: Read + Seek>(reader: &mut std::io::BufReader<R>) -> Result<()> {
+        io::read_u8(reader)?;
+        let body_length = io::read_u64(reader)?;
+        reader.seek_relative(body_length as i64)?;
+        Ok(())
+    }
+}
+
+fn write_string_list(writer: &mut Vec<u8>, values: &[String]) -> Result<()> {
+    let mut offset = 0u32;
+    io::write_u32(writer, offset)?;
+    for value in values {
+        offset = u32::try_from(value.len())
+            .ok()
+            .and_then(|x| offset.checked_add(x))
+            .expect("String column chunk holds more than 4 GiB of strings");
+        io::write_u32(writer, offset)?;
+    }
+    for value in values {
+        writer.extend_from_slice(value.as_bytes());
+    }
+    Ok(())
+}
+
+fn read_string_list(body: &mut &[u8], count: usize) -> Result<Vec<String>> {
+    let offsets = (0..=count)
+        .map(|_| io::read_u32(body).map(|x| x as usize))
+        .collect::<Result<Vec<_>>>()?;
+    let length = offsets[count];
+    if offsets[0] != 0 || offsets.windows(2).any(|x| x[0] > x[1]) || length > body.len() {
+        return Err(Error::corrupt("invalid string offsets"));
+    }
+    let (bytes, rest) = body.split_at(length);
+    *body = rest;
+    offsets
+        .windows(2)
+        .map(|x| {
+            String::from_utf8(bytes[x[0]..x[1]].to_vec())
+                .map_err(|_| Error::corrupt("string column holds invalid UTF-8"))
+        })
+        .collect()
+}
+
+fn bit_width(dictionary_length: usize) -> u8 {
+    (usize::BITS - dictionary_length.saturating_sub(1).leading_zeros()) as u8
+}
+
+fn packed_size(count: usize, bit_width: u8) -> usize {
+    (count * bit_width as usize).div_ceil(8)
+}
+
+fn write_codes(writer: &mut Vec<u8>, codes: &[u32], dictionary_length: usize) -> Result<()> {
+    let bit_width = bit_width(dictionary_length);
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for &code in codes {
+        match runs.last_mut() {
+            Some((last, length)) if *last == code => *length += 1,
+            _ => runs.push((code, 1)),
+        }
+    }
+    io::write_u8(writer, bit_width)?;
+    if runs.len() * 4 + packed_size(runs.len(), bit_width) < packed_size(codes.len(), bit_width) {
+        io::write_u8(writer, CODE_RUNS)?;
+        io::write_u32(writer, runs.len() as u32)?;
+        for (_, length) in &runs {
+            io::write_u32(writer, *length)?;
+        }
+        let run_codes: Vec<u32> = runs.iter().map(|(code, _)| *code).collect();
+        writer.extend_from_slice(&bit_pack(&run_codes, bit_width));
+    } else {
+        io::write_u8(writer, CODE_PACKED)?;
+        writer.extend_from_slice(&bit_pack(codes, bit_width));
+    }
+    Ok(())
+}
+
+fn read_codes(body: &mut &[u8], row_count: usize) -> Result<Vec<u32>> {
+    let bit_width = io::read_u8(body)?;
+    if bit_width > 32 {
+        return Err(Error::corrupt(format!(
+            "invalid code bit width {}",
+            bit_width
+        )));
+    }
+    match io::read_u8(body)? {
+        CODE_RUNS => {
+            let run_count = io::read_u32(body)? as usize;
+            if run_count > row_count {
+                return Err(Error::corrupt("more code runs than rows"));
+            }
+            let lengths = (0..run_count)
+                .map(|_| io::read_u32(body))
+                .collect::<Result<Vec<_>>>()?;
+            if lengths.iter().map(|x| *x as u64).sum::<u64>() != row_count as u64 {
+                return Err(Error::corrupt("code runs do not cover the row group"));
+            }
+            let run_codes = bit_unpack(body, bit_width, run_count)?;
+            Ok(run_codes
+                .into_iter()
+                .zip(lengths)
+                .flat_map(|(code, length)| std::iter::repeat_n(code, length as usize))
+                .collect())
+        }
+        CODE_PACKED => bit_unpack(body, bit_width, row_count),
+        layout => Err(Error::corrupt(format!("unknown code layout {}", layout))),
+    }
+}
+
+/// Packs the low `bit_width` bits of each value, least significant first.
+fn bit_pack(values: &[u32], bit_width: u8) -> Vec<u8> {
+    let mut bytes = vec![0u8; packed_size(values.len(), bit_width)];
+    for (index, &value) in values.iter().enumerate() {
+        for bit in 0..bit_width as usize {
+            if value >> bit & 1 == 1 {
+                let position = index * bit_width as usize + bit;
+                bytes[position / 8] |= 1 << (position % 8);
+            }
+        }
+    }
+    bytes
+}
+
+fn bit_unpack(body: &mut &[u8], bit_width: u8, count: usize) -> Result<Vec<u32>> {
+    let size = packed_size(count, bit_width);
+    if size > body.len() {
+        return Err(Error::corrupt("bit-packed codes are truncated"));
+    }
+    let (bytes, rest) = body.split_at(size);
+    *body = rest;
+    Ok((0..count)
+        .map(|index| {
+            (0..bit_width as usize).fold(0u32, |value, bit| {
+                let position = index * bit_width as usize + bit;
+                value | ((bytes[position / 8] >> (position % 8) & 1) as u32) << bit
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor};
+
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|x| x.to_string()).collect()
+    }
+
+    fn round_trip(column: &VarcharColumn) -> VarcharColumn {
+        let mut buffer = Vec::new();
+        column.write(&mut buffer).unwrap();
+        VarcharColumn::read(&mut buffer.as_slice(), column.len()).unwrap()
+    }
+
+    #[test]
+    fn test_low_cardinality_uses_dictionary() {
+        let values = strings(&["TRUCK", "MAIL", "TRUCK", "REG AIR", "TRUCK", "MAIL"]);
+
+        let column = VarcharColumn::from_strings(&values);
+
+        assert!(
+            matches!(&column, VarcharColumn::Dictionary { dictionary, .. } if dictionary.len() == 3)
+        );
+        assert_eq!(round_trip(&column).to_strings(), values);
+    }
+
+    #[test]
+    fn test_high_cardinality_falls_back_to_plain() {
+        let values = strings(&["carefully final", "", "ironic ßpecial ✓", "deposits"]);
+
+        let column = VarcharColumn::from_strings(&values);
+
+        assert!(matches!(column, VarcharColumn::Plain(_)));
+        assert_eq!(round_trip(&column).to_strings(), values);
+    }
+
+    #[test]
+    fn test_sorted_codes_are_run_length_encoded() {
+        let mut values = strings(&["DELIVER IN PERSON"; 1000]);
+        values.extend(strings(&["NONE"; 1000]));
+        let column = VarcharColumn::from_strings(&values);
+        let mut buffer = Vec::new();
+        column.write(&mut buffer).unwrap();
+
+        // Two runs instead of 2000 bit-packed codes
+        assert!(buffer.len() < 100);
+        assert_eq!(round_trip(&column), column);
+    }
+
+    #[test]
+    fn test_bit_pack_round_trip() {
+        let values: Vec<u32> = (0..100).map(|x| x * 7 % 13).collect();
+
+        let packed = bit_pack(&values, 4);
+
+        assert_eq!(packed.len(), 50);
+        assert_eq!(bit_unpack(&mut packed.as_slice(), 4, 100).unwrap(), values);
+        assert_eq!(bit_width(1), 0);
+        assert_eq!(bit_width(2), 1);
+        assert_eq!(bit_width(7), 3);
+        assert_eq!(bit_width(8), 3);
+        assert_eq!(bit_width(9), 4);
+    }
+
+    #[test]
+    fn test_skip() {
+        let column = VarcharColumn::from_strings(&strings(&["a", "b", "a", "a"]));
+        let mut buffer = Vec::new();
+        column.write(&mut buffer).unwrap();
+        buffer.push(42);
+
+        let mut reader = BufReader::new(Cursor::new(buffer));
+        VarcharColumn::skip(&mut reader).unwrap();
+
+        assert_eq!(io::read_u8(&mut reader).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_read_rejects_out_of_range_codes() {
+        let mut buffer = Vec::new();
+        VarcharColumn::Dictionary {
+            dictionary: strings(&["a", "b", "c"]),
+            codes: vec![0, 2, 1, 2],
+        }
+        .write(&mut buffer)
+        .unwrap();
+        // The last byte holds the packed codes; setting every bit makes code 3
+        *buffer.last_mut().unwrap() = 0xFF;
+
+        assert!(matches!(
+            VarcharColumn::read(&mut buffer.as_slice(), 4),
+            Err(Error::Corrupt(_))
+        ));
+    }
+}